@@ -0,0 +1,13 @@
+-- Exit rows never changed the class of a seat, so the tag is no longer accepted
+DELETE FROM cabin_seat_tags
+WHERE tag = 'ExitRow';
+
+ALTER TABLE cabin_seat_tags
+    DROP CONSTRAINT IF EXISTS cabin_seat_tags_tag_check;
+
+ALTER TABLE cabin_seat_tags
+    ADD CONSTRAINT cabin_seat_tags_tag_check CHECK (tag IN ('Blocked', 'ExtraLegroom'));
+
+INSERT INTO schema_migrations (version)
+VALUES (10)
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,37 @@
+-- Multipliers are in percent of the base fare. For each kind the first
+-- matching rule (by rule_id) applies, and multipliers of different kinds compound.
+CREATE TABLE IF NOT EXISTS pricing_rules
+(
+    rule_id         SERIAL                  PRIMARY KEY,
+    kind            VARCHAR(20)             NOT NULL CHECK (kind IN ('LoadFactor', 'DaysToDeparture', 'Weekday')),
+    min_value       INT                     NOT NULL,
+    max_value       INT                     NOT NULL,
+    multiplier      INT                     NOT NULL CHECK (multiplier > 0),
+
+    CHECK (min_value <= max_value)
+);
+
+-- Load factor in percent of occupied seats of the fare class. Default rules
+-- are only seeded once, rules replaced by admins are kept.
+INSERT INTO pricing_rules (kind, min_value, max_value, multiplier)
+SELECT *
+FROM (VALUES ('LoadFactor', 0, 49, 100),
+             ('LoadFactor', 50, 79, 110),
+             ('LoadFactor', 80, 100, 125),
+             ('DaysToDeparture', 0, 6, 130),
+             ('DaysToDeparture', 7, 20, 110),
+             ('Weekday', 5, 5, 110),
+             ('Weekday', 7, 7, 110)) AS defaults (kind, min_value, max_value, multiplier)
+WHERE NOT EXISTS (SELECT 1 FROM pricing_rules);
+
+CREATE TABLE IF NOT EXISTS ticket_flight_pricing
+(
+    ticket_no       CHAR(13)                NOT NULL,
+    flight_id       INT                     NOT NULL,
+    base_amount     INT                     NOT NULL,
+    amount          INT                     NOT NULL,
+    applied_rules   JSONB                   NOT NULL,
+
+    PRIMARY KEY (ticket_no, flight_id),
+    FOREIGN KEY (ticket_no, flight_id) REFERENCES ticket_flights (ticket_no, flight_id)
+);
@@ -0,0 +1,50 @@
+DROP VIEW IF EXISTS seats_layout_v;
+
+-- Row ranges of an aircraft cabin assigned to a fare class. Ranges of one
+-- aircraft must not overlap, this is checked by the admin API.
+CREATE TABLE IF NOT EXISTS cabin_layouts
+(
+    aircraft_code   CHAR(3)                 NOT NULL,
+    first_row       INT                     NOT NULL,
+    last_row        INT                     NOT NULL,
+    fare_conditions VARCHAR(10)             NOT NULL CHECK (fare_conditions IN ('Economy', 'Comfort', 'Business')),
+
+    PRIMARY KEY (aircraft_code, first_row),
+    FOREIGN KEY (aircraft_code) REFERENCES aircrafts_data (aircraft_code),
+    CHECK (first_row <= last_row)
+);
+
+CREATE TABLE IF NOT EXISTS cabin_seat_tags
+(
+    aircraft_code   CHAR(3)                 NOT NULL,
+    seat_no         VARCHAR(4)              NOT NULL,
+    tag             VARCHAR(20)             NOT NULL CHECK (tag IN ('Blocked', 'ExitRow', 'ExtraLegroom')),
+
+    PRIMARY KEY (aircraft_code, seat_no, tag),
+    FOREIGN KEY (aircraft_code, seat_no) REFERENCES seats (aircraft_code, seat_no)
+);
+
+-- Seat classes resulting from cabin layouts: the class of the row range (or the
+-- original class when no range covers the row), extra legroom Economy seats
+-- are sold as Comfort, blocked seats are not sold at all.
+CREATE VIEW seats_layout_v AS
+SELECT
+    seats.aircraft_code,
+    seats.seat_no,
+    (CASE
+        WHEN COALESCE(layout.fare_conditions, seats.fare_conditions) = 'Economy'
+            AND 'ExtraLegroom' = ANY(COALESCE(seat_tags.tags, '{}'))
+            THEN 'Comfort'
+        ELSE COALESCE(layout.fare_conditions, seats.fare_conditions)
+    END)::VARCHAR(10) AS fare_conditions,
+    COALESCE(seat_tags.tags, '{}'::VARCHAR[]) AS tags,
+    'Blocked' = ANY(COALESCE(seat_tags.tags, '{}')) AS blocked
+FROM seats
+         LEFT JOIN cabin_layouts layout ON layout.aircraft_code = seats.aircraft_code
+    AND substring(seats.seat_no FROM '^[0-9]+')::INT BETWEEN layout.first_row AND layout.last_row
+         LEFT JOIN (
+    SELECT aircraft_code, seat_no, array_agg(tag ORDER BY tag)::VARCHAR[] AS tags
+    FROM cabin_seat_tags
+    GROUP BY aircraft_code, seat_no
+) AS seat_tags ON seat_tags.aircraft_code = seats.aircraft_code AND seat_tags.seat_no = seats.seat_no
+WHERE EXISTS(SELECT 1 FROM cabin_layouts WHERE cabin_layouts.aircraft_code = seats.aircraft_code);
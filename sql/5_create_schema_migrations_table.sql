@@ -0,0 +1,11 @@
+-- Scripts are applied in order, so recording this one marks all previous as applied.
+-- Every following script must add its own version.
+CREATE TABLE IF NOT EXISTS schema_migrations
+(
+    version         INT                     PRIMARY KEY,
+    applied_at      TIMESTAMPTZ             NOT NULL DEFAULT now()
+);
+
+INSERT INTO schema_migrations (version)
+VALUES (1), (2), (3), (4), (5)
+ON CONFLICT DO NOTHING;
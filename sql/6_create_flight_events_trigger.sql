@@ -0,0 +1,40 @@
+-- Gate assigned to a departing flight, shown on boards and in flight events
+ALTER TABLE flights ADD COLUMN IF NOT EXISTS gate VARCHAR(10);
+
+-- Publishes a JSON event on the flight_events channel whenever the status,
+-- actual times or gate of a flight change, whoever writes them: the API,
+-- another service or a manual UPDATE. Listeners receive it on commit.
+CREATE OR REPLACE FUNCTION notify_flight_event() RETURNS TRIGGER AS
+$$
+BEGIN
+    PERFORM pg_notify('flight_events', json_build_object(
+        'flight_id', NEW.flight_id,
+        'flight_no', NEW.flight_no,
+        'departure_airport', NEW.departure_airport,
+        'arrival_airport', NEW.arrival_airport,
+        'status', NEW.status,
+        'previous_status', OLD.status,
+        'actual_departure', NEW.actual_departure,
+        'actual_arrival', NEW.actual_arrival,
+        'gate', NEW.gate
+    )::TEXT);
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS flights_notify_event ON flights;
+
+CREATE TRIGGER flights_notify_event
+    AFTER UPDATE OF status, actual_departure, actual_arrival, gate
+    ON flights
+    FOR EACH ROW
+    WHEN (OLD.status IS DISTINCT FROM NEW.status
+        OR OLD.actual_departure IS DISTINCT FROM NEW.actual_departure
+        OR OLD.actual_arrival IS DISTINCT FROM NEW.actual_arrival
+        OR OLD.gate IS DISTINCT FROM NEW.gate)
+EXECUTE FUNCTION notify_flight_event();
+
+INSERT INTO schema_migrations (version)
+VALUES (6)
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,63 @@
+-- Times announced when a flight is delayed
+ALTER TABLE flights
+    ADD COLUMN IF NOT EXISTS estimated_departure TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS estimated_arrival TIMESTAMPTZ;
+
+-- One row per status change made through the operations API, with the
+-- state written and who wrote it
+CREATE TABLE IF NOT EXISTS flight_status_audit
+(
+    audit_id            BIGSERIAL PRIMARY KEY,
+    flight_id           INTEGER     NOT NULL REFERENCES flights (flight_id),
+    changed_at          TIMESTAMPTZ NOT NULL DEFAULT now(),
+    changed_by          TEXT        NOT NULL,
+    previous_status     VARCHAR(20) NOT NULL,
+    status              VARCHAR(20) NOT NULL,
+    estimated_departure TIMESTAMPTZ,
+    estimated_arrival   TIMESTAMPTZ,
+    actual_departure    TIMESTAMPTZ,
+    actual_arrival      TIMESTAMPTZ,
+    reason              TEXT
+);
+
+CREATE INDEX IF NOT EXISTS flight_status_audit_flight_id_idx ON flight_status_audit (flight_id, audit_id);
+
+-- Flight events also carry the estimated times
+CREATE OR REPLACE FUNCTION notify_flight_event() RETURNS TRIGGER AS
+$$
+BEGIN
+    PERFORM pg_notify('flight_events', json_build_object(
+        'flight_id', NEW.flight_id,
+        'flight_no', NEW.flight_no,
+        'departure_airport', NEW.departure_airport,
+        'arrival_airport', NEW.arrival_airport,
+        'status', NEW.status,
+        'previous_status', OLD.status,
+        'estimated_departure', NEW.estimated_departure,
+        'estimated_arrival', NEW.estimated_arrival,
+        'actual_departure', NEW.actual_departure,
+        'actual_arrival', NEW.actual_arrival,
+        'gate', NEW.gate
+    )::TEXT);
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS flights_notify_event ON flights;
+
+CREATE TRIGGER flights_notify_event
+    AFTER UPDATE OF status, estimated_departure, estimated_arrival, actual_departure, actual_arrival, gate
+    ON flights
+    FOR EACH ROW
+    WHEN (OLD.status IS DISTINCT FROM NEW.status
+        OR OLD.estimated_departure IS DISTINCT FROM NEW.estimated_departure
+        OR OLD.estimated_arrival IS DISTINCT FROM NEW.estimated_arrival
+        OR OLD.actual_departure IS DISTINCT FROM NEW.actual_departure
+        OR OLD.actual_arrival IS DISTINCT FROM NEW.actual_arrival
+        OR OLD.gate IS DISTINCT FROM NEW.gate)
+EXECUTE FUNCTION notify_flight_event();
+
+INSERT INTO schema_migrations (version)
+VALUES (7)
+ON CONFLICT DO NOTHING;
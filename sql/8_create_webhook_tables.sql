@@ -0,0 +1,58 @@
+-- Receivers of webhook events. An empty list of event types subscribes to all of them.
+CREATE TABLE IF NOT EXISTS webhook_endpoints
+(
+    endpoint_id SERIAL PRIMARY KEY,
+    url         TEXT        NOT NULL,
+    secret      TEXT        NOT NULL,
+    event_types TEXT[]      NOT NULL DEFAULT '{}',
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Events written in the same transaction as the change they describe
+CREATE TABLE IF NOT EXISTS webhook_outbox
+(
+    event_id   BIGSERIAL PRIMARY KEY,
+    event_type TEXT        NOT NULL,
+    payload    JSONB       NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- One delivery of an event to each endpoint subscribed to it when it was written.
+-- Dead deliveries ran out of attempts and wait for a manual retry.
+CREATE TABLE IF NOT EXISTS webhook_deliveries
+(
+    delivery_id     BIGSERIAL PRIMARY KEY,
+    event_id        BIGINT      NOT NULL REFERENCES webhook_outbox (event_id),
+    endpoint_id     INTEGER     NOT NULL REFERENCES webhook_endpoints (endpoint_id) ON DELETE CASCADE,
+    status          VARCHAR(10) NOT NULL DEFAULT 'Pending' CHECK (status IN ('Pending', 'Delivered', 'Dead')),
+    attempts        INTEGER     NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_error      TEXT,
+    delivered_at    TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_due_idx ON webhook_deliveries (next_attempt_at) WHERE status = 'Pending';
+
+CREATE OR REPLACE FUNCTION fan_out_webhook_event() RETURNS TRIGGER AS
+$$
+BEGIN
+    INSERT INTO webhook_deliveries (event_id, endpoint_id)
+    SELECT NEW.event_id, endpoint_id
+    FROM webhook_endpoints
+    WHERE event_types = '{}' OR NEW.event_type = ANY (event_types);
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS webhook_outbox_fan_out ON webhook_outbox;
+
+CREATE TRIGGER webhook_outbox_fan_out
+    AFTER INSERT
+    ON webhook_outbox
+    FOR EACH ROW
+EXECUTE FUNCTION fan_out_webhook_event();
+
+INSERT INTO schema_migrations (version)
+VALUES (8)
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,8 @@
+-- Fares created, updated or imported by admins. Price rebuilds keep them and
+-- only derive the fares that are missing.
+ALTER TABLE prices
+    ADD COLUMN IF NOT EXISTS managed BOOLEAN NOT NULL DEFAULT false;
+
+INSERT INTO schema_migrations (version)
+VALUES (9)
+ON CONFLICT DO NOTHING;
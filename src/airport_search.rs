@@ -0,0 +1,210 @@
+use actix_web::{HttpResponse, Responder, web};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::app_state::AppState;
+use crate::lang::{Lang, LangParam};
+use crate::repository::{AirportDetails, Coordinates, Names, RepositoryResult};
+use crate::validation::{validate, Validate, ValidationErrors};
+
+pub const DEFAULT_SUGGESTIONS: u8 = 10;
+pub const MAX_SUGGESTIONS: u8 = 50;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AirportSearchParameters {
+    /// Beginning of an airport code, airport name or city name in any language, typos are tolerated
+    q: String,
+    /// Maximum number of suggestions, 10 by default
+    limit: Option<u8>,
+}
+
+impl Validate for AirportSearchParameters {
+    async fn validate(&self, _: &AppState, errors: &mut ValidationErrors) -> RepositoryResult<()> {
+        let length = self.q.trim().chars().count();
+
+        if length == 0 || length > 100 {
+            errors.add("q", "Must be between 1 and 100 characters");
+        }
+
+        if let Some(limit) = self.limit {
+            if limit == 0 || limit > MAX_SUGGESTIONS {
+                errors.add("limit", format!("Must be between 1 and {}", MAX_SUGGESTIONS));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AirportSuggestion {
+    code: String,
+    name: Option<String>,
+    city: Option<String>,
+    coordinates: Coordinates,
+    timezone: String,
+}
+
+/// Field that matched, in ranking order
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum MatchedField {
+    Code,
+    City,
+    Name,
+    CodePrefix,
+}
+
+/// Lowercase words, with punctuation treated as spaces
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Edit distance counting a swap of adjacent characters as one typo
+fn typos(a: &[char], b: &[char]) -> usize {
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+/// Typos allowed in a query, none in short ones as they match too much
+fn allowed_typos(query_length: usize) -> usize {
+    match query_length {
+        0..=3 => { 0 }
+        4..=7 => { 1 }
+        _ => { 2 }
+    }
+}
+
+/// Typos needed for the query to be the beginning of the text or of one of its
+/// words, if within the allowed number
+fn match_text(query: &str, text: &str) -> Option<usize> {
+    let text = normalize(text);
+
+    let starts: Vec<&str> = std::iter::once(text.as_str())
+        .chain(text.char_indices().filter(|(_, c)| *c == ' ').map(|(i, _)| &text[i + 1..]))
+        .collect();
+
+    if starts.iter().any(|s| s.starts_with(query)) {
+        return Some(0);
+    }
+
+    let query: Vec<char> = query.chars().collect();
+    let allowed = allowed_typos(query.len());
+
+    if allowed == 0 {
+        return None;
+    }
+
+    starts
+        .iter()
+        .flat_map(|s| {
+            let s: Vec<char> = s.chars().collect();
+
+            // Prefixes one character shorter or longer catch a missed or doubled letter
+            (query.len().saturating_sub(1)..=query.len() + 1)
+                .filter(|n| *n <= s.len())
+                .map(|n| typos(&query, &s[..n]))
+                .collect::<Vec<usize>>()
+        })
+        .min()
+        .filter(|x| *x <= allowed)
+}
+
+fn match_names(query: &str, names: &Names) -> Option<usize> {
+    [&names.en, &names.ru]
+        .into_iter()
+        .flatten()
+        .filter_map(|name| match_text(query, name))
+        .min()
+}
+
+/// Best match of the airport as (typos, field), exact matches of any field
+/// rank above all matches with typos
+fn match_airport(query: &str, airport: &AirportDetails) -> Option<(usize, MatchedField)> {
+    let code = airport.code.to_lowercase();
+
+    if code == query {
+        return Some((0, MatchedField::Code));
+    }
+
+    [
+        match_names(query, &airport.city).map(|x| (x, MatchedField::City)),
+        match_names(query, &airport.name).map(|x| (x, MatchedField::Name)),
+        code.starts_with(query).then_some((0, MatchedField::CodePrefix)),
+    ]
+        .into_iter()
+        .flatten()
+        .min()
+}
+
+fn rank_airports(query: &str, airports: Vec<AirportDetails>, lang: Lang, limit: usize) -> Vec<AirportSuggestion> {
+    let query = normalize(query);
+
+    let mut matches: Vec<((usize, MatchedField), AirportDetails)> = airports
+        .into_iter()
+        .filter_map(|airport| Some((match_airport(&query, &airport)?, airport)))
+        .collect();
+
+    matches.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.code.cmp(&b.1.code)));
+
+    matches
+        .into_iter()
+        .take(limit)
+        .map(|(_, airport)| AirportSuggestion {
+            name: airport.name.get(lang),
+            city: airport.city.get(lang),
+            code: airport.code,
+            coordinates: airport.coordinates,
+            timezone: airport.timezone,
+        })
+        .collect()
+}
+
+#[utoipa::path(
+    get, path = "/api/airports/search", tag = "locations",
+    params(AirportSearchParameters, LangParam, ("Accept-Language" = Option<String>, Header)),
+    responses(
+        (status = 200, description = "Matching airports, exact code matches first, then city and airport name matches", body = Vec<AirportSuggestion>),
+        (status = 400, description = "Invalid fields", body = ValidationErrors)
+    )
+)]
+pub async fn search_airports(parameters: web::Query<AirportSearchParameters>, lang: Lang, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = validate(&*parameters, &state).await {
+        return response;
+    }
+
+    let limit = parameters.limit.unwrap_or(DEFAULT_SUGGESTIONS) as usize;
+
+    match state.repo.airport_details().await {
+        Ok(airports) => {
+            HttpResponse::Ok().json(rank_airports(&parameters.q, airports, lang, limit))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
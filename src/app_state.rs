@@ -1,7 +1,16 @@
+use std::sync::Arc;
 use sqlx::PgPool;
 use crate::config::Config;
+use crate::flight_events::FlightEvents;
+use crate::metrics::Metrics;
+use crate::rebuild::RebuildJobs;
+use crate::repository::Repository;
 
 pub struct AppState {
     pub db_pool: PgPool,
+    pub repo: Arc<dyn Repository>,
     pub cfg: Config,
-}
\ No newline at end of file
+    pub rebuild_jobs: RebuildJobs,
+    pub metrics: Metrics,
+    pub flight_events: FlightEvents,
+}
@@ -0,0 +1,70 @@
+use std::future::{ready, Ready};
+use actix_web::dev::ServiceRequest;
+use actix_web::error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized};
+use actix_web::{HttpMessage, web};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use actix_web_httpauth::middleware::HttpAuthentication;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use crate::app_state::AppState;
+
+/// Roles are ordered by privilege, so a route requiring `Agent` also admits `Admin`.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Role {
+    Passenger,
+    Agent,
+    /// Operations control, changing the status of flights
+    Operations,
+    Admin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Claims {
+    pub sub: String,
+    pub role: Role,
+    pub exp: usize,
+}
+
+/// Audience of API tokens. Tokens without an audience are accepted too, tokens
+/// for another audience, like quote tokens signed with the same secret, are not.
+pub const API_AUDIENCE: &str = "api";
+
+pub fn decode_token(token: &str, secret: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_audience(&[API_AUDIENCE]);
+
+    jsonwebtoken::decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &validation
+    )
+        .map(|data| data.claims)
+}
+
+type ValidationResult = Result<ServiceRequest, (actix_web::Error, ServiceRequest)>;
+
+fn validate(req: ServiceRequest, credentials: BearerAuth, role: Role) -> ValidationResult {
+    let secret = match req.app_data::<web::Data<AppState>>() {
+        Some(state) => { state.cfg.auth.jwt_secret.clone() }
+        None => { return Err((ErrorInternalServerError("Application state is not configured"), req)); }
+    };
+
+    let claims = match decode_token(credentials.token(), &secret) {
+        Ok(c) => { c }
+        Err(e) => { return Err((ErrorUnauthorized(e.to_string()), req)); }
+    };
+
+    if claims.role < role {
+        return Err((ErrorForbidden(format!("{:?} role is required", role)), req));
+    }
+
+    req.extensions_mut().insert(claims);
+
+    Ok(req)
+}
+
+/// Middleware admitting only requests with a valid HS256 bearer token
+/// whose role is at least `role`. Decoded `Claims` are put into request extensions.
+pub fn require_role(role: Role) -> HttpAuthentication<BearerAuth, impl Fn(ServiceRequest, BearerAuth) -> Ready<ValidationResult>> {
+    HttpAuthentication::bearer(move |req, credentials| ready(validate(req, credentials, role)))
+}
@@ -0,0 +1,694 @@
+//! Fills the bookings schema (see `test/fixtures/bookings_schema.sql`) with a
+//! synthetic airline network for demos and load tests. The same seed and options
+//! always produce the same rows. Derived tables are left alone: apply the `sql/`
+//! scripts or run the admin rebuilds afterwards.
+
+use std::collections::BTreeSet;
+use std::process::exit;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use clap::Parser;
+use dotenv::dotenv;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use sqlx::{Connection, PgConnection, Postgres, Transaction};
+
+#[derive(Parser, Debug)]
+#[command(about = "Generates a synthetic airline network into the bookings schema")]
+struct Args {
+    #[arg(long, env = "DATABASE_URL")]
+    database_url: String,
+
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+
+    #[arg(long, default_value_t = 30)]
+    airports: usize,
+    /// Airport pairs, each flown in both directions
+    #[arg(long, default_value_t = 60)]
+    routes: usize,
+
+    /// First day of the schedule
+    #[arg(long, default_value = "2017-07-16")]
+    from: NaiveDate,
+    /// Last day of the schedule
+    #[arg(long, default_value = "2017-09-14")]
+    to: NaiveDate,
+
+    /// Share of seats sold by departure
+    #[arg(long, default_value_t = 0.7)]
+    load_factor: f64,
+
+    /// Delete all existing reference data, flights and bookings first
+    #[arg(long)]
+    truncate: bool,
+}
+
+impl Args {
+    fn check(&self) -> Vec<String> {
+        let mut errors = vec![];
+
+        if self.airports < 2 || self.airports > 1000 {
+            errors.push("--airports must be between 2 and 1000".to_string());
+        }
+
+        if self.routes == 0 || self.routes > self.airports * (self.airports - 1) / 2 {
+            errors.push("--routes must be positive and not exceed the number of airport pairs".to_string());
+        }
+
+        if self.from > self.to {
+            errors.push("--from must not be after --to".to_string());
+        }
+
+        if !(0.0..=1.0).contains(&self.load_factor) {
+            errors.push("--load-factor must be between 0 and 1".to_string());
+        }
+
+        errors
+    }
+}
+
+struct Cabin {
+    fare_conditions: &'static str,
+    rows: u32,
+    letters: &'static str,
+}
+
+struct AircraftModel {
+    code: &'static str,
+    model: &'static str,
+    range: i32,
+    cabins: &'static [Cabin],
+}
+
+impl AircraftModel {
+    /// Seat numbers with their class, rows numbered from the front across cabins
+    fn seats(&self) -> Vec<(String, &'static str)> {
+        let mut seats = vec![];
+        let mut row = 0;
+
+        for cabin in self.cabins {
+            for _ in 0..cabin.rows {
+                row += 1;
+                seats.extend(cabin.letters.chars().map(|letter| (format!("{}{}", row, letter), cabin.fare_conditions)));
+            }
+        }
+
+        seats
+    }
+}
+
+const FLEET: [AircraftModel; 7] = [
+    AircraftModel {
+        code: "773", model: "Boeing 777-300", range: 11100,
+        cabins: &[
+            Cabin { fare_conditions: "Business", rows: 5, letters: "ACDGHK" },
+            Cabin { fare_conditions: "Comfort", rows: 3, letters: "ACDEFGHK" },
+            Cabin { fare_conditions: "Economy", rows: 40, letters: "ABCDEFGHK" },
+        ],
+    },
+    AircraftModel {
+        code: "763", model: "Boeing 767-300", range: 7900,
+        cabins: &[
+            Cabin { fare_conditions: "Business", rows: 6, letters: "ACDGHK" },
+            Cabin { fare_conditions: "Economy", rows: 30, letters: "ABCDEFGK" },
+        ],
+    },
+    AircraftModel {
+        code: "321", model: "Airbus A321-200", range: 5600,
+        cabins: &[
+            Cabin { fare_conditions: "Business", rows: 7, letters: "ACDF" },
+            Cabin { fare_conditions: "Economy", rows: 25, letters: "ABCDEF" },
+        ],
+    },
+    AircraftModel {
+        code: "320", model: "Airbus A320-200", range: 5700,
+        cabins: &[
+            Cabin { fare_conditions: "Business", rows: 5, letters: "ACDF" },
+            Cabin { fare_conditions: "Economy", rows: 24, letters: "ABCDEF" },
+        ],
+    },
+    AircraftModel {
+        code: "733", model: "Boeing 737-300", range: 4200,
+        cabins: &[
+            Cabin { fare_conditions: "Business", rows: 3, letters: "ACDF" },
+            Cabin { fare_conditions: "Economy", rows: 20, letters: "ABCDEF" },
+        ],
+    },
+    AircraftModel {
+        code: "SU9", model: "Sukhoi Superjet-100", range: 3000,
+        cabins: &[
+            Cabin { fare_conditions: "Business", rows: 3, letters: "ACDF" },
+            Cabin { fare_conditions: "Economy", rows: 17, letters: "ACDF" },
+        ],
+    },
+    AircraftModel {
+        code: "CR2", model: "Bombardier CRJ-200", range: 2700,
+        cabins: &[
+            Cabin { fare_conditions: "Economy", rows: 13, letters: "ABCD" },
+        ],
+    },
+];
+
+/// Time zones by western boundary longitude
+const TIMEZONES: [(f64, &str); 10] = [
+    (0.0, "Europe/Kaliningrad"),
+    (24.0, "Europe/Moscow"),
+    (50.0, "Europe/Samara"),
+    (56.0, "Asia/Yekaterinburg"),
+    (68.0, "Asia/Omsk"),
+    (78.0, "Asia/Novosibirsk"),
+    (90.0, "Asia/Krasnoyarsk"),
+    (105.0, "Asia/Irkutsk"),
+    (118.0, "Asia/Yakutsk"),
+    (135.0, "Asia/Vladivostok"),
+];
+
+const SYLLABLES: [&str; 24] = [
+    "no", "vo", "gor", "ka", "la", "mi", "re", "ta", "zan", "bur", "ro", "len",
+    "sib", "ir", "ku", "tsk", "om", "ya", "ros", "tov", "che", "lya", "pe", "ne",
+];
+
+const GIVEN_NAMES: [&str; 12] = [
+    "IVAN", "PETR", "SERGEY", "DMITRY", "ALEKSANDR", "NIKOLAY",
+    "ANNA", "OLGA", "ELENA", "MARIA", "TATYANA", "NATALIYA",
+];
+
+const FAMILY_NAMES: [&str; 12] = [
+    "IVANOV", "PETROV", "SMIRNOV", "KUZNETSOV", "POPOV", "VOLKOV",
+    "SOKOLOV", "LEBEDEV", "KOZLOV", "NOVIKOV", "MOROZOV", "ZAYTSEV",
+];
+
+/// Days before departure when sales open
+const SALES_DAYS: i64 = 30;
+
+/// Rows are sent in batches of this size
+const BATCH_SIZE: usize = 10_000;
+
+struct Airport {
+    code: String,
+    name: String,
+    city: String,
+    longitude: f64,
+    latitude: f64,
+    timezone: &'static str,
+}
+
+struct Route {
+    flight_no: String,
+    departure_airport: String,
+    arrival_airport: String,
+    departure_time: NaiveTime,
+    duration_minutes: i32,
+    aircraft: &'static AircraftModel,
+    days_of_week: Vec<u32>,
+    fare: i32,
+}
+
+fn city_name(rng: &mut StdRng) -> String {
+    let syllables = rng.gen_range(2..=3);
+    let name: String = (0..syllables).map(|_| *SYLLABLES.choose(rng).unwrap()).collect();
+
+    let mut chars = name.chars();
+    chars.next().unwrap().to_uppercase().chain(chars).collect()
+}
+
+fn generate_airports(rng: &mut StdRng, count: usize) -> Vec<Airport> {
+    let mut codes = BTreeSet::new();
+    let mut cities = BTreeSet::new();
+    let mut airports: Vec<Airport> = vec![];
+
+    while airports.len() < count {
+        let code: String = (0..3).map(|_| rng.gen_range(b'A'..=b'Z') as char).collect();
+
+        if !codes.insert(code.clone()) {
+            continue;
+        }
+
+        // Some large cities have a second airport nearby
+        let (city, longitude, latitude) = match airports.last() {
+            Some(previous) if rng.gen_bool(0.15) && !airports.iter().any(|a| a.city == previous.city && a.code != previous.code) => {
+                (previous.city.clone(), previous.longitude + rng.gen_range(-0.5..0.5), previous.latitude + rng.gen_range(-0.5..0.5))
+            }
+            _ => {
+                let mut city = city_name(rng);
+
+                while !cities.insert(city.clone()) {
+                    city = city_name(rng);
+                }
+
+                (city, rng.gen_range(20.0..140.0), rng.gen_range(42.0..70.0))
+            }
+        };
+
+        let timezone = TIMEZONES.iter().rev().find(|(west, _)| longitude >= *west).unwrap().1;
+
+        airports.push(Airport {
+            name: format!("{} International Airport", city),
+            code,
+            city,
+            longitude,
+            latitude,
+            timezone,
+        });
+    }
+
+    airports
+}
+
+/// Great-circle distance in kilometers
+fn distance(a: &Airport, b: &Airport) -> f64 {
+    let (lat1, lat2) = (a.latitude.to_radians(), b.latitude.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (b.longitude - a.longitude).to_radians();
+
+    let h = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+
+    2.0 * 6371.0 * h.sqrt().asin()
+}
+
+fn generate_routes(rng: &mut StdRng, airports: &[Airport], pairs: usize) -> Vec<Route> {
+    let mut all_pairs: Vec<(usize, usize)> = (0..airports.len())
+        .flat_map(|i| (i + 1..airports.len()).map(move |j| (i, j)))
+        .filter(|(i, j)| airports[*i].city != airports[*j].city)
+        .collect();
+
+    all_pairs.shuffle(rng);
+
+    let mut routes = vec![];
+
+    for (i, j) in all_pairs.into_iter().take(pairs) {
+        let km = distance(&airports[i], &airports[j]);
+
+        let candidates: Vec<&'static AircraftModel> = FLEET.iter().filter(|a| a.range as f64 >= km * 1.1).collect();
+        let aircraft = *candidates.choose(rng).unwrap();
+
+        // Cruise at 750 km/h plus taxiing, rounded to 5 minutes
+        let duration_minutes = ((km / 12.5 + 25.0) / 5.0).round() as i32 * 5;
+        let fare = ((km * 4.0 + 2000.0) / 100.0).round() as i32 * 100;
+
+        let days_of_week: Vec<u32> = if rng.gen_bool(0.5) {
+            (1..=7).collect()
+        } else {
+            let mut days: Vec<u32> = (1..=7).collect();
+            days.shuffle(rng);
+            days.truncate(rng.gen_range(1..=6));
+            days.sort();
+            days
+        };
+
+        for (from, to) in [(i, j), (j, i)] {
+            routes.push(Route {
+                flight_no: format!("PG{:04}", routes.len() + 1),
+                departure_airport: airports[from].code.clone(),
+                arrival_airport: airports[to].code.clone(),
+                departure_time: NaiveTime::from_hms_opt(rng.gen_range(6..23), rng.gen_range(0..12) * 5, 0).unwrap(),
+                duration_minutes,
+                aircraft,
+                days_of_week: days_of_week.clone(),
+                fare,
+            });
+        }
+    }
+
+    routes
+}
+
+async fn insert_reference_data(tx: &mut Transaction<'_, Postgres>, airports: &[Airport]) -> Result<(), sqlx::Error> {
+    let translated = |x: &str| serde_json::json!({"en": x, "ru": x});
+
+    for airport in airports {
+        sqlx::query!(
+            "
+            INSERT INTO airports_data (airport_code, airport_name, city, coordinates, timezone)
+            VALUES ($1, $2, $3, point($4, $5), $6)
+            ",
+            airport.code, translated(&airport.name), translated(&airport.city),
+            airport.longitude, airport.latitude, airport.timezone
+        )
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    for aircraft in &FLEET {
+        sqlx::query!(
+            "INSERT INTO aircrafts_data (aircraft_code, model, range) VALUES ($1, $2, $3)",
+            aircraft.code, translated(aircraft.model), aircraft.range
+        )
+            .execute(&mut **tx)
+            .await?;
+
+        let (seat_nos, fare_conditions): (Vec<String>, Vec<String>) = aircraft.seats()
+            .into_iter()
+            .map(|(seat_no, class)| (seat_no, class.to_string()))
+            .unzip();
+
+        sqlx::query!(
+            "
+            INSERT INTO seats (aircraft_code, seat_no, fare_conditions)
+            SELECT $1, * FROM UNNEST($2::VARCHAR[], $3::VARCHAR[])
+            ",
+            aircraft.code, seat_nos.as_slice(), fare_conditions.as_slice()
+        )
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Expands the routes over the schedule days, converting local departure times
+/// with the time zone of the departure airport.
+async fn insert_flights(tx: &mut Transaction<'_, Postgres>, routes: &[Route], from: NaiveDate, to: NaiveDate) -> Result<usize, sqlx::Error> {
+    let mut flights: Vec<(NaiveDateTime, &Route)> = from
+        .iter_days()
+        .take_while(|day| *day <= to)
+        .flat_map(|day| {
+            routes
+                .iter()
+                .filter(move |r| r.days_of_week.contains(&day.weekday().number_from_monday()))
+                .map(move |r| (day.and_time(r.departure_time), r))
+        })
+        .collect();
+
+    flights.sort_by(|a, b| (a.0, &a.1.flight_no).cmp(&(b.0, &b.1.flight_no)));
+
+    for chunk in flights.chunks(BATCH_SIZE) {
+        let flight_nos: Vec<String> = chunk.iter().map(|(_, r)| r.flight_no.clone()).collect();
+        let departures: Vec<NaiveDateTime> = chunk.iter().map(|(d, _)| *d).collect();
+        let durations: Vec<i32> = chunk.iter().map(|(_, r)| r.duration_minutes).collect();
+        let departure_airports: Vec<String> = chunk.iter().map(|(_, r)| r.departure_airport.clone()).collect();
+        let arrival_airports: Vec<String> = chunk.iter().map(|(_, r)| r.arrival_airport.clone()).collect();
+        let aircraft_codes: Vec<String> = chunk.iter().map(|(_, r)| r.aircraft.code.to_string()).collect();
+
+        sqlx::query!(
+            "
+            INSERT INTO flights (flight_no, scheduled_departure, scheduled_arrival, departure_airport, arrival_airport, status, aircraft_code)
+            SELECT f.flight_no,
+                   f.departure AT TIME ZONE dep.timezone,
+                   f.departure AT TIME ZONE dep.timezone + f.duration * INTERVAL '1 minute',
+                   f.departure_airport,
+                   f.arrival_airport,
+                   'Scheduled',
+                   f.aircraft_code
+            FROM UNNEST($1::CHAR(6)[], $2::TIMESTAMP[], $3::INT[], $4::CHAR(3)[], $5::CHAR(3)[], $6::CHAR(3)[])
+                WITH ORDINALITY AS f(flight_no, departure, duration, departure_airport, arrival_airport, aircraft_code, n)
+                     JOIN airports_data dep ON dep.airport_code = f.departure_airport
+            ORDER BY f.n
+            ",
+            flight_nos.as_slice(), departures.as_slice(), durations.as_slice(),
+            departure_airports.as_slice(), arrival_airports.as_slice(), aircraft_codes.as_slice()
+        )
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(flights.len())
+}
+
+/// Rows of bookings, tickets, legs and boarding passes waiting to be inserted
+#[derive(Default)]
+struct Sales {
+    book_refs: Vec<String>,
+    book_dates: Vec<DateTime<Utc>>,
+    total_amounts: Vec<i32>,
+
+    ticket_nos: Vec<String>,
+    ticket_book_refs: Vec<String>,
+    passenger_ids: Vec<String>,
+    passenger_names: Vec<String>,
+    contact_data: Vec<String>,
+
+    flight_ids: Vec<i32>,
+    fare_conditions: Vec<String>,
+    amounts: Vec<i32>,
+
+    boarded_ticket_nos: Vec<String>,
+    boarded_flight_ids: Vec<i32>,
+    boarding_nos: Vec<i32>,
+    seat_nos: Vec<String>,
+}
+
+impl Sales {
+    async fn flush(&mut self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "
+            INSERT INTO bookings (book_ref, book_date, total_amount)
+            SELECT * FROM UNNEST($1::CHAR(6)[], $2::TIMESTAMPTZ[], $3::INT[])
+            ",
+            self.book_refs.as_slice(), self.book_dates.as_slice(), self.total_amounts.as_slice()
+        )
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!(
+            "
+            INSERT INTO tickets (ticket_no, book_ref, passenger_id, passenger_name, contact_data)
+            SELECT ticket_no, book_ref, passenger_id, passenger_name, contact_data::JSONB
+            FROM UNNEST($1::CHAR(13)[], $2::CHAR(6)[], $3::VARCHAR[], $4::TEXT[], $5::TEXT[])
+                AS t(ticket_no, book_ref, passenger_id, passenger_name, contact_data)
+            ",
+            self.ticket_nos.as_slice(), self.ticket_book_refs.as_slice(), self.passenger_ids.as_slice(),
+            self.passenger_names.as_slice(), self.contact_data.as_slice()
+        )
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!(
+            "
+            INSERT INTO ticket_flights (ticket_no, flight_id, fare_conditions, amount)
+            SELECT * FROM UNNEST($1::CHAR(13)[], $2::INT[], $3::VARCHAR[], $4::INT[])
+            ",
+            self.ticket_nos.as_slice(), self.flight_ids.as_slice(), self.fare_conditions.as_slice(), self.amounts.as_slice()
+        )
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!(
+            "
+            INSERT INTO boarding_passes (ticket_no, flight_id, boarding_no, seat_no)
+            SELECT * FROM UNNEST($1::CHAR(13)[], $2::INT[], $3::INT[], $4::VARCHAR[])
+            ",
+            self.boarded_ticket_nos.as_slice(), self.boarded_flight_ids.as_slice(),
+            self.boarding_nos.as_slice(), self.seat_nos.as_slice()
+        )
+            .execute(&mut **tx)
+            .await?;
+
+        *self = Sales::default();
+
+        Ok(())
+    }
+}
+
+struct FlightStatus {
+    status: &'static str,
+    delay_minutes: i32,
+}
+
+fn flight_status(rng: &mut StdRng, departure: DateTime<Utc>, arrival: DateTime<Utc>, now: DateTime<Utc>) -> FlightStatus {
+    let delay_minutes = if rng.gen_bool(0.2) { rng.gen_range(1..=24) * 5 } else { 0 };
+    let delay = Duration::minutes(delay_minutes as i64);
+
+    let status = if departure > now && rng.gen_bool(0.01) {
+        "Cancelled"
+    } else if arrival + delay < now {
+        "Arrived"
+    } else if departure + delay < now {
+        "Departed"
+    } else if departure < now + Duration::days(1) {
+        if delay_minutes > 0 { "Delayed" } else { "On Time" }
+    } else {
+        "Scheduled"
+    };
+
+    FlightStatus { status, delay_minutes }
+}
+
+/// Sells seats of every flight whose sales have opened, booking them in groups
+/// of up to three passengers, and boards the passengers of departed flights.
+async fn insert_sales(tx: &mut Transaction<'_, Postgres>, rng: &mut StdRng, routes: &[Route], load_factor: f64) -> Result<(usize, usize), sqlx::Error> {
+    let now = sqlx::query!("SELECT bookings.now() AS \"now!\"")
+        .fetch_one(&mut **tx)
+        .await?
+        .now;
+
+    let flights = sqlx::query!(
+        "
+        SELECT flight_id, flight_no, scheduled_departure, scheduled_arrival
+        FROM flights
+        ORDER BY flight_id
+        "
+    )
+        .fetch_all(&mut **tx)
+        .await?;
+
+    let mut statuses = (vec![], vec![], vec![]);
+    let mut sales = Sales::default();
+    let (mut bookings, mut tickets) = (0, 0);
+
+    for flight in flights {
+        let route = routes.iter().find(|r| r.flight_no == flight.flight_no).unwrap();
+        let status = flight_status(rng, flight.scheduled_departure, flight.scheduled_arrival, now);
+
+        statuses.0.push(flight.flight_id);
+        statuses.1.push(status.status.to_string());
+        statuses.2.push(status.delay_minutes);
+
+        let sales_open = flight.scheduled_departure - Duration::days(SALES_DAYS);
+        let elapsed = (now - sales_open).num_minutes() as f64 / Duration::days(SALES_DAYS).num_minutes() as f64;
+
+        if elapsed <= 0.0 {
+            continue;
+        }
+
+        let boarded = matches!(status.status, "Departed" | "Arrived");
+        let sales_close = now.min(flight.scheduled_departure);
+        let mut boarding_order = vec![];
+
+        for cabin in route.aircraft.cabins {
+            let mut seats: Vec<String> = route.aircraft
+                .seats()
+                .into_iter()
+                .filter(|(_, class)| *class == cabin.fare_conditions)
+                .map(|(seat_no, _)| seat_no)
+                .collect();
+
+            seats.shuffle(rng);
+
+            let sold = (seats.len() as f64 * load_factor * rng.gen_range(0.6..=1.0) * elapsed.min(1.0)).round() as usize;
+
+            let amount = match cabin.fare_conditions {
+                "Business" => { route.fare * 3 }
+                "Comfort" => { route.fare * 16 / 10 / 100 * 100 }
+                _ => { route.fare }
+            };
+
+            let mut seats = seats.into_iter().take(sold).peekable();
+
+            while seats.peek().is_some() {
+                let group: Vec<String> = (0..rng.gen_range(1..=3)).filter_map(|_| seats.next()).collect();
+
+                bookings += 1;
+                let book_ref = format!("{:06X}", bookings);
+                let book_date = sales_open + (sales_close - sales_open) * rng.gen_range(0..1000) / 1000;
+
+                sales.book_refs.push(book_ref.clone());
+                sales.book_dates.push(book_date);
+                sales.total_amounts.push(amount * group.len() as i32);
+
+                for seat_no in group {
+                    tickets += 1;
+                    let ticket_no = format!("{:013}", 5_432_000_000u64 + tickets as u64);
+
+                    sales.ticket_nos.push(ticket_no.clone());
+                    sales.ticket_book_refs.push(book_ref.clone());
+                    sales.passenger_ids.push(format!("{:04} {:06}", rng.gen_range(0..10_000), rng.gen_range(0..1_000_000)));
+                    sales.passenger_names.push(format!("{} {}", GIVEN_NAMES.choose(rng).unwrap(), FAMILY_NAMES.choose(rng).unwrap()));
+                    sales.contact_data.push(serde_json::json!({"phone": format!("+70{:09}", rng.gen_range(0..1_000_000_000))}).to_string());
+                    sales.flight_ids.push(flight.flight_id);
+                    sales.fare_conditions.push(cabin.fare_conditions.to_string());
+                    sales.amounts.push(amount);
+
+                    if boarded {
+                        boarding_order.push((ticket_no, seat_no));
+                    }
+                }
+            }
+        }
+
+        boarding_order.shuffle(rng);
+
+        for (i, (ticket_no, seat_no)) in boarding_order.into_iter().enumerate() {
+            sales.boarded_ticket_nos.push(ticket_no);
+            sales.boarded_flight_ids.push(flight.flight_id);
+            sales.boarding_nos.push(i as i32 + 1);
+            sales.seat_nos.push(seat_no);
+        }
+
+        if sales.ticket_nos.len() >= BATCH_SIZE {
+            sales.flush(tx).await?;
+        }
+    }
+
+    sales.flush(tx).await?;
+
+    sqlx::query!(
+        "
+        UPDATE flights
+        SET status           = f.status,
+            actual_departure = CASE WHEN f.status IN ('Departed', 'Arrived') THEN scheduled_departure + f.delay * INTERVAL '1 minute' END,
+            actual_arrival   = CASE WHEN f.status = 'Arrived' THEN scheduled_arrival + f.delay * INTERVAL '1 minute' END
+        FROM UNNEST($1::INT[], $2::VARCHAR[], $3::INT[]) AS f(flight_id, status, delay)
+        WHERE flights.flight_id = f.flight_id
+        ",
+        statuses.0.as_slice(), statuses.1.as_slice(), statuses.2.as_slice()
+    )
+        .execute(&mut **tx)
+        .await?;
+
+    Ok((bookings, tickets))
+}
+
+async fn generate(args: &Args) -> Result<(), sqlx::Error> {
+    let mut rng = StdRng::seed_from_u64(args.seed);
+
+    let mut conn = PgConnection::connect(&args.database_url).await?;
+    let mut tx = conn.begin().await?;
+
+    if args.truncate {
+        sqlx::query!(
+            "TRUNCATE boarding_passes, ticket_flights, tickets, bookings, flights, seats, aircrafts_data, airports_data RESTART IDENTITY CASCADE"
+        )
+            .execute(&mut *tx)
+            .await?;
+    } else {
+        let has_data = sqlx::query!("SELECT EXISTS(SELECT 1 FROM airports_data) OR EXISTS(SELECT 1 FROM flights) AS \"has_data!\"")
+            .fetch_one(&mut *tx)
+            .await?
+            .has_data;
+
+        if has_data {
+            eprintln!("The database already has airports or flights, pass --truncate to replace them");
+            exit(1);
+        }
+    }
+
+    let airports = generate_airports(&mut rng, args.airports);
+    let routes = generate_routes(&mut rng, &airports, args.routes);
+
+    insert_reference_data(&mut tx, &airports).await?;
+    let flights = insert_flights(&mut tx, &routes, args.from, args.to).await?;
+    let (bookings, tickets) = insert_sales(&mut tx, &mut rng, &routes, args.load_factor).await?;
+
+    tx.commit().await?;
+
+    println!(
+        "Generated {} airports, {} routes, {} flights, {} bookings and {} tickets",
+        airports.len(), routes.len(), flights, bookings, tickets
+    );
+
+    Ok(())
+}
+
+#[actix_web::main]
+async fn main() {
+    dotenv().ok();
+
+    let args = Args::parse();
+
+    let errors = args.check();
+
+    if !errors.is_empty() {
+        eprintln!("Invalid arguments:");
+        for e in errors {
+            eprintln!("  {}", e);
+        }
+        exit(1);
+    }
+
+    if let Err(e) = generate(&args).await {
+        eprintln!("Error while generating data: {}", e);
+        exit(1);
+    }
+}
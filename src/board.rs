@@ -0,0 +1,129 @@
+use actix_web::{HttpResponse, Responder, web};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::app_state::AppState;
+use crate::flight_status::delay_minutes;
+use crate::lang::{Lang, LangParam};
+use crate::repository::{FlightDetails, Repository, RepositoryResult};
+use crate::types::BoardDirection;
+use crate::validation::{validate, AirportCodeParam, Validate, ValidationErrors};
+
+pub const DEFAULT_HOURS_BEFORE: u8 = 1;
+pub const DEFAULT_HOURS_AFTER: u8 = 6;
+pub const MAX_WINDOW_HOURS: u8 = 24;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BoardParameters {
+    direction: BoardDirection,
+    /// Instant the board is shown for, the current time of the dataset by default
+    at: Option<DateTime<Utc>>,
+    /// Hours shown before the instant, 1 by default
+    hours_before: Option<u8>,
+    /// Hours shown after the instant, 6 by default
+    hours_after: Option<u8>,
+}
+
+impl Validate for BoardParameters {
+    async fn validate(&self, _: &AppState, errors: &mut ValidationErrors) -> RepositoryResult<()> {
+        for (field, hours) in [("hours_before", self.hours_before), ("hours_after", self.hours_after)] {
+            if hours.is_some_and(|x| x > MAX_WINDOW_HOURS) {
+                errors.add(field, format!("Must not exceed {}", MAX_WINDOW_HOURS));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// One line of the board. The airport is the destination of a departure or
+/// the origin of an arrival, times are those of the board's side.
+#[derive(Serialize, ToSchema)]
+pub struct BoardEntry {
+    flight_id: i32,
+    flight_no: String,
+    status: String,
+    airport_code: String,
+    airport_name: Option<String>,
+    city: Option<String>,
+    scheduled: DateTime<Utc>,
+    scheduled_local: NaiveDateTime,
+    estimated: Option<DateTime<Utc>>,
+    estimated_local: Option<NaiveDateTime>,
+    actual: Option<DateTime<Utc>>,
+    actual_local: Option<NaiveDateTime>,
+    delay_minutes: Option<i64>,
+}
+
+impl BoardEntry {
+    fn new(flight: FlightDetails, direction: BoardDirection, now: DateTime<Utc>) -> BoardEntry {
+        let (own, counterpart) = match direction {
+            BoardDirection::Departures => { (flight.departure, flight.arrival) }
+            BoardDirection::Arrivals => { (flight.arrival, flight.departure) }
+        };
+
+        BoardEntry {
+            delay_minutes: delay_minutes(&own, &flight.status, now),
+            flight_id: flight.flight_id,
+            flight_no: flight.flight_no,
+            status: flight.status,
+            airport_code: counterpart.airport_code,
+            airport_name: counterpart.airport_name,
+            city: counterpart.city,
+            scheduled: own.scheduled,
+            scheduled_local: own.scheduled_local,
+            estimated: own.estimated,
+            estimated_local: own.estimated_local,
+            actual: own.actual,
+            actual_local: own.actual_local,
+        }
+    }
+}
+
+async fn board(airport_code: &str, parameters: &BoardParameters, lang: Lang, repo: &dyn Repository) -> RepositoryResult<Vec<BoardEntry>> {
+    let now = repo.now().await?;
+    let at = parameters.at.unwrap_or(now);
+
+    let from = at - Duration::hours(parameters.hours_before.unwrap_or(DEFAULT_HOURS_BEFORE) as i64);
+    let to = at + Duration::hours(parameters.hours_after.unwrap_or(DEFAULT_HOURS_AFTER) as i64);
+
+    let flight_ids = repo.board_flight_ids(airport_code, parameters.direction, from, to).await?;
+
+    let mut entries: Vec<BoardEntry> = repo.flight_details(&flight_ids, lang)
+        .await?
+        .into_iter()
+        .map(|flight| BoardEntry::new(flight, parameters.direction, now))
+        .collect();
+
+    entries.sort_by(|a, b| a.scheduled.cmp(&b.scheduled).then_with(|| a.flight_no.cmp(&b.flight_no)));
+
+    Ok(entries)
+}
+
+#[utoipa::path(
+    get, path = "/api/airports/{airport_code}/board", tag = "flights",
+    params(("airport_code" = String, Path), BoardParameters, LangParam, ("Accept-Language" = Option<String>, Header)),
+    responses(
+        (status = 200, description = "Flights of the airport in the time window, by scheduled time", body = Vec<BoardEntry>),
+        (status = 400, description = "Invalid fields", body = ValidationErrors)
+    )
+)]
+pub async fn airport_board(path: web::Path<String>, parameters: web::Query<BoardParameters>, lang: Lang, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = validate(&AirportCodeParam(path.as_str()), &state).await {
+        return response;
+    }
+
+    if let Err(response) = validate(&*parameters, &state).await {
+        return response;
+    }
+
+    match board(path.as_str(), &parameters, lang, &*state.repo).await {
+        Ok(entries) => {
+            HttpResponse::Ok().json(entries)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
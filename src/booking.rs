@@ -1,10 +1,11 @@
-use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 use rand::Rng;
-use sqlx::types::Decimal;
 
+use crate::config::PricingConfig;
 use crate::handlers::{CreateBookingParameters, CreateBookingResult};
+use crate::pricing::{quote_flights, FlightQuote};
+use crate::repository::{NewBooking, Repository, RepositoryError};
 
 #[derive(Debug)]
 struct NoFreeSpace;
@@ -17,71 +18,95 @@ impl Display for NoFreeSpace {
 
 impl Error for NoFreeSpace {}
 
-pub async fn create_booking_entries(parameters: CreateBookingParameters, transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<CreateBookingResult, Box<dyn Error>> {
-    let prices: HashMap<i32, i32> = sqlx::query!(
-        "
-        SELECT flights_v.flight_id, prices.amount
-        FROM flights_v
-        JOIN prices ON flights_v.flight_no = prices.flight_no
-        WHERE flights_v.flight_id = ANY($1::INT[])
-          AND fare_conditions = $2
-        ",
-        parameters.flight_ids.as_slice(),
-        String::from(parameters.fare_conditions)
-    )
-        .fetch_all(&mut **transaction)
-        .await?
-        .iter()
-        .map(|x| (x.flight_id.unwrap(), x.amount))
-        .collect();
+#[derive(Debug)]
+struct NoFareError {
+    flight_id: i32,
+}
 
-    let total_price: i32 = prices.iter().map(|(_, price)| price).sum();
+impl Display for NoFareError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Flight {} has no fare for this class", self.flight_id)
+    }
+}
 
+impl Error for NoFareError {}
+
+/// How many fresh booking numbers are tried before giving up on a booking
+const BOOKING_NUMBER_ATTEMPTS: u32 = 5;
+
+/// Draws a booking reference and ticket number from a space wide enough that
+/// collisions are rare, in the fixed widths of the `bookings` schema. The
+/// leading underscore keeps them apart from the numbers of the demo dataset.
+fn booking_numbers() -> (String, String) {
     let mut rng = rand::thread_rng();
 
-    let book_ref = format!("_FFF{:X}", rng.gen_range(0..256));
+    (
+        format!("_{:05X}", rng.gen_range(0..0x100000)),
+        format!("_{:012}", rng.gen_range(0..1_000_000_000_000u64)),
+    )
+}
 
-    let ticket_no = format!("_9999999999{:X}", rng.gen_range(0..256));
+/// Books the flights at current prices, or at `locked_quotes` prices when the
+/// client presented a valid quote token. Seat availability is always rechecked.
+pub async fn create_booking_entries(parameters: CreateBookingParameters, locked_quotes: Option<Vec<FlightQuote>>, pricing: &PricingConfig, repo: &dyn Repository) -> Result<CreateBookingResult, Box<dyn Error>> {
+    let quotes = quote_flights(
+        parameters.flight_ids.as_slice(),
+        parameters.fare_conditions,
+        pricing,
+        repo
+    ).await?;
 
-    println!("{}", book_ref);
+    let mut flight_quotes = vec![];
 
-    sqlx::query!(
-        "
-        INSERT INTO bookings (book_ref, book_date, total_amount)
-        VALUES      ($1, bookings.now(), $2);
-        ",
-        book_ref, Decimal::from(total_price)
-    )
-        .execute(&mut **transaction)
-        .await?;
-
-    sqlx::query!(
-        "
-        INSERT INTO tickets (ticket_no, book_ref, passenger_id, passenger_name)
-        VALUES      ($1, $2, $3, $4);
-        ",
-        ticket_no, book_ref, parameters.passenger_id, parameters.passenger_name
-    )
-        .execute(&mut **transaction)
-        .await?;
-
-    for flight_id in parameters.flight_ids {
-        let price = Decimal::from(*prices.get(&flight_id).unwrap_or(&0));
-
-        sqlx::query!(
-        "
-        INSERT INTO ticket_flights (ticket_no, flight_id, fare_conditions, amount)
-        VALUES      ($1, $2, $3, $4);
-        ",
-        ticket_no, flight_id, String::from(parameters.fare_conditions), price
-    )
-            .execute(&mut **transaction)
-            .await?;
+    for flight_id in &parameters.flight_ids {
+        let quote = match quotes.get(flight_id) {
+            Some(q) => { q }
+            None => { return Err(Box::new(NoFareError { flight_id: *flight_id })); }
+        };
+
+        if !quote.has_free_seats() {
+            return Err(Box::new(NoFreeSpace));
+        }
+
+        let locked = locked_quotes
+            .iter()
+            .flatten()
+            .find(|q| q.flight_id == *flight_id);
+
+        flight_quotes.push(locked.unwrap_or(quote).clone());
     }
 
+    let total_price: i32 = flight_quotes.iter().map(|q| q.amount).sum();
+
+    let mut attempt = 1;
+
+    let (book_ref, ticket_no) = loop {
+        let (book_ref, ticket_no) = booking_numbers();
+
+        let result = repo.create_booking(&NewBooking {
+            book_ref: book_ref.clone(),
+            ticket_no: ticket_no.clone(),
+            passenger_id: parameters.passenger_id.clone(),
+            passenger_name: parameters.passenger_name.clone(),
+            fare_conditions: parameters.fare_conditions,
+            legs: flight_quotes.clone(),
+        }).await;
+
+        match result {
+            Ok(()) => { break (book_ref, ticket_no); }
+            Err(RepositoryError::Duplicate) if attempt < BOOKING_NUMBER_ATTEMPTS => {
+                tracing::warn!(book_ref, ticket_no, attempt, "Booking number already taken");
+                attempt += 1;
+            }
+            Err(e) => { return Err(Box::new(e)); }
+        }
+    };
+
+    tracing::info!(book_ref, ticket_no, total_price, "Booking created");
+
     Ok(CreateBookingResult {
         booking_id: book_ref,
         ticker_no: ticket_no,
         total_price,
     })
-}
\ No newline at end of file
+}
@@ -0,0 +1,345 @@
+use actix_web::{HttpResponse, Responder, web};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::app_state::AppState;
+use crate::types::BookingClass;
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, ToSchema)]
+pub enum SeatTag {
+    Blocked,
+    ExtraLegroom,
+}
+
+impl From<SeatTag> for String {
+    fn from(value: SeatTag) -> Self {
+        match value {
+            SeatTag::Blocked => { "Blocked".to_string() }
+            SeatTag::ExtraLegroom => { "ExtraLegroom".to_string() }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RowRange {
+    first_row: i32,
+    last_row: i32,
+    fare_conditions: BookingClass,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TaggedSeat {
+    seat_no: String,
+    tag: SeatTag,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CabinLayout {
+    rows: Vec<RowRange>,
+    seat_tags: Vec<TaggedSeat>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RowRangeRecord {
+    first_row: i32,
+    last_row: i32,
+    fare_conditions: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct TaggedSeatRecord {
+    seat_no: String,
+    tag: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CabinLayoutRecord {
+    aircraft_code: String,
+    rows: Vec<RowRangeRecord>,
+    seat_tags: Vec<TaggedSeatRecord>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SeatClassification {
+    seat_no: Option<String>,
+    current_fare_conditions: Option<String>,
+    fare_conditions: Option<String>,
+    tags: Option<Vec<String>>,
+    blocked: Option<bool>,
+}
+
+fn validate_layout(layout: &CabinLayout) -> Result<(), String> {
+    let mut rows: Vec<&RowRange> = layout.rows.iter().collect();
+
+    rows.sort_by_key(|r| r.first_row);
+
+    for row in &rows {
+        if row.first_row <= 0 || row.first_row > row.last_row {
+            return Err(format!("Invalid row range {}-{}", row.first_row, row.last_row));
+        }
+    }
+
+    for pair in rows.windows(2) {
+        if pair[1].first_row <= pair[0].last_row {
+            return Err(format!(
+                "Row ranges {}-{} and {}-{} overlap",
+                pair[0].first_row, pair[0].last_row, pair[1].first_row, pair[1].last_row
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get, path = "/api/admin/cabin_layouts/{aircraft_code}", tag = "admin",
+    security(("bearer" = [])),
+    params(("aircraft_code" = String, Path)),
+    responses((status = 200, body = CabinLayoutRecord))
+)]
+pub async fn get_cabin_layout(path: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
+    let aircraft_code = path.into_inner();
+
+    let rows = sqlx::query_as!(
+        RowRangeRecord,
+        "
+        SELECT first_row, last_row, fare_conditions
+        FROM cabin_layouts
+        WHERE aircraft_code = $1
+        ORDER BY first_row
+        ",
+        aircraft_code
+    )
+        .fetch_all(&state.db_pool)
+        .await;
+
+    let seat_tags = sqlx::query_as!(
+        TaggedSeatRecord,
+        "
+        SELECT seat_no, tag
+        FROM cabin_seat_tags
+        WHERE aircraft_code = $1
+        ORDER BY seat_no, tag
+        ",
+        aircraft_code
+    )
+        .fetch_all(&state.db_pool)
+        .await;
+
+    match (rows, seat_tags) {
+        (Ok(rows), Ok(seat_tags)) => {
+            HttpResponse::Ok().json(CabinLayoutRecord {
+                aircraft_code,
+                rows,
+                seat_tags,
+            })
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+/// Replaces the layout of an aircraft. Seat classes are not changed until the
+/// layout is applied or seats are recomputed.
+#[utoipa::path(
+    put, path = "/api/admin/cabin_layouts/{aircraft_code}", tag = "admin",
+    security(("bearer" = [])),
+    params(("aircraft_code" = String, Path)),
+    request_body = CabinLayout,
+    responses(
+        (status = 200, body = CabinLayout),
+        (status = 400, description = "Invalid row ranges, unknown aircraft or seat", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn replace_cabin_layout(path: web::Path<String>, layout: web::Json<CabinLayout>, state: web::Data<AppState>) -> impl Responder {
+    let aircraft_code = path.into_inner();
+
+    if let Err(e) = validate_layout(&layout) {
+        return HttpResponse::BadRequest().body(e);
+    }
+
+    let result: Result<(), sqlx::Error> = async {
+        let mut transaction = state.db_pool.begin().await?;
+
+        sqlx::query!("DELETE FROM cabin_layouts WHERE aircraft_code = $1", aircraft_code)
+            .execute(&mut *transaction)
+            .await?;
+
+        sqlx::query!("DELETE FROM cabin_seat_tags WHERE aircraft_code = $1", aircraft_code)
+            .execute(&mut *transaction)
+            .await?;
+
+        for row in &layout.rows {
+            sqlx::query!(
+                "
+                INSERT INTO cabin_layouts (aircraft_code, first_row, last_row, fare_conditions)
+                VALUES      ($1, $2, $3, $4);
+                ",
+                aircraft_code, row.first_row, row.last_row, String::from(row.fare_conditions)
+            )
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        for seat in &layout.seat_tags {
+            sqlx::query!(
+                "
+                INSERT INTO cabin_seat_tags (aircraft_code, seat_no, tag)
+                VALUES      ($1, $2, $3)
+                ON CONFLICT DO NOTHING;
+                ",
+                aircraft_code, seat.seat_no, String::from(seat.tag)
+            )
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        transaction.commit().await
+    }.await;
+
+    match result {
+        Ok(()) => {
+            HttpResponse::Ok().json(layout.into_inner())
+        }
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() || e.is_check_violation() => {
+            HttpResponse::BadRequest().body(e.to_string())
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[utoipa::path(
+    delete, path = "/api/admin/cabin_layouts/{aircraft_code}", tag = "admin",
+    security(("bearer" = [])),
+    params(("aircraft_code" = String, Path)),
+    responses((status = 204))
+)]
+pub async fn delete_cabin_layout(path: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
+    let aircraft_code = path.into_inner();
+
+    let result: Result<(), sqlx::Error> = async {
+        let mut transaction = state.db_pool.begin().await?;
+
+        sqlx::query!("DELETE FROM cabin_layouts WHERE aircraft_code = $1", aircraft_code)
+            .execute(&mut *transaction)
+            .await?;
+
+        sqlx::query!("DELETE FROM cabin_seat_tags WHERE aircraft_code = $1", aircraft_code)
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await
+    }.await;
+
+    match result {
+        Ok(()) => {
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[utoipa::path(
+    get, path = "/api/admin/cabin_layouts/{aircraft_code}/preview", tag = "admin",
+    security(("bearer" = [])),
+    params(("aircraft_code" = String, Path)),
+    responses((status = 200, body = Vec<SeatClassification>))
+)]
+pub async fn preview_cabin_layout(path: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
+    match sqlx::query_as!(
+        SeatClassification,
+        "
+        SELECT
+            seats_layout_v.seat_no,
+            seats_comfort.fare_conditions AS current_fare_conditions,
+            seats_layout_v.fare_conditions,
+            seats_layout_v.tags::TEXT[] AS tags,
+            seats_layout_v.blocked
+        FROM seats_layout_v
+        LEFT JOIN seats_comfort ON seats_comfort.aircraft_code = seats_layout_v.aircraft_code
+            AND seats_comfort.seat_no = seats_layout_v.seat_no
+        WHERE seats_layout_v.aircraft_code = $1
+        ORDER BY substring(seats_layout_v.seat_no FROM '^[0-9]+')::INT, seats_layout_v.seat_no
+        ",
+        path.as_str()
+    )
+        .fetch_all(&state.db_pool)
+        .await
+    {
+        Ok(result) => {
+            HttpResponse::Ok().json(result)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+/// Replaces the seats of an aircraft in `seats_comfort` with the layout classification.
+#[utoipa::path(
+    post, path = "/api/admin/cabin_layouts/{aircraft_code}/apply", tag = "admin",
+    security(("bearer" = [])),
+    params(("aircraft_code" = String, Path)),
+    responses(
+        (status = 200, body = String),
+        (status = 404, description = "Aircraft has no cabin layout", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn apply_cabin_layout(path: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
+    let aircraft_code = path.into_inner();
+
+    // None when the aircraft has no layout, a layout may still block every seat
+    let result: Result<Option<u64>, sqlx::Error> = async {
+        let mut transaction = state.db_pool.begin().await?;
+
+        let has_layout = sqlx::query!(
+            "SELECT EXISTS(SELECT 1 FROM cabin_layouts WHERE aircraft_code = $1) AS exists",
+            aircraft_code
+        )
+            .fetch_one(&mut *transaction)
+            .await?
+            .exists
+            .unwrap_or(false);
+
+        if !has_layout {
+            return Ok(None);
+        }
+
+        sqlx::query!("DELETE FROM seats_comfort WHERE aircraft_code = $1", aircraft_code)
+            .execute(&mut *transaction)
+            .await?;
+
+        let inserted = sqlx::query!(
+            "
+            INSERT INTO seats_comfort (aircraft_code, seat_no, fare_conditions)
+            SELECT aircraft_code, seat_no, fare_conditions
+            FROM seats_layout_v
+            WHERE aircraft_code = $1 AND NOT blocked
+            ",
+            aircraft_code
+        )
+            .execute(&mut *transaction)
+            .await?
+            .rows_affected();
+
+        transaction.commit().await?;
+
+        Ok(Some(inserted))
+    }.await;
+
+    match result {
+        Ok(None) => {
+            HttpResponse::NotFound().body("Aircraft has no cabin layout")
+        }
+        Ok(Some(seats)) => {
+            HttpResponse::Ok().json(format!("Ok, classified {} seats", seats))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
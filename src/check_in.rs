@@ -1,6 +1,8 @@
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
-use sqlx::PgPool;
+use chrono::Duration;
+use crate::config::CheckInConfig;
+use crate::repository::{BoardingPass, Repository};
 
 #[derive(Debug)]
 pub struct NotRegisteredError;
@@ -13,74 +15,84 @@ impl Display for NotRegisteredError {
 
 impl Error for NotRegisteredError {}
 
-pub async fn check_in_passanger(ticket_no: String, flight_id: i32, pool: &PgPool) -> Result<String, Box<dyn Error>> {
-    let boarded_count = sqlx::query!(
-        "
-        SELECT COUNT(ticket_no) FROM boarding_passes
-        WHERE flight_id = $1
-        ",
-        flight_id
-    )
-        .fetch_one(pool)
-        .await?
-        .count
-        .unwrap_or(0);
-
-    let boarding_number = boarded_count + 1;
-
-    let fare_condition = match sqlx::query!(
-        "
-        SELECT fare_conditions FROM ticket_flights
-        WHERE ticket_no = $1 AND flight_id = $2;
-        ",
-        ticket_no, flight_id
-    )
-        .fetch_optional(pool)
+#[derive(Debug)]
+pub struct CheckInClosedError;
+
+impl Display for CheckInClosedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Check-in for this flight is not open")
+    }
+}
+
+impl Error for CheckInClosedError {}
+
+#[derive(Debug)]
+pub struct AlreadyCheckedInError;
+
+impl Display for AlreadyCheckedInError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("This passenger is already checked in for this flight")
+    }
+}
+
+impl Error for AlreadyCheckedInError {}
+
+#[derive(Debug)]
+pub struct NoFreeSeatError;
+
+impl Display for NoFreeSeatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("There are no free seats in this class")
+    }
+}
+
+impl Error for NoFreeSeatError {}
+
+pub async fn check_in_passanger(ticket_no: String, flight_id: i32, window: &CheckInConfig, repo: &dyn Repository) -> Result<String, Box<dyn Error>> {
+    let now = repo.now().await?;
+
+    let is_open = repo.flights(&[flight_id])
         .await?
-    {
-        Some(r) => { r.fare_conditions }
+        .first()
+        .map(|flight| {
+            let opens = Duration::try_hours(window.opens_hours_before).and_then(|d| flight.scheduled_departure.checked_sub_signed(d));
+            let closes = Duration::try_minutes(window.closes_minutes_before).and_then(|d| flight.scheduled_departure.checked_sub_signed(d));
+
+            // A window too wide for chrono opens before any date
+            opens.is_none_or(|opens| now >= opens) && closes.is_some_and(|closes| now <= closes)
+        })
+        .unwrap_or(false);
+
+    if !is_open {
+        return Err(Box::new(CheckInClosedError));
+    }
+
+    let fare_conditions = match repo.ticket_fare_conditions(&ticket_no, flight_id).await? {
+        Some(c) => { c }
         None => { return Err(Box::new(NotRegisteredError)); }
     };
 
-    let aircraft_code = sqlx::query!(
-        "
-        SELECT aircraft_code FROM flights_v
-        WHERE flight_id = $1
-        ",
-        flight_id
-    )
-        .fetch_one(pool)
-        .await?
-        .aircraft_code
-        .unwrap();
-
-    let place = sqlx::query!(
-        "
-        WITH occupied_seats_query AS (
-            SELECT COALESCE(array_agg(seat_no), '{}'::VARCHAR[]) AS occupied_seats
-            FROM boarding_passes
-            WHERE flight_id = $1
-        )
-        SELECT seat_no
-        FROM seats_comfort, occupied_seats_query
-        WHERE aircraft_code = $2 AND fare_conditions = $3
-                AND NOT (seats_comfort.seat_no = ANY(occupied_seats_query.occupied_seats));
-        ",
-        flight_id, aircraft_code, fare_condition
-    )
-        .fetch_one(pool)
+    let boarding_passes = repo.boarding_passes(flight_id).await?;
+
+    if boarding_passes.iter().any(|b| b.ticket_no == ticket_no) {
+        return Err(Box::new(AlreadyCheckedInError));
+    }
+
+    let place = match repo.cabin_seats(flight_id, fare_conditions)
         .await?
-        .seat_no;
-
-    sqlx::query!(
-        "
-        INSERT INTO boarding_passes (ticket_no, flight_id, boarding_no, seat_no)
-        VALUES      ($1, $2, $3, $4);
-        ",
-        ticket_no, flight_id, boarding_number as i32, place
-    )
-        .execute(pool)
-        .await?;
+        .into_iter()
+        .find(|seat| !boarding_passes.iter().any(|b| &b.seat_no == seat))
+    {
+        Some(s) => { s }
+        None => { return Err(Box::new(NoFreeSeatError)); }
+    };
+
+    repo.create_boarding_pass(&BoardingPass {
+        ticket_no,
+        flight_id,
+        boarding_no: boarding_passes.len() as i32 + 1,
+        seat_no: place.clone(),
+    }).await?;
 
     Ok(place)
-}
\ No newline at end of file
+}
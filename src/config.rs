@@ -1,19 +1,480 @@
-use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use clap::Parser;
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout_secs: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: String::new(),
+            max_connections: 5,
+            min_connections: 0,
+            acquire_timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub addr: String,
+    /// Defaults to the number of physical CPU cores
+    pub workers: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub jwt_secret: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    pub max_connections: u8,
+    pub max_connection_time_hours: u8,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            max_connections: 3,
+            max_connection_time_hours: 24,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CheckInConfig {
+    pub opens_hours_before: i64,
+    pub closes_minutes_before: i64,
+}
+
+impl Default for CheckInConfig {
+    fn default() -> Self {
+        CheckInConfig {
+            opens_hours_before: 24,
+            closes_minutes_before: 40,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PricingConfig {
+    pub dynamic: bool,
+    pub quote_ttl_minutes: i64,
+}
+
+impl Default for PricingConfig {
+    fn default() -> Self {
+        PricingConfig {
+            dynamic: true,
+            quote_ttl_minutes: 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhooksConfig {
+    /// Runs the delivery worker, events are written to the outbox either way
+    pub enabled: bool,
+    pub poll_interval_ms: u64,
+    /// Deliveries sent per poll
+    pub batch_size: i64,
+    pub timeout_secs: u64,
+    /// Attempts before a delivery goes to the dead letters
+    pub max_attempts: i32,
+    /// Delay before the first retry, doubled after every failed attempt
+    pub backoff_base_secs: i64,
+    pub max_backoff_secs: i64,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        WebhooksConfig {
+            enabled: true,
+            poll_interval_ms: 1000,
+            batch_size: 50,
+            timeout_secs: 10,
+            max_attempts: 8,
+            backoff_base_secs: 10,
+            max_backoff_secs: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => { Ok(LogFormat::Text) }
+            "json" => { Ok(LogFormat::Json) }
+            _ => { Err(format!("Unknown log format {}", s)) }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// `tracing` filter directives, e.g. `info` or `info,sqlx=debug`
+    pub level: String,
+    pub format: LogFormat,
+    pub slow_query_ms: u64,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            slow_query_ms: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    pub database_url: String,
-    pub server_addr: String
+    pub database: DatabaseConfig,
+    pub server: ServerConfig,
+    pub auth: AuthConfig,
+    pub search: SearchConfig,
+    pub check_in: CheckInConfig,
+    pub pricing: PricingConfig,
+    pub webhooks: WebhooksConfig,
+    pub logging: LoggingConfig,
+}
+
+/// Command line flags, each of which can also be given as an environment variable.
+/// Values are kept as strings so that all malformed ones are reported together.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Overrides {
+    /// TOML configuration file, `config.toml` is used if present
+    #[arg(long, env = "CONFIG_FILE")]
+    config: Option<PathBuf>,
+
+    #[arg(long, env = "DATABASE_URL")]
+    database_url: Option<String>,
+    #[arg(long, env = "DB_MAX_CONNECTIONS")]
+    db_max_connections: Option<String>,
+    #[arg(long, env = "DB_MIN_CONNECTIONS")]
+    db_min_connections: Option<String>,
+    #[arg(long, env = "DB_ACQUIRE_TIMEOUT_SECS")]
+    db_acquire_timeout_secs: Option<String>,
+
+    #[arg(long, env = "SERVER_ADDR")]
+    server_addr: Option<String>,
+    #[arg(long, env = "SERVER_WORKERS")]
+    workers: Option<String>,
+
+    #[arg(long, env = "JWT_SECRET", hide_env_values = true)]
+    jwt_secret: Option<String>,
+
+    #[arg(long, env = "SEARCH_MAX_CONNECTIONS")]
+    search_max_connections: Option<String>,
+    #[arg(long, env = "SEARCH_MAX_CONNECTION_TIME_HOURS")]
+    search_max_connection_time_hours: Option<String>,
+
+    #[arg(long, env = "CHECK_IN_OPENS_HOURS_BEFORE")]
+    check_in_opens_hours_before: Option<String>,
+    #[arg(long, env = "CHECK_IN_CLOSES_MINUTES_BEFORE")]
+    check_in_closes_minutes_before: Option<String>,
+
+    #[arg(long, env = "DYNAMIC_PRICING")]
+    dynamic_pricing: Option<String>,
+    #[arg(long, env = "QUOTE_TTL_MINUTES")]
+    quote_ttl_minutes: Option<String>,
+
+    #[arg(long, env = "WEBHOOKS_ENABLED")]
+    webhooks_enabled: Option<String>,
+    #[arg(long, env = "WEBHOOK_MAX_ATTEMPTS")]
+    webhook_max_attempts: Option<String>,
+    #[arg(long, env = "WEBHOOK_TIMEOUT_SECS")]
+    webhook_timeout_secs: Option<String>,
+
+    #[arg(long, env = "LOG_LEVEL")]
+    log_level: Option<String>,
+    #[arg(long, env = "LOG_FORMAT")]
+    log_format: Option<String>,
+    #[arg(long, env = "SLOW_QUERY_MS")]
+    slow_query_ms: Option<String>,
+}
+
+fn apply<T: FromStr>(errors: &mut Vec<String>, name: &str, value: &Option<String>, target: &mut T) {
+    if let Some(value) = value {
+        match value.parse() {
+            Ok(v) => { *target = v }
+            Err(_) => { errors.push(format!("{}: can't parse {:?}", name, value)) }
+        }
+    }
 }
 
 impl Config {
-    pub fn init() -> Config {
-        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        let server_addr = std::env::var("SERVER_ADDR").expect("SERVER_ADDR must be set");
+    /// Builds the configuration from defaults, the TOML file, environment
+    /// variables and command line flags, in increasing priority.
+    pub fn init() -> Result<Config, Vec<String>> {
+        Config::load(Overrides::parse())
+    }
+
+    pub fn load(overrides: Overrides) -> Result<Config, Vec<String>> {
+        let mut errors = vec![];
+
+        let path = overrides.config.clone().unwrap_or_else(|| PathBuf::from("config.toml"));
+
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                toml::from_str(&content).unwrap_or_else(|e| {
+                    errors.push(format!("{}: {}", path.display(), e));
+                    Config::default()
+                })
+            }
+            Err(e) if overrides.config.is_some() => {
+                errors.push(format!("{}: {}", path.display(), e));
+                Config::default()
+            }
+            Err(_) => { Config::default() }
+        };
+
+        config.apply_overrides(&overrides, &mut errors);
+        config.validate(&mut errors);
 
-        Config {
-            database_url,
-            server_addr
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
         }
     }
-}
\ No newline at end of file
+
+    fn apply_overrides(&mut self, o: &Overrides, errors: &mut Vec<String>) {
+        apply(errors, "database_url", &o.database_url, &mut self.database.url);
+        apply(errors, "db_max_connections", &o.db_max_connections, &mut self.database.max_connections);
+        apply(errors, "db_min_connections", &o.db_min_connections, &mut self.database.min_connections);
+        apply(errors, "db_acquire_timeout_secs", &o.db_acquire_timeout_secs, &mut self.database.acquire_timeout_secs);
+
+        apply(errors, "server_addr", &o.server_addr, &mut self.server.addr);
+
+        let mut workers = 0;
+        apply(errors, "workers", &o.workers, &mut workers);
+        if o.workers.is_some() {
+            self.server.workers = Some(workers);
+        }
+
+        apply(errors, "jwt_secret", &o.jwt_secret, &mut self.auth.jwt_secret);
+
+        apply(errors, "search_max_connections", &o.search_max_connections, &mut self.search.max_connections);
+        apply(errors, "search_max_connection_time_hours", &o.search_max_connection_time_hours, &mut self.search.max_connection_time_hours);
+
+        apply(errors, "check_in_opens_hours_before", &o.check_in_opens_hours_before, &mut self.check_in.opens_hours_before);
+        apply(errors, "check_in_closes_minutes_before", &o.check_in_closes_minutes_before, &mut self.check_in.closes_minutes_before);
+
+        apply(errors, "dynamic_pricing", &o.dynamic_pricing, &mut self.pricing.dynamic);
+        apply(errors, "quote_ttl_minutes", &o.quote_ttl_minutes, &mut self.pricing.quote_ttl_minutes);
+
+        apply(errors, "webhooks_enabled", &o.webhooks_enabled, &mut self.webhooks.enabled);
+        apply(errors, "webhook_max_attempts", &o.webhook_max_attempts, &mut self.webhooks.max_attempts);
+        apply(errors, "webhook_timeout_secs", &o.webhook_timeout_secs, &mut self.webhooks.timeout_secs);
+
+        apply(errors, "log_level", &o.log_level, &mut self.logging.level);
+        apply(errors, "log_format", &o.log_format, &mut self.logging.format);
+        apply(errors, "slow_query_ms", &o.slow_query_ms, &mut self.logging.slow_query_ms);
+    }
+
+    fn validate(&self, errors: &mut Vec<String>) {
+        if self.database.url.is_empty() {
+            errors.push("database.url (DATABASE_URL) must be set".to_string());
+        }
+        if self.database.max_connections == 0 {
+            errors.push("database.max_connections must be positive".to_string());
+        }
+        if self.database.min_connections > self.database.max_connections {
+            errors.push("database.min_connections must not exceed database.max_connections".to_string());
+        }
+        if self.database.acquire_timeout_secs == 0 {
+            errors.push("database.acquire_timeout_secs must be positive".to_string());
+        }
+
+        if self.server.addr.is_empty() {
+            errors.push("server.addr (SERVER_ADDR) must be set".to_string());
+        }
+        if self.server.workers == Some(0) {
+            errors.push("server.workers must be positive".to_string());
+        }
+
+        if self.auth.jwt_secret.is_empty() {
+            errors.push("auth.jwt_secret (JWT_SECRET) must be set".to_string());
+        }
+
+        if self.search.max_connection_time_hours == 0 {
+            errors.push("search.max_connection_time_hours must be positive".to_string());
+        }
+
+        if self.check_in.opens_hours_before < 0 {
+            errors.push("check_in.opens_hours_before must not be negative".to_string());
+        }
+        if self.check_in.closes_minutes_before < 0 {
+            errors.push("check_in.closes_minutes_before must not be negative".to_string());
+        }
+        match self.check_in.opens_hours_before.checked_mul(60) {
+            Some(opens) if opens <= self.check_in.closes_minutes_before => {
+                errors.push("check_in.opens_hours_before must be earlier than check_in.closes_minutes_before".to_string());
+            }
+            Some(_) => {}
+            None => {
+                errors.push("check_in.opens_hours_before is too large".to_string());
+            }
+        }
+
+        if self.pricing.quote_ttl_minutes <= 0 {
+            errors.push("pricing.quote_ttl_minutes must be positive".to_string());
+        }
+
+        if self.webhooks.poll_interval_ms == 0 || self.webhooks.batch_size <= 0 || self.webhooks.timeout_secs == 0 {
+            errors.push("webhooks.poll_interval_ms, batch_size and timeout_secs must be positive".to_string());
+        }
+        if self.webhooks.max_attempts <= 0 {
+            errors.push("webhooks.max_attempts must be positive".to_string());
+        }
+        if self.webhooks.backoff_base_secs < 0 || self.webhooks.max_backoff_secs < self.webhooks.backoff_base_secs {
+            errors.push("webhooks.backoff_base_secs must not be negative nor exceed max_backoff_secs".to_string());
+        }
+
+        if let Err(e) = EnvFilter::try_new(&self.logging.level) {
+            errors.push(format!("logging.level: {}", e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use super::*;
+
+    fn valid() -> Config {
+        let mut config = Config::default();
+        config.database.url = "postgres://localhost/demo".to_string();
+        config.server.addr = "127.0.0.1:8080".to_string();
+        config.auth.jwt_secret = "secret".to_string();
+        config
+    }
+
+    fn errors(config: &Config) -> Vec<String> {
+        let mut errors = vec![];
+        config.validate(&mut errors);
+        errors
+    }
+
+    #[test]
+    fn reports_every_missing_setting() {
+        assert!(errors(&valid()).is_empty());
+        assert_eq!(errors(&Config::default()), [
+            "database.url (DATABASE_URL) must be set",
+            "server.addr (SERVER_ADDR) must be set",
+            "auth.jwt_secret (JWT_SECRET) must be set",
+        ]);
+    }
+
+    #[test]
+    fn rejects_negative_inverted_and_overflowing_check_in_windows() {
+        let mut config = valid();
+        config.check_in.opens_hours_before = -1;
+        config.check_in.closes_minutes_before = -5;
+
+        assert_eq!(errors(&config), [
+            "check_in.opens_hours_before must not be negative",
+            "check_in.closes_minutes_before must not be negative",
+            "check_in.opens_hours_before must be earlier than check_in.closes_minutes_before",
+        ]);
+
+        config.check_in.opens_hours_before = 1;
+        config.check_in.closes_minutes_before = 60;
+        assert_eq!(errors(&config), ["check_in.opens_hours_before must be earlier than check_in.closes_minutes_before"]);
+
+        config.check_in.opens_hours_before = i64::MAX;
+        assert_eq!(errors(&config), ["check_in.opens_hours_before is too large"]);
+    }
+
+    #[test]
+    fn flags_override_environment_which_overrides_the_file() {
+        let path = std::env::temp_dir().join(format!("dp-flights-config-{}.toml", std::process::id()));
+
+        std::fs::write(&path, "
+            [database]
+            url = \"postgres://file/demo\"
+
+            [server]
+            addr = \"127.0.0.1:8080\"
+
+            [auth]
+            jwt_secret = \"secret\"
+
+            [search]
+            max_connections = 2
+
+            [logging]
+            level = \"debug\"
+            slow_query_ms = 100
+        ").unwrap();
+
+        std::env::set_var("LOG_LEVEL", "warn");
+        std::env::set_var("SLOW_QUERY_MS", "200");
+
+        let overrides = Overrides::try_parse_from([
+            "dp-flights-backend",
+            "--config", path.to_str().unwrap(),
+            "--database-url", "postgres://flag/demo",
+            "--log-level", "error",
+        ]).unwrap();
+
+        let config = Config::load(overrides);
+
+        std::env::remove_var("LOG_LEVEL");
+        std::env::remove_var("SLOW_QUERY_MS");
+        std::fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.database.url, "postgres://flag/demo");
+        assert_eq!(config.logging.level, "error");
+        assert_eq!(config.logging.slow_query_ms, 200);
+        assert_eq!(config.search.max_connections, 2);
+        assert_eq!(config.search.max_connection_time_hours, 24);
+    }
+
+    #[test]
+    fn reports_unreadable_file_and_unparseable_values_together() {
+        let overrides = Overrides::try_parse_from([
+            "dp-flights-backend",
+            "--config", "/nonexistent/config.toml",
+            "--db-max-connections", "lots",
+            "--workers", "none",
+        ]).unwrap();
+
+        let errors = Config::load(overrides).unwrap_err();
+
+        assert!(errors[0].starts_with("/nonexistent/config.toml: "), "{:?}", errors);
+        assert!(errors.contains(&"db_max_connections: can't parse \"lots\"".to_string()), "{:?}", errors);
+        assert!(errors.contains(&"workers: can't parse \"none\"".to_string()), "{:?}", errors);
+    }
+}
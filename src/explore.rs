@@ -0,0 +1,197 @@
+use std::collections::{BTreeMap, HashMap};
+use actix_web::{HttpResponse, Responder, web};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::app_state::AppState;
+use crate::find_flights::ExploreRecord;
+use crate::handlers::{convert_location_to_airport_codes, validate_location};
+use crate::lang::{Lang, LangParam};
+use crate::pricing::{quote_flights, FlightQuote};
+use crate::repository::{FlightSearch, RepositoryResult};
+use crate::types::{AirportCode, BookingClass, LocationType};
+use crate::validation::{check_departure_date, check_search_limits, validate, Validate, ValidationErrors};
+
+pub const DEFAULT_DESTINATIONS: u8 = 50;
+pub const MAX_DESTINATIONS: u8 = 200;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExploreParameters {
+    source_type: LocationType,
+    source: String,
+
+    max_connections: u8,
+
+    connection_time_min: u8,
+    connection_time_max: u8,
+
+    departure_date: NaiveDate,
+
+    booking_class: BookingClass,
+
+    /// Maximum number of destinations, 50 by default
+    limit: Option<u8>,
+}
+
+impl Validate for ExploreParameters {
+    async fn validate(&self, state: &AppState, errors: &mut ValidationErrors) -> RepositoryResult<()> {
+        validate_location(errors, "source", &self.source_type, &self.source, &*state.repo).await?;
+        check_search_limits(errors, &state.cfg.search, self.max_connections, self.connection_time_min, self.connection_time_max);
+
+        if let Some(limit) = self.limit {
+            if limit == 0 || limit > MAX_DESTINATIONS {
+                errors.add("limit", format!("Must be between 1 and {}", MAX_DESTINATIONS));
+            }
+        }
+
+        check_departure_date(errors, "departure_date", self.departure_date, &*state.repo).await
+    }
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct ExploreItinerary {
+    path: Vec<String>,
+    flight_ids: Vec<i32>,
+    departure_time: DateTime<Utc>,
+    arrival_time: DateTime<Utc>,
+    duration_minutes: i64,
+    /// Absent when some leg has no fare in the requested class
+    total_price: Option<i32>,
+}
+
+/// Best itineraries to one airport, each criterion chosen independently, so
+/// the same itinerary may appear more than once.
+#[derive(Serialize, ToSchema)]
+pub struct ExploreDestination {
+    airport_code: AirportCode,
+    city: Option<String>,
+    earliest_arrival: ExploreItinerary,
+    shortest: ExploreItinerary,
+    /// Lowest sum of base fares, priced at the current booking time,
+    /// absent when no itinerary to this airport is priced
+    cheapest: Option<ExploreItinerary>,
+}
+
+impl ExploreItinerary {
+    fn new(flight: ExploreRecord, quotes: &HashMap<i32, FlightQuote>) -> Option<ExploreItinerary> {
+        let flight_ids = flight.flight_ids?;
+        let (departure_time, arrival_time) = (flight.departure_time?, flight.arrival_time?);
+
+        let total_price = flight_ids
+            .iter()
+            .map(|id| quotes.get(id).map(|q| q.amount))
+            .sum();
+
+        Some(ExploreItinerary {
+            path: flight.path?,
+            duration_minutes: (arrival_time - departure_time).num_minutes(),
+            flight_ids,
+            departure_time,
+            arrival_time,
+            total_price,
+        })
+    }
+}
+
+/// Earliest arrival, shortest and cheapest itinerary to one airport
+type BestItineraries = (Option<ExploreItinerary>, Option<ExploreItinerary>, Option<ExploreItinerary>);
+
+/// Groups the itineraries chosen by the repository by destination
+fn best_by_destination(records: Vec<ExploreRecord>, quotes: &HashMap<i32, FlightQuote>) -> BTreeMap<AirportCode, BestItineraries> {
+    let mut best: BTreeMap<AirportCode, BestItineraries> = BTreeMap::new();
+
+    for record in records {
+        let criterion = record.criterion.clone();
+        let Some(itinerary) = ExploreItinerary::new(record, quotes) else { continue; };
+        let Some(destination) = itinerary.path.last().cloned() else { continue; };
+
+        let (earliest, shortest, cheapest) = best.entry(destination).or_default();
+
+        match criterion.as_deref() {
+            Some("earliest_arrival") => { *earliest = Some(itinerary) }
+            Some("shortest") => { *shortest = Some(itinerary) }
+            Some("cheapest") => { *cheapest = Some(itinerary) }
+            _ => {}
+        }
+    }
+
+    best
+}
+
+#[utoipa::path(
+    get, path = "/api/explore", tag = "search",
+    params(ExploreParameters, LangParam, ("Accept-Language" = Option<String>, Header)),
+    responses(
+        (status = 200, description = "The first `limit` reachable airports ordered by code", body = Vec<ExploreDestination>),
+        (status = 400, description = "Invalid fields", body = ValidationErrors)
+    )
+)]
+pub async fn explore(parameters: web::Query<ExploreParameters>, lang: Lang, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = validate(&*parameters, &state).await {
+        return response;
+    }
+
+    let sources = match convert_location_to_airport_codes(parameters.source_type.clone(), parameters.source.clone(), &*state.repo).await {
+        Ok(s) => { s }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    let search = FlightSearch {
+        sources,
+        destinations: None,
+        departure_date: parameters.departure_date,
+        max_connections: parameters.max_connections as i32,
+        connection_time_min: parameters.connection_time_min as i32,
+        connection_time_max: parameters.connection_time_max as i32,
+        booking_class: parameters.booking_class,
+    };
+
+    let limit = parameters.limit.unwrap_or(DEFAULT_DESTINATIONS) as i64;
+
+    let records = match state.repo.explore_destinations(&search, limit).await {
+        Ok(r) => { r }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    let mut flight_ids: Vec<i32> = records
+        .iter()
+        .flat_map(|x| x.flight_ids.clone().unwrap_or_default())
+        .collect();
+
+    flight_ids.sort();
+    flight_ids.dedup();
+
+    let quotes = match quote_flights(flight_ids.as_slice(), parameters.booking_class, &state.cfg.pricing, &*state.repo).await {
+        Ok(q) => { q }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    let cities: HashMap<String, Option<String>> = match state.repo.airport_details().await {
+        Ok(airports) => {
+            airports.into_iter().map(|a| (a.code, a.city.get(lang))).collect()
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    let destinations: Vec<ExploreDestination> = best_by_destination(records, &quotes)
+        .into_iter()
+        .filter_map(|(airport_code, (earliest_arrival, shortest, cheapest))| Some(ExploreDestination {
+            city: cities.get(&airport_code).cloned().flatten(),
+            airport_code,
+            earliest_arrival: earliest_arrival?,
+            shortest: shortest?,
+            cheapest,
+        }))
+        .collect();
+
+    HttpResponse::Ok().json(destinations)
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use actix_web::{HttpResponse, Responder, web};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::app_state::AppState;
+use crate::repository::{Fare, RepositoryError};
+use crate::types::BookingClass;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListFaresParameters {
+    flight_no: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateFareParameters {
+    amount: i32,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportFaresParameters {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ImportRowError {
+    line: u64,
+    message: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ImportReport {
+    total_rows: usize,
+    valid_rows: usize,
+    imported: usize,
+    errors: Vec<ImportRowError>,
+}
+
+fn validate_amount(amount: i32) -> Result<(), String> {
+    if amount <= 0 {
+        return Err(format!("Amount must be positive, got {}", amount));
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get, path = "/api/admin/fares", tag = "admin",
+    security(("bearer" = [])),
+    params(ListFaresParameters),
+    responses((status = 200, body = Vec<Fare>))
+)]
+pub async fn list_fares(parameters: web::Query<ListFaresParameters>, state: web::Data<AppState>) -> impl Responder {
+    match state.repo.fares(parameters.flight_no.as_deref()).await {
+        Ok(result) => {
+            HttpResponse::Ok().json(result)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[utoipa::path(
+    post, path = "/api/admin/fares", tag = "admin",
+    security(("bearer" = [])),
+    request_body = Fare,
+    responses(
+        (status = 201, body = Fare),
+        (status = 400, description = "Invalid amount or unknown flight", body = String, content_type = "text/plain"),
+        (status = 409, description = "Fare already exists", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn create_fare(fare: web::Json<Fare>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(e) = validate_amount(fare.amount) {
+        return HttpResponse::BadRequest().body(e);
+    }
+
+    match state.repo.flight_numbers().await {
+        Ok(flight_numbers) if flight_numbers.contains(&fare.flight_no) => {}
+        Ok(_) => {
+            return HttpResponse::BadRequest().body(format!("Unknown flight {}", fare.flight_no));
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    }
+
+    match state.repo.create_fare(&fare).await {
+        Ok(()) => {
+            HttpResponse::Created().json(fare.into_inner())
+        }
+        Err(RepositoryError::Duplicate) => {
+            HttpResponse::Conflict().body("Fare for this flight and class already exists")
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[utoipa::path(
+    put, path = "/api/admin/fares/{flight_no}/{fare_conditions}", tag = "admin",
+    security(("bearer" = [])),
+    params(("flight_no" = String, Path), ("fare_conditions" = BookingClass, Path)),
+    request_body = UpdateFareParameters,
+    responses(
+        (status = 200, body = Fare),
+        (status = 400, description = "Invalid amount", body = String, content_type = "text/plain"),
+        (status = 404, description = "Unknown fare")
+    )
+)]
+pub async fn update_fare(path: web::Path<(String, BookingClass)>, parameters: web::Json<UpdateFareParameters>, state: web::Data<AppState>) -> impl Responder {
+    let (flight_no, fare_conditions) = path.into_inner();
+
+    if let Err(e) = validate_amount(parameters.amount) {
+        return HttpResponse::BadRequest().body(e);
+    }
+
+    let fare = Fare {
+        flight_no,
+        fare_conditions,
+        amount: parameters.amount,
+    };
+
+    match state.repo.update_fare(&fare).await {
+        Ok(false) => {
+            HttpResponse::NotFound().json("")
+        }
+        Ok(true) => {
+            HttpResponse::Ok().json(fare)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[utoipa::path(
+    delete, path = "/api/admin/fares/{flight_no}/{fare_conditions}", tag = "admin",
+    security(("bearer" = [])),
+    params(("flight_no" = String, Path), ("fare_conditions" = BookingClass, Path)),
+    responses(
+        (status = 204),
+        (status = 404, description = "Unknown fare")
+    )
+)]
+pub async fn delete_fare(path: web::Path<(String, BookingClass)>, state: web::Data<AppState>) -> impl Responder {
+    let (flight_no, fare_conditions) = path.into_inner();
+
+    match state.repo.delete_fare(&flight_no, fare_conditions).await {
+        Ok(false) => {
+            HttpResponse::NotFound().json("")
+        }
+        Ok(true) => {
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[utoipa::path(
+    get, path = "/api/admin/fares/export", tag = "admin",
+    security(("bearer" = [])),
+    responses((status = 200, description = "`flight_no,fare_conditions,amount` CSV", body = String, content_type = "text/csv"))
+)]
+pub async fn export_fares(state: web::Data<AppState>) -> impl Responder {
+    let fares = match state.repo.fares(None).await {
+        Ok(r) => { r }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    for fare in fares {
+        if let Err(e) = writer.serialize(fare) {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    }
+
+    match writer.into_inner() {
+        Ok(body) => {
+            HttpResponse::Ok()
+                .content_type("text/csv")
+                .insert_header(("Content-Disposition", "attachment; filename=\"fares.csv\""))
+                .body(body)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+/// Validates every row of a `flight_no,fare_conditions,amount` CSV and upserts
+/// the fares in a single statement. Nothing is written if any row is invalid.
+#[utoipa::path(
+    post, path = "/api/admin/fares/import", tag = "admin",
+    security(("bearer" = [])),
+    params(ImportFaresParameters),
+    request_body(content = String, description = "`flight_no,fare_conditions,amount` CSV", content_type = "text/csv"),
+    responses(
+        (status = 200, body = ImportReport),
+        (status = 400, description = "Malformed CSV header", body = String, content_type = "text/plain"),
+        (status = 422, description = "Some rows are invalid, nothing was imported", body = ImportReport)
+    )
+)]
+pub async fn import_fares(body: web::Bytes, parameters: web::Query<ImportFaresParameters>, state: web::Data<AppState>) -> impl Responder {
+    let known_flights = match state.repo.flight_numbers().await {
+        Ok(r) => { r }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    let mut reader = csv::Reader::from_reader(body.as_ref());
+
+    let headers = match reader.headers() {
+        Ok(h) => { h.clone() }
+        Err(e) => {
+            return HttpResponse::BadRequest().body(e.to_string());
+        }
+    };
+
+    let mut report = ImportReport {
+        total_rows: 0,
+        valid_rows: 0,
+        imported: 0,
+        errors: vec![],
+    };
+
+    // Later rows override earlier ones for the same flight and class
+    let mut fares: HashMap<(String, BookingClass), Fare> = HashMap::new();
+
+    for record in reader.records() {
+        report.total_rows += 1;
+
+        let (line, row) = match record {
+            Ok(r) => { (r.position().map(|p| p.line()).unwrap_or(0), r.deserialize::<Fare>(Some(&headers))) }
+            Err(e) => { (e.position().map(|p| p.line()).unwrap_or(0), Err(e)) }
+        };
+
+        let fare = match row {
+            Ok(f) => { f }
+            Err(e) => {
+                report.errors.push(ImportRowError { line, message: e.to_string() });
+                continue;
+            }
+        };
+
+        if let Err(message) = validate_amount(fare.amount) {
+            report.errors.push(ImportRowError { line, message });
+            continue;
+        }
+
+        if !known_flights.contains(&fare.flight_no) {
+            report.errors.push(ImportRowError {
+                line,
+                message: format!("Unknown flight {}", fare.flight_no),
+            });
+            continue;
+        }
+
+        report.valid_rows += 1;
+
+        fares.insert((fare.flight_no.clone(), fare.fare_conditions), fare);
+    }
+
+    if !report.errors.is_empty() {
+        return HttpResponse::UnprocessableEntity().json(report);
+    }
+
+    if parameters.dry_run {
+        return HttpResponse::Ok().json(report);
+    }
+
+    let fares: Vec<Fare> = fares.into_values().collect();
+
+    match state.repo.upsert_fares(&fares).await {
+        Ok(imported) => {
+            report.imported = imported as usize;
+            HttpResponse::Ok().json(report)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
@@ -1,28 +1,20 @@
-use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
-use serde::de::Error;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
-use crate::types::{AirportCode, BookingClass};
+use tracing::Instrument;
+use utoipa::ToSchema;
+use crate::repository::FlightSearch;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct FlightRecord {
-    path: Option<Vec<String>>,
-    flight_ids: Option<Vec<i32>>,
-    departure_time: Option<DateTime<Utc>>,
-    arrival_time: Option<DateTime<Utc>>,
-    connections: Option<i32>
+    pub path: Option<Vec<String>>,
+    pub flight_ids: Option<Vec<i32>>,
+    pub departure_time: Option<DateTime<Utc>>,
+    pub arrival_time: Option<DateTime<Utc>>,
+    pub connections: Option<i32>
 }
 
-pub async fn find_flights(
-    sources: Vec<AirportCode>,
-    destinations: Vec<AirportCode>,
-    departure_date: NaiveDate,
-    max_connections: i32,
-    connection_time_min: i32,
-    connection_time_max: i32,
-    booking_class: BookingClass,
-    pool: &PgPool) -> Result<Vec<FlightRecord>, sqlx::Error> {
-
+pub async fn find_flights(search: &FlightSearch, pool: &PgPool) -> Result<Vec<FlightRecord>, sqlx::Error> {
     sqlx::query_as!(
         FlightRecord,
         "
@@ -36,7 +28,7 @@ pub async fn find_flights(
                     0 as len
             FROM flights_v f1
             WHERE f1.departure_airport = ANY($1::VARCHAR[])
-              AND f1.status = 'Scheduled'
+              AND f1.status IN ('Scheduled', 'On Time', 'Delayed')
               AND f1.scheduled_departure
                 BETWEEN $3
                 AND $3 + INTERVAL '24h'
@@ -54,11 +46,11 @@ pub async fn find_flights(
             FROM flights_recur
                      JOIN flights_v fn ON (
                 flights_recur.end_point = fn.departure_airport
-                    AND fn.status = 'Scheduled'
+                    AND fn.status IN ('Scheduled', 'On Time', 'Delayed')
                     AND NOT (fn.arrival_airport = ANY(flights_recur.path))
                     AND fn.scheduled_departure
-                    BETWEEN (flights_recur.arrival_time + INTERVAL '1h')
-                    AND (flights_recur.arrival_time + INTERVAL '24h')
+                    BETWEEN (flights_recur.arrival_time + make_interval(hours => $6))
+                    AND (flights_recur.arrival_time + make_interval(hours => $7))
                     AND free_seats(occupied_seats(fn.flight_id, $5), aircraft_type(fn.flight_id), $5) > 1
                 )
             WHERE len < $4
@@ -70,16 +62,126 @@ pub async fn find_flights(
             arrival_time,
             len AS connections
         FROM flights_recur
-        WHERE flights_recur.end_point = ANY($2::VARCHAR[]);
+        WHERE $2::VARCHAR[] IS NULL OR flights_recur.end_point = ANY($2::VARCHAR[]);
+        ",
+        search.sources.as_slice(),
+        search.destinations.as_deref(),
+        DateTime::<Utc>::from_naive_utc_and_offset(NaiveDateTime::from(search.departure_date), Utc),
+        search.max_connections,
+        String::from(search.booking_class),
+        search.connection_time_min,
+        search.connection_time_max,
+    )
+        .fetch_all(pool)
+        .instrument(tracing::info_span!("sql", query = "find_flights"))
+        .await
+}
+
+/// One of the best itineraries to a destination, `criterion` naming what it is best at:
+/// `earliest_arrival`, `shortest` or `cheapest` by the sum of base fares
+pub struct ExploreRecord {
+    pub criterion: Option<String>,
+    pub path: Option<Vec<String>>,
+    pub flight_ids: Option<Vec<i32>>,
+    pub departure_time: Option<DateTime<Utc>>,
+    pub arrival_time: Option<DateTime<Utc>>,
+}
+
+/// Same search as `find_flights` to any destination outside `sources`, keeping only the
+/// best itineraries of the first `limit` destinations by airport code
+pub async fn explore_destinations(search: &FlightSearch, limit: i64, pool: &PgPool) -> Result<Vec<ExploreRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ExploreRecord,
+        "
+        WITH RECURSIVE flights_recur AS (
+            SELECT
+                ARRAY[f1.departure_airport]::VARCHAR[] AS path,
+                    f1.arrival_airport AS end_point,
+                f1.scheduled_departure AS departure_time,
+                f1.scheduled_arrival AS arrival_time,
+                ARRAY[f1.flight_id]::INT[] as flight_id,
+                    0 as len
+            FROM flights_v f1
+            WHERE f1.departure_airport = ANY($1::VARCHAR[])
+              AND f1.status IN ('Scheduled', 'On Time', 'Delayed')
+              AND f1.scheduled_departure
+                BETWEEN $2
+                AND $2 + INTERVAL '24h'
+                AND free_seats(occupied_seats(f1.flight_id, $4), aircraft_type(f1.flight_id), $4) > 1
+
+            UNION
+
+            SELECT
+                flights_recur.path || flights_recur.end_point,
+                fn.arrival_airport,
+                flights_recur.departure_time,
+                fn.scheduled_arrival,
+                flights_recur.flight_id || fn.flight_id,
+                len + 1 AS i
+            FROM flights_recur
+                     JOIN flights_v fn ON (
+                flights_recur.end_point = fn.departure_airport
+                    AND fn.status IN ('Scheduled', 'On Time', 'Delayed')
+                    AND NOT (fn.arrival_airport = ANY(flights_recur.path))
+                    AND fn.scheduled_departure
+                    BETWEEN (flights_recur.arrival_time + make_interval(hours => $5))
+                    AND (flights_recur.arrival_time + make_interval(hours => $6))
+                    AND free_seats(occupied_seats(fn.flight_id, $4), aircraft_type(fn.flight_id), $4) > 1
+                )
+            WHERE len < $3
+        ),
+        itineraries AS (
+            SELECT
+                flights_recur.path || flights_recur.end_point AS path,
+                flights_recur.end_point,
+                flights_recur.flight_id AS flight_ids,
+                departure_time,
+                arrival_time,
+                -- NULL unless every leg has a fare in the class
+                (
+                    SELECT CASE WHEN count(prices.amount) = cardinality(flights_recur.flight_id) THEN sum(prices.amount) END
+                    FROM flights
+                    LEFT JOIN prices ON prices.flight_no = flights.flight_no AND prices.fare_conditions = $4
+                    WHERE flights.flight_id = ANY(flights_recur.flight_id)
+                ) AS base_price
+            FROM flights_recur
+            WHERE flights_recur.end_point IN (
+                -- Airports of the origin city reachable from one another are not destinations
+                SELECT DISTINCT end_point
+                FROM flights_recur
+                WHERE NOT (end_point = ANY($1::VARCHAR[]))
+                ORDER BY end_point
+                LIMIT $7
+            )
+        )
+        (
+            SELECT DISTINCT ON (end_point) 'earliest_arrival' AS criterion, path, flight_ids, departure_time, arrival_time
+            FROM itineraries
+            ORDER BY end_point, arrival_time, arrival_time - departure_time, flight_ids
+        )
+        UNION ALL
+        (
+            SELECT DISTINCT ON (end_point) 'shortest' AS criterion, path, flight_ids, departure_time, arrival_time
+            FROM itineraries
+            ORDER BY end_point, arrival_time - departure_time, arrival_time, flight_ids
+        )
+        UNION ALL
+        (
+            SELECT DISTINCT ON (end_point) 'cheapest' AS criterion, path, flight_ids, departure_time, arrival_time
+            FROM itineraries
+            WHERE base_price IS NOT NULL
+            ORDER BY end_point, base_price, arrival_time, flight_ids
+        )
         ",
-        sources.as_slice(),
-        destinations.as_slice(),
-        DateTime::<Utc>::from_naive_utc_and_offset(NaiveDateTime::from(departure_date), Utc),
-        max_connections,
-        String::from(booking_class)
-        // format!("{}h", connection_time_min).to_string(),
-        // connection_time_max,
+        search.sources.as_slice(),
+        DateTime::<Utc>::from_naive_utc_and_offset(NaiveDateTime::from(search.departure_date), Utc),
+        search.max_connections,
+        String::from(search.booking_class),
+        search.connection_time_min,
+        search.connection_time_max,
+        limit,
     )
         .fetch_all(pool)
+        .instrument(tracing::info_span!("sql", query = "explore_destinations"))
         .await
 }
@@ -0,0 +1,287 @@
+use std::collections::HashSet;
+use std::time::Duration;
+use actix_web::rt::time::sleep;
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use actix_ws::Message;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgListener, PgPoolOptions};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{channel, Receiver, Sender};
+use utoipa::{IntoParams, ToSchema};
+use crate::app_state::AppState;
+use crate::repository::RepositoryResult;
+use crate::validation::{check_iata_code, validate, Validate, ValidationErrors};
+
+/// Channel notified by the trigger of `sql/6_create_flight_events_trigger.sql`,
+/// whose payload `sql/7_create_flight_status_audit_table.sql` extends
+pub const CHANNEL: &str = "flight_events";
+
+/// Events kept for subscribers that fall behind, older ones are dropped
+const BUFFER: usize = 1024;
+
+/// Comment sent to idle SSE streams so that proxies keep them open
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Change of the status, estimated or actual times or gate of a flight
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct FlightEvent {
+    pub flight_id: i32,
+    pub flight_no: String,
+    pub departure_airport: String,
+    pub arrival_airport: String,
+    pub status: String,
+    pub previous_status: String,
+    pub estimated_departure: Option<DateTime<Utc>>,
+    pub estimated_arrival: Option<DateTime<Utc>>,
+    pub actual_departure: Option<DateTime<Utc>>,
+    pub actual_arrival: Option<DateTime<Utc>>,
+    pub gate: Option<String>,
+}
+
+/// Fans flight events out to every open subscription
+pub struct FlightEvents {
+    sender: Sender<FlightEvent>,
+}
+
+impl Default for FlightEvents {
+    fn default() -> Self {
+        FlightEvents { sender: channel(BUFFER).0 }
+    }
+}
+
+impl FlightEvents {
+    pub fn publish(&self, event: FlightEvent) {
+        // Fails only when nobody is subscribed
+        let _ = self.sender.send(event);
+    }
+
+    fn subscribe(&self) -> Receiver<FlightEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// Publishes the notifications of the `flights` trigger until the server stops.
+/// The listener holds a connection of its own outside `database.max_connections`.
+/// It reconnects by itself, events sent while disconnected are lost.
+pub async fn listen(state: web::Data<AppState>) {
+    // Same settings as the pool, but one connection that never expires, as PgListener::connect does
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .max_lifetime(None)
+        .idle_timeout(None)
+        .connect_lazy_with((*state.db_pool.connect_options()).clone());
+
+    loop {
+        let mut listener = match PgListener::connect_with(&pool).await {
+            Ok(l) => { l }
+            Err(e) => {
+                tracing::error!(error = %e, "Can't connect the flight events listener");
+                sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+
+        if let Err(e) = listener.listen(CHANNEL).await {
+            tracing::error!(error = %e, "Can't listen to flight events");
+            sleep(RECONNECT_DELAY).await;
+            continue;
+        }
+
+        tracing::info!(channel = CHANNEL, "Listening to flight events");
+
+        loop {
+            match listener.recv().await {
+                Ok(notification) => {
+                    match serde_json::from_str::<FlightEvent>(notification.payload()) {
+                        Ok(event) => { state.flight_events.publish(event) }
+                        Err(e) => { tracing::warn!(error = %e, payload = notification.payload(), "Malformed flight event") }
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, "Flight events listener disconnected");
+                    sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubscriptionParameters {
+    /// Comma-separated flight ids
+    flight_ids: Option<String>,
+    /// Comma-separated airport codes, matching flights departing from or arriving at them
+    airports: Option<String>,
+}
+
+impl Validate for SubscriptionParameters {
+    async fn validate(&self, _: &AppState, errors: &mut ValidationErrors) -> RepositoryResult<()> {
+        if let Some(flight_ids) = &self.flight_ids {
+            if flight_ids.split(',').any(|id| id.trim().parse::<i32>().is_err()) {
+                errors.add("flight_ids", "Must be flight ids separated by commas");
+            }
+        }
+
+        if let Some(airports) = &self.airports {
+            for code in airports.split(',') {
+                check_iata_code(errors, "airports", code.trim());
+            }
+        }
+
+        if self.flight_ids.is_none() && self.airports.is_none() {
+            errors.add("flight_ids", "At least one flight or airport is required");
+        }
+
+        Ok(())
+    }
+}
+
+struct Subscription {
+    flight_ids: HashSet<i32>,
+    airports: HashSet<String>,
+}
+
+impl Subscription {
+    fn new(parameters: &SubscriptionParameters) -> Subscription {
+        let split = |list: &Option<String>| -> Vec<String> {
+            list.iter().flat_map(|x| x.split(',')).map(|x| x.trim().to_string()).collect()
+        };
+
+        Subscription {
+            flight_ids: split(&parameters.flight_ids).iter().filter_map(|x| x.parse().ok()).collect(),
+            airports: split(&parameters.airports).into_iter().collect(),
+        }
+    }
+
+    fn matches(&self, event: &FlightEvent) -> bool {
+        self.flight_ids.contains(&event.flight_id)
+            || self.airports.contains(&event.departure_airport)
+            || self.airports.contains(&event.arrival_airport)
+    }
+}
+
+enum Received {
+    Event(FlightEvent),
+    /// Number of events dropped because the subscriber was too slow
+    Lagged(u64),
+    Closed,
+}
+
+/// Next event of the subscription, skipping the events of other flights
+async fn next_event(receiver: &mut Receiver<FlightEvent>, subscription: &Subscription) -> Received {
+    loop {
+        match receiver.recv().await {
+            Ok(event) if subscription.matches(&event) => { return Received::Event(event) }
+            Ok(_) => {}
+            Err(RecvError::Lagged(missed)) => { return Received::Lagged(missed) }
+            Err(RecvError::Closed) => { return Received::Closed }
+        }
+    }
+}
+
+fn lagged_message(missed: u64) -> String {
+    serde_json::json!({ "missed": missed }).to_string()
+}
+
+#[utoipa::path(
+    get, path = "/api/flight_events", tag = "flights",
+    params(SubscriptionParameters),
+    responses(
+        (status = 200, description = "Server-sent `flight` events, and a `lagged` event with the number of missed events when the client reads too slowly", body = FlightEvent, content_type = "text/event-stream"),
+        (status = 400, description = "Invalid fields", body = ValidationErrors)
+    )
+)]
+pub async fn flight_events_stream(parameters: web::Query<SubscriptionParameters>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = validate(&*parameters, &state).await {
+        return response;
+    }
+
+    let receiver = state.flight_events.subscribe();
+    let subscription = Subscription::new(&parameters);
+
+    let stream = futures::stream::unfold((receiver, subscription), |(mut receiver, subscription)| async move {
+        let chunk = tokio::select! {
+            received = next_event(&mut receiver, &subscription) => {
+                match received {
+                    Received::Event(event) => {
+                        format!("event: flight\ndata: {}\n\n", serde_json::to_string(&event).unwrap_or_default())
+                    }
+                    Received::Lagged(missed) => {
+                        format!("event: lagged\ndata: {}\n\n", lagged_message(missed))
+                    }
+                    Received::Closed => { return None; }
+                }
+            }
+            _ = sleep(KEEP_ALIVE) => { ": keep-alive\n\n".to_string() }
+        };
+
+        Some((Ok::<_, actix_web::Error>(Bytes::from(chunk)), (receiver, subscription)))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
+}
+
+#[utoipa::path(
+    get, path = "/api/flight_events/ws", tag = "flights",
+    params(SubscriptionParameters),
+    responses(
+        (status = 101, description = "WebSocket sending each event as a JSON text message, and `{\"missed\": n}` when the client reads too slowly"),
+        (status = 400, description = "Invalid fields or not a WebSocket handshake", body = ValidationErrors)
+    )
+)]
+pub async fn flight_events_ws(req: HttpRequest, body: web::Payload, parameters: web::Query<SubscriptionParameters>, state: web::Data<AppState>) -> HttpResponse {
+    if let Err(response) = validate(&*parameters, &state).await {
+        return response;
+    }
+
+    let (response, mut session, mut messages) = match actix_ws::handle(&req, body) {
+        Ok(x) => { x }
+        Err(e) => {
+            return HttpResponse::from_error(e);
+        }
+    };
+
+    let mut receiver = state.flight_events.subscribe();
+    let subscription = Subscription::new(&parameters);
+
+    actix_web::rt::spawn(async move {
+        loop {
+            tokio::select! {
+                received = next_event(&mut receiver, &subscription) => {
+                    let text = match received {
+                        Received::Event(event) => { serde_json::to_string(&event).unwrap_or_default() }
+                        Received::Lagged(missed) => { lagged_message(missed) }
+                        Received::Closed => { break; }
+                    };
+
+                    if session.text(text).await.is_err() {
+                        return;
+                    }
+                }
+                message = messages.recv() => {
+                    match message {
+                        Some(Ok(Message::Ping(bytes))) => {
+                            if session.pong(&bytes).await.is_err() {
+                                return;
+                            }
+                        }
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => { break; }
+                        Some(Ok(_)) => {}
+                    }
+                }
+            }
+        }
+
+        let _ = session.close(None).await;
+    });
+
+    response
+}
@@ -0,0 +1,200 @@
+use actix_web::{HttpResponse, Responder, web};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use utoipa::ToSchema;
+use crate::app_state::AppState;
+use crate::auth::Claims;
+use crate::flight_status::{flight_statuses, FlightStatus};
+use crate::lang::{Lang, LangParam};
+use crate::repository::{FlightAuditEntry, FlightStatusChange, FlightTimes};
+use crate::types::FlightState;
+use crate::validation::ValidationErrors;
+
+/// New status of a flight with the times it requires, other times are kept
+#[derive(Deserialize, ToSchema)]
+pub struct FlightStatusUpdate {
+    status: FlightState,
+    /// Required for `Delayed`, after the scheduled departure
+    estimated_departure: Option<DateTime<Utc>>,
+    /// For `Delayed`, the scheduled arrival shifted by the delay by default
+    estimated_arrival: Option<DateTime<Utc>>,
+    /// Required for `Departed`
+    actual_departure: Option<DateTime<Utc>>,
+    /// Required for `Arrived`, after the actual departure
+    actual_arrival: Option<DateTime<Utc>>,
+    /// Required for `Cancelled`, kept in the audit entry
+    reason: Option<String>,
+}
+
+impl FlightStatusUpdate {
+    /// Status and times of the flight after the update, with the fields that
+    /// are missing, out of order or not used by the new status reported
+    fn apply(&self, current: &FlightTimes, errors: &mut ValidationErrors) -> FlightTimes {
+        let mut times = FlightTimes { status: self.status, ..current.clone() };
+
+        let used = |field: &str| match self.status {
+            FlightState::Delayed => { field == "estimated_departure" || field == "estimated_arrival" }
+            FlightState::Departed => { field == "actual_departure" }
+            FlightState::Arrived => { field == "actual_arrival" }
+            _ => { false }
+        };
+
+        for (field, value) in [
+            ("estimated_departure", self.estimated_departure),
+            ("estimated_arrival", self.estimated_arrival),
+            ("actual_departure", self.actual_departure),
+            ("actual_arrival", self.actual_arrival),
+        ] {
+            if value.is_some() && !used(field) {
+                errors.add(field, format!("Not used by status {}", self.status.as_str()));
+            }
+        }
+
+        match self.status {
+            FlightState::Delayed => {
+                match self.estimated_departure {
+                    Some(departure) if departure <= current.scheduled_departure => {
+                        errors.add("estimated_departure", "Must be after the scheduled departure");
+                    }
+                    Some(departure) => {
+                        let arrival = self.estimated_arrival
+                            .unwrap_or(current.scheduled_arrival + (departure - current.scheduled_departure));
+
+                        if arrival <= departure {
+                            errors.add("estimated_arrival", "Must be after the estimated departure");
+                        }
+
+                        times.estimated_departure = Some(departure);
+                        times.estimated_arrival = Some(arrival);
+                    }
+                    None => {
+                        errors.add("estimated_departure", "Required to delay a flight");
+                    }
+                }
+            }
+            FlightState::Departed => {
+                match self.actual_departure {
+                    Some(departure) => { times.actual_departure = Some(departure) }
+                    None => { errors.add("actual_departure", "Required when a flight departs") }
+                }
+            }
+            FlightState::Arrived => {
+                match self.actual_arrival {
+                    Some(arrival) if current.actual_departure.is_some_and(|departure| arrival <= departure) => {
+                        errors.add("actual_arrival", "Must be after the actual departure");
+                    }
+                    Some(arrival) => { times.actual_arrival = Some(arrival) }
+                    None => { errors.add("actual_arrival", "Required when a flight arrives") }
+                }
+            }
+            FlightState::Cancelled => {
+                if self.reason.as_deref().is_none_or(|r| r.trim().is_empty()) {
+                    errors.add("reason", "Required to cancel a flight");
+                }
+            }
+            FlightState::Scheduled | FlightState::OnTime => {}
+        }
+
+        times
+    }
+}
+
+#[utoipa::path(
+    post, path = "/api/ops/flights/{flight_id}/status", tag = "operations",
+    security(("bearer" = [])),
+    params(("flight_id" = i32, Path), LangParam, ("Accept-Language" = Option<String>, Header)),
+    request_body = FlightStatusUpdate,
+    responses(
+        (status = 200, description = "Flight after the change", body = FlightStatus),
+        (status = 400, description = "Invalid fields", body = ValidationErrors),
+        (status = 404, description = "Unknown flight", body = String, content_type = "text/plain"),
+        (status = 409, description = "The flight can't go from its status to the new one, or was changed concurrently", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn change_flight_status(path: web::Path<i32>, update: web::Json<FlightStatusUpdate>, claims: web::ReqData<Claims>, lang: Lang, state: web::Data<AppState>) -> impl Responder {
+    let flight_id = path.into_inner();
+
+    let current = match state.repo.flight_times(flight_id).await {
+        Ok(Some(t)) => { t }
+        Ok(None) => {
+            return HttpResponse::NotFound().body(format!("Unknown flight {}", flight_id));
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    if !current.status.can_become(update.status) {
+        return HttpResponse::Conflict().body(format!(
+            "Flight {} can't go from {} to {}", flight_id, current.status.as_str(), update.status.as_str()
+        ));
+    }
+
+    let mut errors = ValidationErrors::default();
+    let times = update.apply(&current, &mut errors);
+
+    if !errors.errors.is_empty() {
+        return HttpResponse::BadRequest().json(errors);
+    }
+
+    let change = FlightStatusChange {
+        flight_id,
+        previous_status: current.status,
+        times,
+        reason: update.reason.clone(),
+        changed_by: claims.sub.clone(),
+    };
+
+    match state.repo.change_flight_status(&change).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::Conflict().body(format!("Flight {} was changed concurrently, try again", flight_id));
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    }
+
+    tracing::info!(flight_id, from = current.status.as_str(), to = change.times.status.as_str(), by = %change.changed_by, "Flight status changed");
+
+    match flight_statuses(&[flight_id], lang, &*state.repo).await {
+        Ok(flights) => {
+            HttpResponse::Ok().json(flights.first())
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[utoipa::path(
+    get, path = "/api/ops/flights/{flight_id}/audit", tag = "operations",
+    security(("bearer" = [])),
+    params(("flight_id" = i32, Path)),
+    responses(
+        (status = 200, description = "Status changes of the flight, oldest first", body = Vec<FlightAuditEntry>),
+        (status = 404, description = "Unknown flight", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn flight_status_audit(path: web::Path<i32>, state: web::Data<AppState>) -> impl Responder {
+    let flight_id = path.into_inner();
+
+    match state.repo.flight_times(flight_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return HttpResponse::NotFound().body(format!("Unknown flight {}", flight_id));
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    }
+
+    match state.repo.flight_status_audit(flight_id).await {
+        Ok(entries) => {
+            HttpResponse::Ok().json(entries)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
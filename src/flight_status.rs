@@ -0,0 +1,114 @@
+use actix_web::{HttpResponse, Responder, web};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::app_state::AppState;
+use crate::lang::{Lang, LangParam};
+use crate::repository::{FlightDetails, FlightEndpoint, Repository, RepositoryResult};
+use crate::validation::{validate, Validate, ValidationErrors};
+
+#[derive(Serialize, ToSchema)]
+pub struct FlightStatus {
+    #[serde(flatten)]
+    flight: FlightDetails,
+    /// Minutes the departure is behind schedule: actual, else estimated, else
+    /// counted up to now for delayed flights that have not left yet. Absent
+    /// for other flights that have not left.
+    delay_minutes: Option<i64>,
+}
+
+/// Minutes the departure or arrival is behind schedule, see `FlightStatus`
+pub fn delay_minutes(endpoint: &FlightEndpoint, status: &str, now: DateTime<Utc>) -> Option<i64> {
+    match (endpoint.actual, endpoint.estimated) {
+        (Some(actual), _) => { Some((actual - endpoint.scheduled).num_minutes()) }
+        (None, Some(estimated)) => { Some((estimated - endpoint.scheduled).num_minutes()) }
+        (None, None) if status == "Delayed" => { Some((now - endpoint.scheduled).num_minutes().max(0)) }
+        (None, None) => { None }
+    }
+}
+
+pub async fn flight_statuses(flight_ids: &[i32], lang: Lang, repo: &dyn Repository) -> RepositoryResult<Vec<FlightStatus>> {
+    let now = repo.now().await?;
+
+    Ok(
+        repo.flight_details(flight_ids, lang)
+            .await?
+            .into_iter()
+            .map(|flight| FlightStatus {
+                delay_minutes: delay_minutes(&flight.departure, &flight.status, now),
+                flight,
+            })
+            .collect()
+    )
+}
+
+#[utoipa::path(
+    get, path = "/api/flights/{flight_id}", tag = "flights",
+    params(("flight_id" = i32, Path), LangParam, ("Accept-Language" = Option<String>, Header)),
+    responses(
+        (status = 200, body = FlightStatus),
+        (status = 404, description = "Unknown flight", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn get_flight(path: web::Path<i32>, lang: Lang, state: web::Data<AppState>) -> impl Responder {
+    let flight_id = path.into_inner();
+
+    match flight_statuses(&[flight_id], lang, &*state.repo).await {
+        Ok(flights) => {
+            match flights.into_iter().next() {
+                Some(flight) => { HttpResponse::Ok().json(flight) }
+                None => { HttpResponse::NotFound().body(format!("Unknown flight {}", flight_id)) }
+            }
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FlightNumberParameters {
+    /// Flight number, e.g. `PG0001`
+    flight_no: String,
+    /// Departure date, local to the departure airport
+    date: NaiveDate,
+}
+
+impl Validate for FlightNumberParameters {
+    async fn validate(&self, _: &AppState, errors: &mut ValidationErrors) -> RepositoryResult<()> {
+        if self.flight_no.len() != 6 || !self.flight_no.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+            errors.add("flight_no", "Must be 6 capital letters or digits");
+        }
+
+        Ok(())
+    }
+}
+
+#[utoipa::path(
+    get, path = "/api/flights", tag = "flights",
+    params(FlightNumberParameters, LangParam, ("Accept-Language" = Option<String>, Header)),
+    responses(
+        (status = 200, description = "Flights with the number departing on the date, usually one", body = Vec<FlightStatus>),
+        (status = 400, description = "Invalid fields", body = ValidationErrors)
+    )
+)]
+pub async fn find_flights_by_number(parameters: web::Query<FlightNumberParameters>, lang: Lang, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = validate(&*parameters, &state).await {
+        return response;
+    }
+
+    let flights = async {
+        let flight_ids = state.repo.flight_ids_by_number(&parameters.flight_no, parameters.date).await?;
+        flight_statuses(&flight_ids, lang, &*state.repo).await
+    }.await;
+
+    match flights {
+        Ok(flights) => {
+            HttpResponse::Ok().json(flights)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
@@ -0,0 +1,909 @@
+use std::future::poll_fn;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use actix_web::body::MessageBody;
+use actix_web::http::StatusCode;
+use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use chrono::{DateTime, TimeZone, Utc};
+use jsonwebtoken::{EncodingKey, Header};
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use crate::app_state::AppState;
+use crate::auth::{Claims, Role};
+use crate::config::Config;
+use crate::flight_events::{FlightEvent, FlightEvents};
+use crate::memory_repository::{MemoryFlight, MemoryRepository};
+use crate::metrics::Metrics;
+use crate::rebuild::{spawn_rebuild, RebuildJobs, RebuildKind, RebuildStatus};
+use crate::types::BookingClass;
+use crate::webhooks::{deliver_due, signature};
+
+const SECRET: &str = "secret";
+
+fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2017, 8, day, hour, minute, 0).unwrap()
+}
+
+fn flight(flight_id: i32, flight_no: &str, from: &str, to: &str, departure: DateTime<Utc>, arrival: DateTime<Utc>) -> MemoryFlight {
+    MemoryFlight {
+        flight_id,
+        flight_no: flight_no.to_string(),
+        departure_airport: from.to_string(),
+        arrival_airport: to.to_string(),
+        scheduled_departure: departure,
+        scheduled_arrival: arrival,
+        status: "Scheduled".to_string(),
+        aircraft_code: "321".to_string(),
+        estimated_departure: None,
+        estimated_arrival: None,
+        actual_departure: None,
+        actual_arrival: None,
+    }
+}
+
+/// Two Moscow airports, St. Petersburg and Kazan. The dataset clock stands at
+/// 15:00 on August 15, an hour before flight 3 departs.
+fn repository() -> MemoryRepository {
+    MemoryRepository::new(at(15, 15, 0))
+        .with_airport("SVO", ("Sheremetyevo", "Шереметьево"), ("Moscow", "Москва"), (55.9726, 37.4146))
+        .with_airport("DME", ("Domodedovo", "Домодедово"), ("Moscow", "Москва"), (55.4088, 37.9063))
+        .with_airport("LED", ("Pulkovo", "Пулково"), ("St. Petersburg", "Санкт-Петербург"), (59.8003, 30.2625))
+        .with_airport("KZN", ("Kazan", "Казань"), ("Kazan", "Казань"), (55.6062, 49.2787))
+        .with_aircraft("321", ("Airbus A321-200", "Аэробус A321-200"), 5600)
+        .with_flight(flight(1, "PG0001", "SVO", "LED", at(16, 6, 0), at(16, 7, 30)))
+        .with_flight(flight(2, "PG0002", "LED", "KZN", at(16, 10, 0), at(16, 12, 0)))
+        .with_flight(flight(3, "PG0003", "DME", "KZN", at(15, 16, 0), at(15, 17, 30)))
+        .with_seats("321", BookingClass::Economy, &["1A", "1B", "2A", "2B"])
+        .with_fare("PG0001", BookingClass::Economy, 1000)
+        .with_fare("PG0002", BookingClass::Economy, 2000)
+        .with_fare("PG0003", BookingClass::Economy, 1500)
+        .with_ticket("0005432000001", "IVAN IVANOV", 3, BookingClass::Economy)
+}
+
+fn app_state(repo: Arc<MemoryRepository>) -> web::Data<AppState> {
+    app_state_with_config(repo, Config::default())
+}
+
+fn app_state_with_config(repo: Arc<MemoryRepository>, mut cfg: Config) -> web::Data<AppState> {
+    cfg.auth.jwt_secret = SECRET.to_string();
+
+    // Only the handlers not ported to the repository use the pool, none of them is tested here
+    let pool = PgPoolOptions::new()
+        .connect_lazy("postgres://localhost:1/none")
+        .unwrap();
+
+    web::Data::new(AppState {
+        db_pool: pool,
+        repo,
+        cfg,
+        rebuild_jobs: RebuildJobs::default(),
+        metrics: Metrics::new().unwrap(),
+        flight_events: FlightEvents::default(),
+    })
+}
+
+fn token(role: Role) -> String {
+    jsonwebtoken::encode(
+        &Header::default(),
+        &Claims { sub: "test".to_string(), role, exp: usize::MAX },
+        &EncodingKey::from_secret(SECRET.as_bytes())
+    ).unwrap()
+}
+
+fn error_fields(body: &Value) -> Vec<&str> {
+    body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["field"].as_str().unwrap())
+        .collect()
+}
+
+#[actix_web::test]
+async fn lists_cities_and_their_airports() {
+    let app = init_service(App::new().app_data(app_state(Arc::new(repository()))).configure(crate::routes)).await;
+
+    let cities: Value = read_body_json(call_service(&app, TestRequest::get().uri("/api/cities").to_request()).await).await;
+    assert_eq!(cities, json!(["Kazan", "Moscow", "St. Petersburg"]));
+
+    let airports: Value = read_body_json(call_service(&app, TestRequest::get().uri("/api/city_airports/Moscow").to_request()).await).await;
+    assert_eq!(airports, json!([{"code": "DME", "name": "Domodedovo"}, {"code": "SVO", "name": "Sheremetyevo"}]));
+
+    let res = call_service(&app, TestRequest::get().uri("/api/city_airports/Paris").to_request()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["city"]);
+}
+
+#[actix_web::test]
+async fn names_follow_the_requested_language() {
+    let app = init_service(App::new().app_data(app_state(Arc::new(repository()))).configure(crate::routes)).await;
+
+    let req = TestRequest::get()
+        .uri("/api/cities")
+        .insert_header(("Accept-Language", "de-DE, ru;q=0.9, en;q=0.8"))
+        .to_request();
+
+    let cities: Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(cities, json!(["Казань", "Москва", "Санкт-Петербург"]));
+
+    // City names are accepted in any language, the parameter overrides the header
+    let req = TestRequest::get()
+        .uri("/api/city_airports/%D0%9C%D0%BE%D1%81%D0%BA%D0%B2%D0%B0?lang=en")
+        .insert_header(("Accept-Language", "ru"))
+        .to_request();
+
+    let airports: Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(airports, json!([{"code": "DME", "name": "Domodedovo"}, {"code": "SVO", "name": "Sheremetyevo"}]));
+
+    let res = call_service(&app, TestRequest::get().uri("/api/airports?lang=de").to_request()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["lang"]);
+
+    let req = TestRequest::get()
+        .uri("/api/admin/airports")
+        .insert_header(("Authorization", format!("Bearer {}", token(Role::Admin))))
+        .to_request();
+
+    let airports: Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(airports[0], json!({
+        "code": "DME",
+        "name": {"en": "Domodedovo", "ru": "Домодедово"},
+        "city": {"en": "Moscow", "ru": "Москва"},
+        "coordinates": {"latitude": 55.4088, "longitude": 37.9063},
+        "timezone": "Europe/Moscow",
+    }));
+}
+
+#[actix_web::test]
+async fn suggests_airports_by_code_city_and_name() {
+    let app = init_service(App::new().app_data(app_state(Arc::new(repository()))).configure(crate::routes)).await;
+
+    for (q, codes) in [
+        ("kzn", vec!["KZN"]),
+        ("Mos", vec!["DME", "SVO"]),
+        ("st%20pet", vec!["LED"]),
+        ("pulk", vec!["LED"]),
+        ("Moscwo", vec!["DME", "SVO"]),
+        ("Sheremetevo", vec!["SVO"]),
+        ("%D0%A8%D0%B5%D1%80", vec!["SVO"]),
+        ("d", vec!["DME"]),
+        ("xyz", vec![]),
+    ] {
+        let res = call_service(&app, TestRequest::get().uri(&format!("/api/airports/search?q={}", q)).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK, "{}", q);
+
+        let suggestions: Value = read_body_json(res).await;
+        let found: Vec<&str> = suggestions.as_array().unwrap().iter().map(|s| s["code"].as_str().unwrap()).collect();
+        assert_eq!(found, codes, "{}", q);
+    }
+
+    let req = TestRequest::get().uri("/api/airports/search?q=kaz&lang=ru").to_request();
+    let suggestions: Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(suggestions, json!([{
+        "code": "KZN",
+        "name": "Казань",
+        "city": "Казань",
+        "coordinates": {"latitude": 55.6062, "longitude": 49.2787},
+        "timezone": "Europe/Moscow",
+    }]));
+
+    let res = call_service(&app, TestRequest::get().uri("/api/airports/search?q=%20&limit=100").to_request()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["q", "limit"]);
+}
+
+#[actix_web::test]
+async fn finds_airports_near_a_point() {
+    let app = init_service(App::new().app_data(app_state(Arc::new(repository()))).configure(crate::routes)).await;
+
+    // Red Square: Sheremetyevo is 27.5 km away, Domodedovo 42 km and Pulkovo 626 km
+    let req = TestRequest::get().uri("/api/airports/nearby?latitude=55.7539&longitude=37.6208").to_request();
+    let airports: Value = read_body_json(call_service(&app, req).await).await;
+    let found: Vec<&str> = airports.as_array().unwrap().iter().map(|a| a["code"].as_str().unwrap()).collect();
+    assert_eq!(found, ["SVO", "DME"]);
+    assert_eq!(airports[0]["distance_km"], json!(27.5));
+
+    let req = TestRequest::get().uri("/api/airports/nearby?latitude=55.7539&longitude=37.6208&radius_km=30").to_request();
+    let airports: Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(airports.as_array().unwrap().len(), 1);
+
+    let res = call_service(&app, TestRequest::get().uri("/api/airports/nearby?latitude=91&longitude=0&radius_km=0").to_request()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["latitude", "radius_km"]);
+}
+
+#[actix_web::test]
+async fn schedules_reject_malformed_and_unknown_airports() {
+    let app = init_service(App::new().app_data(app_state(Arc::new(repository()))).configure(crate::routes)).await;
+
+    let routes: Value = read_body_json(call_service(&app, TestRequest::get().uri("/api/inbound/KZN").to_request()).await).await;
+    assert_eq!(routes.as_array().unwrap().len(), 2);
+
+    for uri in ["/api/inbound/kzn", "/api/outbound/ABC"] {
+        let res = call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        assert_eq!(error_fields(&read_body_json(res).await), ["airport_code"]);
+    }
+}
+
+#[actix_web::test]
+async fn finds_priced_connections_between_cities() {
+    let app = init_service(App::new().app_data(app_state(Arc::new(repository()))).configure(crate::routes)).await;
+
+    let req = TestRequest::get()
+        .uri("/api/route?source_type=City&source=Moscow&destination_type=Airport&destination=KZN\
+            &max_connections=1&connection_time_min=1&connection_time_max=6&departure_date=2017-08-16&booking_class=Economy")
+        .to_request();
+
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let routes: Value = read_body_json(res).await;
+    assert_eq!(routes.as_array().unwrap().len(), 1);
+    assert_eq!(routes[0]["flight_ids"], json!([1, 2]));
+    assert_eq!(routes[0]["path"], json!(["SVO", "LED", "KZN"]));
+    assert_eq!(routes[0]["total_price"], json!(3000));
+}
+
+#[actix_web::test]
+async fn searches_routes_from_airports_near_a_point() {
+    let app = init_service(App::new().app_data(app_state(Arc::new(repository()))).configure(crate::routes)).await;
+
+    let req = TestRequest::get()
+        .uri("/api/route?source_type=Coordinates&source=55.7539,37.6208,50&destination_type=Airport&destination=KZN\
+            &max_connections=1&connection_time_min=1&connection_time_max=6&departure_date=2017-08-16&booking_class=Economy")
+        .to_request();
+
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let routes: Value = read_body_json(res).await;
+    assert_eq!(routes.as_array().unwrap().len(), 1);
+    assert_eq!(routes[0]["path"], json!(["SVO", "LED", "KZN"]));
+
+    let req = TestRequest::get()
+        .uri("/api/route?source_type=Coordinates&source=55.7539,east&destination_type=Coordinates&destination=0,0\
+            &max_connections=1&connection_time_min=1&connection_time_max=6&departure_date=2017-08-16&booking_class=Economy")
+        .to_request();
+
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["source", "destination"]);
+}
+
+#[actix_web::test]
+async fn explores_destinations_by_arrival_duration_and_price() {
+    // A later direct flight to Kazan, shorter but dearer than the connection through St. Petersburg
+    let repo = repository()
+        .with_flight(flight(4, "PG0004", "DME", "KZN", at(16, 13, 0), at(16, 14, 0)))
+        .with_fare("PG0004", BookingClass::Economy, 4000);
+
+    let app = init_service(App::new().app_data(app_state(Arc::new(repo))).configure(crate::routes)).await;
+
+    let req = TestRequest::get()
+        .uri("/api/explore?source_type=City&source=Moscow&max_connections=1&connection_time_min=1&connection_time_max=6\
+            &departure_date=2017-08-16&booking_class=Economy&lang=ru")
+        .to_request();
+
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let destinations: Value = read_body_json(res).await;
+    let found: Vec<&str> = destinations.as_array().unwrap().iter().map(|d| d["airport_code"].as_str().unwrap()).collect();
+    assert_eq!(found, ["KZN", "LED"]);
+
+    let kazan = &destinations[0];
+    assert_eq!(kazan["city"], json!("Казань"));
+    assert_eq!(kazan["earliest_arrival"]["flight_ids"], json!([1, 2]));
+    assert_eq!(kazan["shortest"]["flight_ids"], json!([4]));
+    assert_eq!(kazan["shortest"]["duration_minutes"], json!(60));
+    assert_eq!(kazan["cheapest"]["flight_ids"], json!([1, 2]));
+    assert_eq!(kazan["cheapest"]["total_price"], json!(3000));
+
+    let res = call_service(&app, TestRequest::get().uri("/api/explore?source_type=City&source=Moscow&max_connections=1\
+        &connection_time_min=1&connection_time_max=6&departure_date=2017-08-16&booking_class=Economy&limit=1").to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let destinations: Value = read_body_json(res).await;
+    assert_eq!(destinations.as_array().unwrap().len(), 1);
+    assert_eq!(destinations[0]["airport_code"], json!("KZN"));
+
+    let res = call_service(&app, TestRequest::get().uri("/api/explore?source_type=City&source=Paris&max_connections=9\
+        &connection_time_min=1&connection_time_max=6&departure_date=2017-08-16&booking_class=Economy&limit=0").to_request()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["source", "max_connections", "limit"]);
+}
+
+#[actix_web::test]
+async fn route_search_reports_every_invalid_field() {
+    let app = init_service(App::new().app_data(app_state(Arc::new(repository()))).configure(crate::routes)).await;
+
+    let req = TestRequest::get()
+        .uri("/api/route?source_type=Airport&source=XXX&destination_type=City&destination=Paris\
+            &max_connections=9&connection_time_min=5&connection_time_max=2&departure_date=2017-09-30&booking_class=Economy")
+        .to_request();
+
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let body: Value = read_body_json(res).await;
+    assert_eq!(
+        error_fields(&body),
+        ["source", "destination", "max_connections", "connection_time_min", "departure_date"]
+    );
+}
+
+#[actix_web::test]
+async fn shows_flight_status_and_details() {
+    let delayed = MemoryFlight {
+        status: "Delayed".to_string(),
+        ..flight(4, "PG0004", "LED", "SVO", at(15, 14, 0), at(15, 15, 30))
+    };
+
+    let app = init_service(App::new().app_data(app_state(Arc::new(repository().with_flight(delayed)))).configure(crate::routes)).await;
+
+    let res = call_service(&app, TestRequest::get().uri("/api/flights/3?lang=ru").to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let flight: Value = read_body_json(res).await;
+    assert_eq!(flight["flight_no"], json!("PG0003"));
+    assert_eq!(flight["status"], json!("Scheduled"));
+    assert_eq!(flight["departure"]["city"], json!("Москва"));
+    assert_eq!(flight["departure"]["scheduled_local"], json!("2017-08-15T19:00:00"));
+    assert_eq!(flight["arrival"]["airport_code"], json!("KZN"));
+    assert_eq!(flight["aircraft"], json!({"code": "321", "model": "Аэробус A321-200", "range": 5600}));
+    assert_eq!(flight["seats_available"], json!([{"fare_conditions": "Economy", "seats": 3}]));
+    assert_eq!(flight["delay_minutes"], json!(null));
+
+    // Not departed an hour after schedule
+    let flight: Value = read_body_json(call_service(&app, TestRequest::get().uri("/api/flights/4").to_request()).await).await;
+    assert_eq!(flight["delay_minutes"], json!(60));
+
+    let res = call_service(&app, TestRequest::get().uri("/api/flights/99").to_request()).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let req = TestRequest::get().uri("/api/flights?flight_no=PG0001&date=2017-08-16").to_request();
+    let flights: Value = read_body_json(call_service(&app, req).await).await;
+    let found: Vec<i64> = flights.as_array().unwrap().iter().map(|f| f["flight_id"].as_i64().unwrap()).collect();
+    assert_eq!(found, [1]);
+
+    let res = call_service(&app, TestRequest::get().uri("/api/flights?flight_no=pg1&date=2017-08-16").to_request()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["flight_no"]);
+}
+
+#[actix_web::test]
+async fn shows_airport_boards_around_an_instant() {
+    let app = init_service(App::new().app_data(app_state(Arc::new(repository()))).configure(crate::routes)).await;
+
+    let board: Value = read_body_json(call_service(&app, TestRequest::get().uri("/api/airports/KZN/board?direction=Arrivals").to_request()).await).await;
+    assert_eq!(board, json!([{
+        "flight_id": 3,
+        "flight_no": "PG0003",
+        "status": "Scheduled",
+        "airport_code": "DME",
+        "airport_name": "Domodedovo",
+        "city": "Moscow",
+        "scheduled": "2017-08-15T17:30:00Z",
+        "scheduled_local": "2017-08-15T20:30:00",
+        "estimated": null,
+        "estimated_local": null,
+        "actual": null,
+        "actual_local": null,
+        "delay_minutes": null,
+    }]));
+
+    let req = TestRequest::get().uri("/api/airports/KZN/board?direction=Arrivals&at=2017-08-16T10:00:00Z&hours_after=3").to_request();
+    let board: Value = read_body_json(call_service(&app, req).await).await;
+    let found: Vec<&str> = board.as_array().unwrap().iter().map(|e| e["flight_no"].as_str().unwrap()).collect();
+    assert_eq!(found, ["PG0002"]);
+
+    let board: Value = read_body_json(call_service(&app, TestRequest::get().uri("/api/airports/SVO/board?direction=Departures").to_request()).await).await;
+    assert_eq!(board, json!([]));
+
+    let res = call_service(&app, TestRequest::get().uri("/api/airports/KZN/board?direction=Departures&hours_after=48").to_request()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["hours_after"]);
+}
+
+#[actix_web::test]
+async fn streams_events_of_subscribed_airports() {
+    let state = app_state(Arc::new(repository()));
+    let app = init_service(App::new().app_data(state.clone()).configure(crate::routes)).await;
+
+    let res = call_service(&app, TestRequest::get().uri("/api/flight_events?airports=KZN").to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("Content-Type").unwrap(), "text/event-stream");
+
+    let event = |flight_id: i32, from: &str, to: &str| FlightEvent {
+        flight_id,
+        flight_no: format!("PG000{}", flight_id),
+        departure_airport: from.to_string(),
+        arrival_airport: to.to_string(),
+        status: "Delayed".to_string(),
+        previous_status: "Scheduled".to_string(),
+        estimated_departure: None,
+        estimated_arrival: None,
+        actual_departure: None,
+        actual_arrival: None,
+        gate: Some("A1".to_string()),
+    };
+
+    state.flight_events.publish(event(1, "SVO", "LED"));
+    state.flight_events.publish(event(2, "LED", "KZN"));
+
+    let mut body = Box::pin(res.into_body());
+    let chunk = poll_fn(|cx| body.as_mut().poll_next(cx)).await.unwrap().unwrap();
+    let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+
+    let data: Value = serde_json::from_str(chunk.strip_prefix("event: flight\ndata: ").unwrap().trim_end()).unwrap();
+    assert_eq!(data["flight_id"], json!(2));
+    assert_eq!(data["gate"], json!("A1"));
+
+    for (uri, fields) in [
+        ("/api/flight_events", vec!["flight_ids"]),
+        ("/api/flight_events?flight_ids=1,x&airports=kzn", vec!["flight_ids", "airports"]),
+    ] {
+        let res = call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        assert_eq!(error_fields(&read_body_json(res).await), fields, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn operations_change_flight_status_in_order() {
+    let app = init_service(App::new().app_data(app_state(Arc::new(repository()))).configure(crate::routes)).await;
+
+    let change = |role, flight_id: i32, body: Value| TestRequest::post()
+        .uri(&format!("/api/ops/flights/{}/status", flight_id))
+        .insert_header(("Authorization", format!("Bearer {}", token(role))))
+        .set_json(body)
+        .to_request();
+
+    let res = call_service(&app, change(Role::Agent, 3, json!({"status": "On Time"}))).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = call_service(&app, change(Role::Operations, 9, json!({"status": "On Time"}))).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let res = call_service(&app, change(Role::Operations, 3, json!({"status": "Delayed", "actual_departure": at(15, 16, 40)}))).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["actual_departure", "estimated_departure"]);
+
+    // The arrival is shifted by the 45 minutes of delay
+    let res = call_service(&app, change(Role::Operations, 3, json!({"status": "Delayed", "estimated_departure": at(15, 16, 45)}))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let flight: Value = read_body_json(res).await;
+    assert_eq!(flight["status"], json!("Delayed"));
+    assert_eq!(flight["delay_minutes"], json!(45));
+    assert_eq!(flight["departure"]["estimated_local"], json!("2017-08-15T19:45:00"));
+    assert_eq!(flight["arrival"]["estimated"], json!("2017-08-15T18:15:00Z"));
+
+    let res = call_service(&app, change(Role::Operations, 3, json!({"status": "Arrived", "actual_arrival": at(15, 18, 20)}))).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    let res = call_service(&app, change(Role::Admin, 3, json!({"status": "Departed", "actual_departure": at(15, 16, 50)}))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = call_service(&app, change(Role::Operations, 3, json!({"status": "Arrived", "actual_arrival": at(15, 16, 30)}))).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["actual_arrival"]);
+
+    let res = call_service(&app, change(Role::Operations, 3, json!({"status": "Cancelled", "reason": "Weather"}))).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    // A scheduled flight may depart without being marked On Time or Delayed first
+    let res = call_service(&app, change(Role::Operations, 2, json!({"status": "Departed", "actual_departure": at(16, 10, 5)}))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = call_service(&app, change(Role::Operations, 1, json!({"status": "Cancelled"}))).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["reason"]);
+
+    let req = TestRequest::get()
+        .uri("/api/ops/flights/3/audit")
+        .insert_header(("Authorization", format!("Bearer {}", token(Role::Operations))))
+        .to_request();
+
+    let audit: Value = read_body_json(call_service(&app, req).await).await;
+    let changes: Vec<(&str, &str, &str)> = audit
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| (e["previous_status"].as_str().unwrap(), e["status"].as_str().unwrap(), e["changed_by"].as_str().unwrap()))
+        .collect();
+
+    assert_eq!(changes, [("Scheduled", "Delayed", "test"), ("Delayed", "Departed", "test")]);
+    assert_eq!(audit[1]["actual_departure"], json!("2017-08-15T16:50:00Z"));
+}
+
+#[actix_web::test]
+async fn rebooks_passengers_of_cancelled_flights() {
+    // Flight 6 arrives first but only has Business seats free, flight 5 has Economy ones
+    let repo = repository()
+        .with_flight(MemoryFlight { aircraft_code: "SU9".to_string(), ..flight(6, "PG0006", "DME", "KZN", at(15, 18, 0), at(15, 19, 30)) })
+        .with_flight(flight(5, "PG0005", "DME", "KZN", at(15, 19, 0), at(15, 20, 30)))
+        .with_seats("SU9", BookingClass::Economy, &["3A"])
+        .with_seats("SU9", BookingClass::Business, &["1A", "1B"])
+        .with_ticket("0005432000002", "PETR PETROV", 3, BookingClass::Economy)
+        .with_ticket("0005432000003", "MARIA IVANOVA", 3, BookingClass::Business);
+
+    let app = init_service(App::new().app_data(app_state(Arc::new(repo))).configure(crate::routes)).await;
+
+    let post = |uri: &str, body: Value| TestRequest::post()
+        .uri(uri)
+        .insert_header(("Authorization", format!("Bearer {}", token(Role::Operations))))
+        .set_json(body)
+        .to_request();
+
+    let res = call_service(&app, post("/api/ops/flights/3/reaccommodate", json!({}))).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    let res = call_service(&app, post("/api/ops/flights/3/status", json!({"status": "Cancelled", "reason": "Runway closed"}))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = call_service(&app, post("/api/ops/flights/3/reaccommodate", json!({}))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // Economy passengers are upgraded to the earlier flight, leaving no Business seat for the last one
+    let report: Value = read_body_json(res).await;
+    let rebooked: Vec<(&str, Value, &str)> = report["rebooked"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| (r["ticket_no"].as_str().unwrap(), r["flight_ids"].clone(), r["fare_conditions"].as_str().unwrap()))
+        .collect();
+
+    assert_eq!(rebooked, [("0005432000001", json!([6]), "Business"), ("0005432000002", json!([6]), "Business")]);
+    assert_eq!(report["rebooked"][0]["replaced_flight_ids"], json!([3]));
+    assert_eq!(report["unprotected"], json!([{
+        "ticket_no": "0005432000003",
+        "passenger_name": "MARIA IVANOVA",
+        "fare_conditions": "Business",
+        "origin": "DME",
+        "destination": "KZN",
+    }]));
+
+    let res = call_service(&app, post("/api/ops/flights/3/reaccommodate", json!({}))).await;
+    let report: Value = read_body_json(res).await;
+    assert_eq!(report["rebooked"], json!([]));
+    assert_eq!(report["unprotected"].as_array().unwrap().len(), 1);
+
+    let flight: Value = read_body_json(call_service(&app, TestRequest::get().uri("/api/flights/6").to_request()).await).await;
+    assert_eq!(flight["seats_available"], json!([{"fare_conditions": "Business", "seats": 0}, {"fare_conditions": "Economy", "seats": 1}]));
+}
+
+#[actix_web::test]
+async fn rebooks_cancelled_connections_after_the_previous_leg_lands() {
+    // The cancelled late connection of flight 7 is replaced by the next morning flight 2,
+    // as flight 9 arrives first but leaves before flight 7 has landed an hour
+    let repo = repository()
+        .with_flight(flight(7, "PG0007", "SVO", "LED", at(15, 20, 0), at(15, 22, 30)))
+        .with_flight(flight(8, "PG0008", "LED", "KZN", at(15, 23, 30), at(16, 1, 30)))
+        .with_flight(flight(9, "PG0009", "LED", "KZN", at(15, 22, 45), at(16, 0, 45)))
+        .with_ticket("0005432000004", "OLGA SIDOROVA", 7, BookingClass::Economy)
+        .with_ticket("0005432000004", "OLGA SIDOROVA", 8, BookingClass::Economy);
+
+    let app = init_service(App::new().app_data(app_state(Arc::new(repo))).configure(crate::routes)).await;
+
+    let post = |uri: &str, body: Value| TestRequest::post()
+        .uri(uri)
+        .insert_header(("Authorization", format!("Bearer {}", token(Role::Operations))))
+        .set_json(body)
+        .to_request();
+
+    let res = call_service(&app, post("/api/ops/flights/8/status", json!({"status": "Cancelled", "reason": "Crew shortage"}))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // Flights confirmed on time are still offered
+    let res = call_service(&app, post("/api/ops/flights/2/status", json!({"status": "On Time"}))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = call_service(&app, post("/api/ops/flights/8/reaccommodate", json!({}))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let report: Value = read_body_json(res).await;
+    assert_eq!(report["error"], Value::Null);
+    assert_eq!(report["unprotected"], json!([]));
+    assert_eq!(report["rebooked"][0]["ticket_no"], json!("0005432000004"));
+    assert_eq!(report["rebooked"][0]["replaced_flight_ids"], json!([8]));
+    assert_eq!(report["rebooked"][0]["flight_ids"], json!([2]));
+}
+
+#[actix_web::test]
+async fn books_a_quoted_itinerary() {
+    let repo = Arc::new(repository());
+    let app = init_service(App::new().app_data(app_state(repo.clone())).configure(crate::routes)).await;
+
+    let req = TestRequest::post()
+        .uri("/api/quote")
+        .set_json(json!({"flight_ids": [1, 2], "booking_class": "Economy", "passengers": 2}))
+        .to_request();
+
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let quote: Value = read_body_json(res).await;
+    assert_eq!(quote["price_per_passenger"], json!(3000));
+    assert_eq!(quote["total_price"], json!(6000));
+
+    let req = TestRequest::post()
+        .uri("/api/create_booking")
+        .set_json(json!({
+            "passenger_name": "IVAN PETROV",
+            "passenger_id": "4510 123456",
+            "flight_ids": [1, 2],
+            "fare_conditions": "Economy",
+            "quote_token": quote["quote_token"],
+        }))
+        .to_request();
+
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let booking: Value = read_body_json(res).await;
+    assert_eq!(booking["total_price"], json!(3000));
+    assert_eq!(repo.ticket_count(), 2);
+
+    // Quote and API tokens share the secret but not the audience
+    let req = TestRequest::get()
+        .uri("/api/admin/fares")
+        .insert_header(("Authorization", format!("Bearer {}", quote["quote_token"].as_str().unwrap())))
+        .to_request();
+    assert_eq!(call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    let req = TestRequest::post()
+        .uri("/api/create_booking")
+        .set_json(json!({
+            "passenger_name": "PETR IVANOV",
+            "passenger_id": "4510 654321",
+            "flight_ids": [1, 2],
+            "fare_conditions": "Economy",
+            "quote_token": token(Role::Admin),
+        }))
+        .to_request();
+
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["quote_token"]);
+}
+
+#[actix_web::test]
+async fn booking_rejects_invalid_passenger_and_itinerary() {
+    let repo = Arc::new(repository());
+    let app = init_service(App::new().app_data(app_state(repo.clone())).configure(crate::routes)).await;
+
+    let req = TestRequest::post()
+        .uri("/api/create_booking")
+        .set_json(json!({
+            "passenger_name": "Ivan",
+            "passenger_id": "123",
+            "flight_ids": [2, 1],
+            "fare_conditions": "Economy",
+        }))
+        .to_request();
+
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["passenger_name", "passenger_id", "flight_ids"]);
+    assert_eq!(repo.ticket_count(), 1);
+}
+
+#[actix_web::test]
+async fn checks_in_once_per_ticket() {
+    let app = init_service(App::new().app_data(app_state(Arc::new(repository()))).configure(crate::routes)).await;
+
+    let check_in = || TestRequest::post()
+        .uri("/api/check_in")
+        .set_json(json!({"ticket_no": "0005432000001", "flight_id": 3}))
+        .to_request();
+
+    let res = call_service(&app, check_in()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(read_body_json::<Value, _>(res).await, json!({"seat_no": "1A"}));
+
+    let res = call_service(&app, check_in()).await;
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let req = TestRequest::post()
+        .uri("/api/check_in")
+        .set_json(json!({"ticket_no": "0005432000001", "flight_id": 1}))
+        .to_request();
+
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["flight_id"]);
+}
+
+#[actix_web::test]
+async fn checks_in_only_within_the_configured_window() {
+    // Flight 1 leaves in 15 hours and flight 3 in an hour
+    let repo = repository().with_ticket("0005432000002", "PETR PETROV", 1, BookingClass::Economy);
+
+    let mut cfg = Config::default();
+    cfg.check_in.opens_hours_before = 12;
+    cfg.check_in.closes_minutes_before = 90;
+
+    let app = init_service(App::new().app_data(app_state_with_config(Arc::new(repo), cfg)).configure(crate::routes)).await;
+
+    for (ticket_no, flight_id) in [("0005432000002", 1), ("0005432000001", 3)] {
+        let req = TestRequest::post()
+            .uri("/api/check_in")
+            .set_json(json!({"ticket_no": ticket_no, "flight_id": flight_id}))
+            .to_request();
+
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(actix_web::test::read_body(res).await, "Check-in for this flight is not open");
+    }
+}
+
+#[actix_web::test]
+async fn fares_are_managed_by_admins_only() {
+    let app = init_service(App::new().app_data(app_state(Arc::new(repository()))).configure(crate::routes)).await;
+
+    let fare = json!({"flight_no": "PG0001", "fare_conditions": "Business", "amount": 5000});
+
+    let res = call_service(&app, TestRequest::post().uri("/api/admin/fares").set_json(&fare).to_request()).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let create = |role| TestRequest::post()
+        .uri("/api/admin/fares")
+        .insert_header(("Authorization", format!("Bearer {}", token(role))))
+        .set_json(&fare)
+        .to_request();
+
+    assert_eq!(call_service(&app, create(Role::Agent)).await.status(), StatusCode::FORBIDDEN);
+    assert!(call_service(&app, create(Role::Admin)).await.status().is_success());
+    assert_eq!(call_service(&app, create(Role::Admin)).await.status(), StatusCode::CONFLICT);
+
+    let req = TestRequest::get()
+        .uri("/api/admin/fares?flight_no=PG0001")
+        .insert_header(("Authorization", format!("Bearer {}", token(Role::Admin))))
+        .to_request();
+
+    let fares: Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(fares.as_array().unwrap().len(), 2);
+}
+
+#[actix_web::test]
+async fn panicking_rebuild_fails_its_job() {
+    let state = app_state(Arc::new(repository()));
+
+    let res = spawn_rebuild(state.clone(), RebuildKind::Prices, |state| async move {
+        assert!(state.rebuild_jobs.get(99).is_some(), "Unknown job");
+        Ok(())
+    });
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+    while state.rebuild_jobs.is_running() {
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    let job = state.rebuild_jobs.get(1).unwrap();
+    assert!(job.status == RebuildStatus::Failed);
+    assert!(job.error.unwrap().contains("panicked"));
+
+    let res = spawn_rebuild(state.clone(), RebuildKind::Seats, |_| async { Ok(()) });
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+}
+
+/// Webhook requests received: headers by name and body
+type Received = Mutex<Vec<(Vec<(String, String)>, String)>>;
+
+async fn receive_webhook(req: HttpRequest, body: String, received: web::Data<Received>) -> HttpResponse {
+    let headers = req.headers()
+        .iter()
+        .filter(|(name, _)| name.as_str().starts_with("x-webhook-"))
+        .map(|(name, value)| (name.to_string(), value.to_str().unwrap().to_string()))
+        .collect();
+
+    received.lock().unwrap().push((headers, body));
+    HttpResponse::NoContent().finish()
+}
+
+#[actix_web::test]
+async fn delivers_signed_webhooks_and_keeps_dead_letters() {
+    let received = web::Data::new(Received::default());
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let receiver_addr = listener.local_addr().unwrap();
+    let receiver_data = received.clone();
+
+    let receiver = HttpServer::new(move || {
+        App::new()
+            .app_data(receiver_data.clone())
+            .route("/ok", web::post().to(receive_webhook))
+            .route("/fail", web::post().to(|| async { HttpResponse::ServiceUnavailable().finish() }))
+    })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+
+    let receiver_handle = receiver.handle();
+    actix_web::rt::spawn(receiver);
+
+    let mut cfg = Config::default();
+    cfg.webhooks.max_attempts = 2;
+    cfg.webhooks.backoff_base_secs = 0;
+
+    let state = app_state_with_config(Arc::new(repository()), cfg);
+    let app = init_service(App::new().app_data(state.clone()).configure(crate::routes)).await;
+
+    let post = |uri: &str, body: Value| TestRequest::post()
+        .uri(uri)
+        .insert_header(("Authorization", format!("Bearer {}", token(Role::Admin))))
+        .set_json(body)
+        .to_request();
+
+    let res = call_service(&app, post("/api/admin/webhooks", json!({"url": "ftp://example.com", "secret": "short"}))).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_fields(&read_body_json(res).await), ["url", "secret"]);
+
+    let secret = "0123456789abcdef";
+
+    let res = call_service(&app, post("/api/admin/webhooks", json!({
+        "url": format!("http://{}/ok", receiver_addr),
+        "secret": secret,
+        "event_types": ["passenger.checked_in"],
+    }))).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let webhook: Value = read_body_json(res).await;
+    assert_eq!(webhook["event_types"], json!(["passenger.checked_in"]));
+    assert!(webhook.get("secret").is_none());
+
+    let res = call_service(&app, post("/api/admin/webhooks", json!({"url": format!("http://{}/fail", receiver_addr), "secret": secret}))).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let req = TestRequest::post()
+        .uri("/api/check_in")
+        .set_json(json!({"ticket_no": "0005432000001", "flight_id": 3}))
+        .to_request();
+    assert_eq!(call_service(&app, req).await.status(), StatusCode::OK);
+
+    let client = reqwest::Client::new();
+
+    assert_eq!(deliver_due(&client, &state).await.unwrap(), 2);
+
+    let (headers, body) = received.lock().unwrap().pop().unwrap();
+    let header = |name: &str| headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone()).unwrap();
+
+    assert_eq!(header("x-webhook-event"), "passenger.checked_in");
+    let timestamp: i64 = header("x-webhook-timestamp").parse().unwrap();
+    assert_eq!(header("x-webhook-signature"), signature(secret, timestamp, body.as_bytes()));
+
+    let body: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["event_type"], json!("passenger.checked_in"));
+    assert_eq!(body["data"], json!({"ticket_no": "0005432000001", "flight_id": 3, "boarding_no": 1, "seat_no": "1A"}));
+
+    // The failing endpoint is retried once, then given up on
+    assert_eq!(deliver_due(&client, &state).await.unwrap(), 1);
+    assert_eq!(deliver_due(&client, &state).await.unwrap(), 0);
+
+    let req = TestRequest::get()
+        .uri("/api/admin/webhooks/dead_letters")
+        .insert_header(("Authorization", format!("Bearer {}", token(Role::Admin))))
+        .to_request();
+
+    let dead_letters: Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(dead_letters.as_array().unwrap().len(), 1);
+    assert_eq!(dead_letters[0]["attempts"], json!(2));
+    assert_eq!(dead_letters[0]["last_error"], json!("Endpoint answered 503 Service Unavailable"));
+
+    let delivery_id = dead_letters[0]["delivery_id"].as_i64().unwrap();
+
+    let res = call_service(&app, post(&format!("/api/admin/webhooks/dead_letters/{}/retry", delivery_id), json!({}))).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+    let res = call_service(&app, post(&format!("/api/admin/webhooks/dead_letters/{}/retry", delivery_id), json!({}))).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    assert_eq!(deliver_due(&client, &state).await.unwrap(), 1);
+    assert!(received.lock().unwrap().is_empty());
+
+    receiver_handle.stop(true).await;
+}
@@ -1,22 +1,32 @@
+use std::time::Instant;
 use actix_web::{HttpResponse, Responder, web};
-use chrono::{NaiveDate, NaiveTime};
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
 use crate::app_state::AppState;
 use crate::booking::create_booking_entries;
 use crate::check_in::check_in_passanger;
-use crate::find_flights::find_flights;
+use crate::find_flights::FlightRecord;
+use crate::pricing::{quote_flights, FlightQuote};
+use crate::quote::verify_quote_token;
+use crate::lang::{Lang, LangParam};
+use crate::nearby::{airports_within, Area};
+use crate::repository::{Airport, AirportDetails, FlightSearch, InboundRoute, OutboundRoute, Repository, RepositoryError, RepositoryResult};
 use crate::types::{AirportCode, BookingClass, LocationType};
+use crate::validation::{check_airport_exists, check_city_exists, check_departure_date, check_iata_code, check_itinerary, check_passenger_id, check_passenger_name, check_search_limits, check_ticket_no, validate, AirportCodeParam, CityParam, Validate, ValidationErrors};
 
-pub async fn list_cities(state: web::Data<AppState>) -> impl Responder {
-    match sqlx::query!("SELECT DISTINCT city FROM airports")
-        .fetch_all(&state.db_pool)
-        .await
-    {
+#[utoipa::path(
+    get, path = "/api/cities", tag = "locations",
+    params(LangParam, ("Accept-Language" = Option<String>, Header)),
+    responses(
+        (status = 200, description = "Names of all cities with an airport", body = Vec<String>),
+        (status = 400, description = "Unsupported language", body = ValidationErrors)
+    )
+)]
+pub async fn list_cities(lang: Lang, state: web::Data<AppState>) -> impl Responder {
+    match state.repo.cities(lang).await {
         Ok(result) => {
-            HttpResponse::Ok().json(
-                result.into_iter().map(|x| x.city.unwrap()).collect::<Vec<String>>()
-            )
+            HttpResponse::Ok().json(result)
         }
         Err(_) => {
             HttpResponse::InternalServerError().json("")
@@ -24,20 +34,16 @@ pub async fn list_cities(state: web::Data<AppState>) -> impl Responder {
     }
 }
 
-#[derive(Serialize)]
-struct Airport {
-    code: Option<String>,
-    name: Option<String>
-}
-
-pub async fn list_all_airports(state: web::Data<AppState>) -> impl Responder {
-    match sqlx::query_as!(
-        Airport,
-        "SELECT airport_name as name, airport_code as code FROM airports"
+#[utoipa::path(
+    get, path = "/api/airports", tag = "locations",
+    params(LangParam, ("Accept-Language" = Option<String>, Header)),
+    responses(
+        (status = 200, body = Vec<Airport>),
+        (status = 400, description = "Unsupported language", body = ValidationErrors)
     )
-        .fetch_all(&state.db_pool)
-        .await
-    {
+)]
+pub async fn list_all_airports(lang: Lang, state: web::Data<AppState>) -> impl Responder {
+    match state.repo.airports(lang).await {
         Ok(result) => {
             HttpResponse::Ok().json(result)
         }
@@ -47,57 +53,63 @@ pub async fn list_all_airports(state: web::Data<AppState>) -> impl Responder {
     }
 }
 
-async fn get_airports_within_city(city: &str, pool: &PgPool) -> Vec<Airport> {
-    sqlx::query_as!(
-        Airport,
-        "SELECT airport_name as name, airport_code as code FROM airports WHERE city=$1",
-        city
+#[utoipa::path(
+    get, path = "/api/city_airports/{city}", tag = "locations",
+    params(
+        ("city" = String, Path, description = "City name in any language"),
+        LangParam,
+        ("Accept-Language" = Option<String>, Header)
+    ),
+    responses(
+        (status = 200, body = Vec<Airport>),
+        (status = 400, description = "Invalid fields", body = ValidationErrors)
     )
-        .fetch_all(pool)
-        .await
-        .unwrap()
-}
-
-pub async fn list_airports_within_city(path: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
-    HttpResponse::Ok().json(
-        get_airports_within_city(path.as_str(), &state.db_pool).await
-    )
-}
+)]
+pub async fn list_airports_within_city(path: web::Path<String>, lang: Lang, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = validate(&CityParam(path.as_str()), &state).await {
+        return response;
+    }
 
-#[derive(Serialize)]
-struct InboundRoute {
-    flight_no: Option<String>,
-    arrival_time: Option<NaiveTime>,
-    origin: Option<String>,
-    days_of_week: Option<Vec<i32>>
+    match state.repo.airports_in_city(path.as_str(), lang).await {
+        Ok(result) => {
+            HttpResponse::Ok().json(result)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().json("")
+        }
+    }
 }
 
-#[derive(Serialize)]
-struct OutboundRoute {
-    flight_no: Option<String>,
-    departure_time: Option<NaiveTime>,
-    destination: Option<String>,
-    days_of_week: Option<Vec<i32>>
+#[utoipa::path(
+    get, path = "/api/admin/airports", tag = "admin",
+    security(("bearer" = [])),
+    responses((status = 200, description = "Airports with names in every language", body = Vec<AirportDetails>))
+)]
+pub async fn list_airport_details(state: web::Data<AppState>) -> impl Responder {
+    match state.repo.airport_details().await {
+        Ok(result) => {
+            HttpResponse::Ok().json(result)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
 }
 
-pub async fn inbound_schedule(path: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
-    match sqlx::query_as!(
-        InboundRoute,
-        "
-        SELECT DISTINCT
-            flights_v.flight_no AS flight_no,
-            days_of_week,
-            scheduled_arrival::TIME AS arrival_time,
-            flights_v.departure_airport AS origin
-        FROM flights_v
-        JOIN routes ON flights_v.flight_no = routes.flight_no
-        WHERE flights_v.arrival_airport=$1
-        ",
-        path.as_str()
+#[utoipa::path(
+    get, path = "/api/inbound/{airport_code}", tag = "locations",
+    params(("airport_code" = String, Path)),
+    responses(
+        (status = 200, description = "Weekly schedule of flights arriving at the airport", body = Vec<InboundRoute>),
+        (status = 400, description = "Invalid fields", body = ValidationErrors)
     )
-        .fetch_all(&state.db_pool)
-        .await
-    {
+)]
+pub async fn inbound_schedule(path: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = validate(&AirportCodeParam(path.as_str()), &state).await {
+        return response;
+    }
+
+    match state.repo.inbound_schedule(path.as_str()).await {
         Ok(result) => {
             HttpResponse::Ok().json(result)
         }
@@ -106,24 +118,20 @@ pub async fn inbound_schedule(path: web::Path<String>, state: web::Data<AppState
         }
     }
 }
-pub async fn outbound_schedule(path: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
-    match sqlx::query_as!(
-        OutboundRoute,
-        "
-        SELECT DISTINCT
-            flights_v.flight_no AS flight_no,
-            days_of_week,
-            scheduled_departure::TIME AS departure_time,
-            flights_v.arrival_airport AS destination
-        FROM flights_v
-        JOIN routes ON flights_v.flight_no = routes.flight_no
-        WHERE flights_v.departure_airport=$1
-        ",
-        path.as_str()
+#[utoipa::path(
+    get, path = "/api/outbound/{airport_code}", tag = "locations",
+    params(("airport_code" = String, Path)),
+    responses(
+        (status = 200, description = "Weekly schedule of flights departing from the airport", body = Vec<OutboundRoute>),
+        (status = 400, description = "Invalid fields", body = ValidationErrors)
     )
-        .fetch_all(&state.db_pool)
-        .await
-    {
+)]
+pub async fn outbound_schedule(path: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = validate(&AirportCodeParam(path.as_str()), &state).await {
+        return response;
+    }
+
+    match state.repo.outbound_schedule(path.as_str()).await {
         Ok(result) => {
             HttpResponse::Ok().json(result)
         }
@@ -133,7 +141,8 @@ pub async fn outbound_schedule(path: web::Path<String>, state: web::Data<AppStat
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ListRoutesParameters {
     source_type: LocationType,
     source: String,
@@ -151,58 +160,211 @@ pub struct ListRoutesParameters {
     booking_class: BookingClass
 }
 
-async fn convert_location_to_airport_codes(location_type: LocationType, location: String, pool: &PgPool) -> Vec<AirportCode> {
+pub async fn validate_location(errors: &mut ValidationErrors, field: &str, location_type: &LocationType, location: &str, repo: &dyn Repository) -> RepositoryResult<()> {
+    match location_type {
+        LocationType::CITY => {
+            check_city_exists(errors, field, location, repo).await
+        }
+        LocationType::AIRPORT => {
+            check_iata_code(errors, field, location);
+
+            if errors.has(field) {
+                return Ok(());
+            }
+
+            check_airport_exists(errors, field, location, repo).await
+        }
+        LocationType::COORDINATES => {
+            let area = match location.parse::<Area>() {
+                Ok(area) => { area }
+                Err(e) => {
+                    errors.add(field, e);
+                    return Ok(());
+                }
+            };
+
+            area.check(errors, field);
+
+            if errors.has(field) {
+                return Ok(());
+            }
+
+            if airports_within(&area, repo).await?.is_empty() {
+                errors.add(field, format!("No airports within {} km of {}", area.radius_km, location));
+            }
+
+            Ok(())
+        }
+    }
+}
+
+impl Validate for ListRoutesParameters {
+    async fn validate(&self, state: &AppState, errors: &mut ValidationErrors) -> RepositoryResult<()> {
+        validate_location(errors, "source", &self.source_type, &self.source, &*state.repo).await?;
+        validate_location(errors, "destination", &self.destination_type, &self.destination, &*state.repo).await?;
+
+        if self.source == self.destination {
+            errors.add("destination", "Must differ from source");
+        }
+
+        check_search_limits(errors, &state.cfg.search, self.max_connections, self.connection_time_min, self.connection_time_max);
+
+        check_departure_date(errors, "departure_date", self.departure_date, &*state.repo).await
+    }
+}
+
+/// A found itinerary priced by the pricing engine. Prices are absent when
+/// some leg has no fare in the requested class.
+#[derive(Serialize, ToSchema)]
+struct RouteOption {
+    #[serde(flatten)]
+    flight: FlightRecord,
+    total_price: Option<i32>,
+    prices: Option<Vec<FlightQuote>>,
+}
+
+pub async fn convert_location_to_airport_codes(location_type: LocationType, location: String, repo: &dyn Repository) -> RepositoryResult<Vec<AirportCode>> {
     match location_type {
         LocationType::CITY => {
-            get_airports_within_city(location.as_str(), pool)
-                .await
-                .into_iter()
-                .map(|x| x.code.unwrap())
-                .collect()
+            Ok(
+                repo.airports_in_city(location.as_str(), Lang::default())
+                    .await?
+                    .into_iter()
+                    .filter_map(|x| x.code)
+                    .collect()
+            )
         }
         LocationType::AIRPORT => {
-            vec![location]
+            Ok(vec![location])
+        }
+        LocationType::COORDINATES => {
+            // Malformed areas are rejected by validation
+            let Ok(area) = location.parse::<Area>() else { return Ok(vec![]); };
+
+            Ok(
+                airports_within(&area, repo)
+                    .await?
+                    .into_iter()
+                    .map(|(_, airport)| airport.code)
+                    .collect()
+            )
         }
     }
 }
 
+#[utoipa::path(
+    get, path = "/api/route", tag = "search",
+    params(ListRoutesParameters),
+    responses(
+        (status = 200, body = Vec<RouteOption>),
+        (status = 400, description = "Invalid fields", body = ValidationErrors)
+    )
+)]
 pub async fn list_routes(parameters: web::Query<ListRoutesParameters>, state: web::Data<AppState>) -> impl Responder {
-    let source_airports = convert_location_to_airport_codes(
-        parameters.source_type.clone(),
-        parameters.source.clone(),
-        &state.db_pool
-    ).await;
-
-    let destination_airports = convert_location_to_airport_codes(
-        parameters.destination_type.clone(),
-        parameters.destination.clone(),
-        &state.db_pool
-    ).await;
-    
-    let flights = find_flights(
-        source_airports,
-        destination_airports,
-        parameters.departure_date,
-        parameters.max_connections as i32,
-        parameters.connection_time_min as i32,
-        parameters.connection_time_max as i32,
-        parameters.booking_class,
-        &state.db_pool
-    ).await.unwrap();
-    
-    HttpResponse::Ok().json(flights)
+    if let Err(response) = validate(&*parameters, &state).await {
+        return response;
+    }
+
+    let airports = async {
+        let source_airports = convert_location_to_airport_codes(
+            parameters.source_type.clone(),
+            parameters.source.clone(),
+            &*state.repo
+        ).await?;
+
+        let destination_airports = convert_location_to_airport_codes(
+            parameters.destination_type.clone(),
+            parameters.destination.clone(),
+            &*state.repo
+        ).await?;
+
+        Ok::<_, RepositoryError>((source_airports, destination_airports))
+    }.await;
+
+    let (source_airports, destination_airports) = match airports {
+        Ok(a) => { a }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    let timer = Instant::now();
+
+    let search = FlightSearch {
+        sources: source_airports,
+        destinations: Some(destination_airports),
+        departure_date: parameters.departure_date,
+        max_connections: parameters.max_connections as i32,
+        connection_time_min: parameters.connection_time_min as i32,
+        connection_time_max: parameters.connection_time_max as i32,
+        booking_class: parameters.booking_class,
+    };
+
+    let flights = match state.repo.find_flights(&search).await {
+        Ok(f) => { f }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    state.metrics.route_search_duration.observe(timer.elapsed().as_secs_f64());
+    state.metrics.route_search_results.observe(flights.len() as f64);
+
+    let mut flight_ids: Vec<i32> = flights
+        .iter()
+        .flat_map(|x| x.flight_ids.clone().unwrap_or_default())
+        .collect();
+
+    flight_ids.sort();
+    flight_ids.dedup();
+
+    let quotes = match quote_flights(flight_ids.as_slice(), parameters.booking_class, &state.cfg.pricing, &*state.repo).await {
+        Ok(q) => { q }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    let routes: Vec<RouteOption> = flights
+        .into_iter()
+        .map(|flight| {
+            let prices: Option<Vec<FlightQuote>> = flight.flight_ids
+                .iter()
+                .flatten()
+                .map(|id| quotes.get(id).cloned())
+                .collect();
+
+            RouteOption {
+                total_price: prices.as_ref().map(|p| p.iter().map(|q| q.amount).sum()),
+                prices,
+                flight,
+            }
+        })
+        .collect();
+
+    HttpResponse::Ok().json(routes)
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct CreateBookingParameters {
     pub passenger_name: String,
     pub passenger_id: String,
 
     pub flight_ids: Vec<i32>,
-    pub fare_conditions: BookingClass
+    pub fare_conditions: BookingClass,
+
+    pub quote_token: Option<String>
 }
 
-#[derive(Serialize, Deserialize)]
+impl Validate for CreateBookingParameters {
+    async fn validate(&self, state: &AppState, errors: &mut ValidationErrors) -> RepositoryResult<()> {
+        check_passenger_name(errors, "passenger_name", &self.passenger_name);
+        check_passenger_id(errors, "passenger_id", &self.passenger_id);
+        check_itinerary(errors, "flight_ids", &self.flight_ids, &*state.repo).await
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct CreateBookingResult {
     pub booking_id: String,
     pub ticker_no: String,
@@ -210,15 +372,37 @@ pub struct CreateBookingResult {
     pub total_price: i32,
 }
 
+#[utoipa::path(
+    post, path = "/api/create_booking", tag = "booking",
+    request_body = CreateBookingParameters,
+    responses(
+        (status = 200, body = CreateBookingResult),
+        (status = 400, description = "Invalid fields, or the quote token is invalid, expired or issued for other flights", body = ValidationErrors),
+        (status = 500, description = "No fare or no free seats on some flight", body = String, content_type = "text/plain")
+    )
+)]
 pub async fn create_booking(parameters: web::Json<CreateBookingParameters>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = validate(&*parameters, &state).await {
+        return response;
+    }
 
-    let pool = &state.db_pool;
+    let locked_quotes = match &parameters.quote_token {
+        Some(token) => {
+            match verify_quote_token(token, &state.cfg.auth.jwt_secret, &parameters.flight_ids, parameters.fare_conditions) {
+                Ok(q) => { Some(q) }
+                Err(e) => {
+                    let mut errors = ValidationErrors::default();
+                    errors.add("quote_token", e.to_string());
+                    return HttpResponse::BadRequest().json(errors);
+                }
+            }
+        }
+        None => { None }
+    };
 
-    let mut transaction = pool.begin().await.unwrap();
-    
-    let result = match create_booking_entries(parameters.into_inner(), &mut transaction).await {
+    let result = match create_booking_entries(parameters.into_inner(), locked_quotes, &state.cfg.pricing, &*state.repo).await {
         Ok(r) => {
-            transaction.commit().await.unwrap();
+            state.metrics.bookings_created.inc();
             r
         }
         Err(e) => {
@@ -229,22 +413,52 @@ pub async fn create_booking(parameters: web::Json<CreateBookingParameters>, stat
     HttpResponse::Ok().json(result)
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct CheckInParameters {
     ticket_no: String,
     flight_id: i32
 }
 
-#[derive(Serialize, Deserialize)]
+impl Validate for CheckInParameters {
+    async fn validate(&self, state: &AppState, errors: &mut ValidationErrors) -> RepositoryResult<()> {
+        check_ticket_no(errors, "ticket_no", &self.ticket_no);
+
+        if errors.has("ticket_no") {
+            return Ok(());
+        }
+
+        if state.repo.ticket_fare_conditions(&self.ticket_no, self.flight_id).await?.is_none() {
+            errors.add("flight_id", "Ticket is not booked on this flight");
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct CheckInResult {
     seat_no: String
 }
 
+#[utoipa::path(
+    post, path = "/api/check_in", tag = "booking",
+    request_body = CheckInParameters,
+    responses(
+        (status = 200, body = CheckInResult),
+        (status = 400, description = "Invalid fields", body = ValidationErrors),
+        (status = 500, description = "Check-in is closed, passenger is already checked in or the flight is full", body = String, content_type = "text/plain")
+    )
+)]
 pub async fn check_in(parameters: web::Json<CheckInParameters>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = validate(&*parameters, &state).await {
+        return response;
+    }
+
     let place = match check_in_passanger(
         parameters.ticket_no.clone(),
         parameters.flight_id,
-        &state.db_pool
+        &state.cfg.check_in,
+        &*state.repo
     )
         .await
     {
@@ -254,6 +468,8 @@ pub async fn check_in(parameters: web::Json<CheckInParameters>, state: web::Data
         }
     };
 
+    state.metrics.check_ins_created.inc();
+
     HttpResponse::Ok().json(CheckInResult {
         seat_no: place,
     })
@@ -0,0 +1,143 @@
+use actix_web::{HttpResponse, Responder, web};
+use serde::Serialize;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use crate::app_state::AppState;
+
+/// Tables and views the handlers query, including the derived ones built by rebuild jobs
+const REQUIRED_RELATIONS: [&str; 9] = [
+    "flights_v",
+    "airports",
+    "routes",
+    "prices",
+    "seats_comfort",
+    "pricing_rules",
+    "ticket_flight_pricing",
+    "cabin_layouts",
+    "seats_layout_v",
+];
+
+const REQUIRED_FUNCTIONS: [&str; 3] = [
+    "free_seats",
+    "occupied_seats",
+    "aircraft_type",
+];
+
+#[derive(Serialize, ToSchema)]
+struct Readiness {
+    ready: bool,
+    database: bool,
+    rebuild_running: bool,
+    missing_relations: Vec<String>,
+    missing_functions: Vec<String>,
+    error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct Version {
+    version: &'static str,
+    git_sha: &'static str,
+    schema_version: Option<i32>,
+}
+
+#[utoipa::path(
+    get, path = "/healthz", tag = "health",
+    responses((status = 200, description = "Process is alive", body = String))
+)]
+pub async fn healthz() -> impl Responder {
+    HttpResponse::Ok().json("Ok")
+}
+
+async fn missing_objects(pool: &PgPool) -> Result<(Vec<String>, Vec<String>), sqlx::Error> {
+    let missing_relations = sqlx::query!(
+        "
+        SELECT name AS \"name!\"
+        FROM UNNEST($1::TEXT[]) AS name
+        WHERE to_regclass(name) IS NULL
+        ",
+        &REQUIRED_RELATIONS.map(String::from)
+    )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|x| x.name)
+        .collect();
+
+    let missing_functions = sqlx::query!(
+        "
+        SELECT name AS \"name!\"
+        FROM UNNEST($1::TEXT[]) AS name
+        WHERE NOT EXISTS(
+            SELECT 1 FROM pg_proc
+            WHERE proname = name AND pg_function_is_visible(oid)
+        )
+        ",
+        &REQUIRED_FUNCTIONS.map(String::from)
+    )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|x| x.name)
+        .collect();
+
+    Ok((missing_relations, missing_functions))
+}
+
+/// Ready when the database answers, the schema is complete and no rebuild is
+/// swapping derived tables.
+#[utoipa::path(
+    get, path = "/readyz", tag = "health",
+    responses(
+        (status = 200, body = Readiness),
+        (status = 503, body = Readiness)
+    )
+)]
+pub async fn readyz(state: web::Data<AppState>) -> impl Responder {
+    let rebuild_running = state.rebuild_jobs.is_running();
+
+    let mut readiness = Readiness {
+        ready: false,
+        database: false,
+        rebuild_running,
+        missing_relations: vec![],
+        missing_functions: vec![],
+        error: None,
+    };
+
+    match missing_objects(&state.db_pool).await {
+        Ok((missing_relations, missing_functions)) => {
+            readiness.database = true;
+            readiness.ready = !rebuild_running && missing_relations.is_empty() && missing_functions.is_empty();
+            readiness.missing_relations = missing_relations;
+            readiness.missing_functions = missing_functions;
+        }
+        Err(e) => {
+            readiness.error = Some(e.to_string());
+        }
+    }
+
+    if readiness.ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+#[utoipa::path(
+    get, path = "/version", tag = "health",
+    responses((status = 200, body = Version))
+)]
+pub async fn version(state: web::Data<AppState>) -> impl Responder {
+    // The migrations table is missing until sql/5 is applied
+    let schema_version = sqlx::query!("SELECT max(version) AS version FROM schema_migrations")
+        .fetch_one(&state.db_pool)
+        .await
+        .ok()
+        .and_then(|x| x.version);
+
+    HttpResponse::Ok().json(Version {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("GIT_SHA"),
+        schema_version,
+    })
+}
@@ -0,0 +1,748 @@
+use std::future::poll_fn;
+use std::net::TcpListener;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use actix_web::body::MessageBody;
+use actix_web::http::StatusCode;
+use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{EncodingKey, Header};
+use serde_json::{json, Value};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::types::Decimal;
+use sqlx::{ConnectOptions, Connection, Executor, PgConnection, PgPool};
+use crate::app_state::AppState;
+use crate::auth::{Claims, Role};
+use crate::config::Config;
+use crate::flight_events::FlightEvents;
+use crate::metrics::Metrics;
+use crate::pg_repository::PgRepository;
+use crate::rebuild::RebuildJobs;
+use crate::webhooks::deliver_due;
+
+const SECRET: &str = "secret";
+
+const SCHEMA: &str = include_str!("../test/fixtures/bookings_schema.sql");
+const DATASET: &str = include_str!("../test/fixtures/synthetic_dataset.sql");
+const SEARCH_FUNCTIONS: &str = include_str!("../test/fixtures/search_functions.sql");
+
+/// Database created for a single test from the synthetic dataset and the
+/// scripts in `sql/`. `TEST_DATABASE_URL` must point to a server where the
+/// user may create databases, e.g. `postgres://postgres@localhost/postgres`.
+struct TestDatabase {
+    admin: PgConnectOptions,
+    name: String,
+    pool: PgPool,
+}
+
+impl TestDatabase {
+    /// Returns `None`, and the test passes vacuously, when no server is configured
+    async fn create() -> Option<TestDatabase> {
+        let url = match std::env::var("TEST_DATABASE_URL") {
+            Ok(url) => { url }
+            Err(_) => {
+                eprintln!("TEST_DATABASE_URL is not set, skipping the integration test");
+                return None;
+            }
+        };
+
+        let admin = PgConnectOptions::from_str(&url).unwrap().disable_statement_logging();
+        let name = format!("dp_flights_test_{}", uuid::Uuid::new_v4().simple());
+
+        let mut conn = PgConnection::connect_with(&admin).await.unwrap();
+        conn.execute(format!("CREATE DATABASE {}", name).as_str()).await.unwrap();
+        conn.execute(format!("ALTER DATABASE {} SET search_path = bookings, public", name).as_str()).await.unwrap();
+        conn.execute(format!("ALTER DATABASE {} SET bookings.lang = en", name).as_str()).await.unwrap();
+        conn.close().await.unwrap();
+
+        let options = admin.clone().database(&name);
+
+        let mut conn = PgConnection::connect_with(&options).await.unwrap();
+
+        conn.execute(SCHEMA).await.unwrap();
+        conn.execute(DATASET).await.unwrap();
+
+        for (path, script) in migrations() {
+            conn.execute(script.as_str()).await.unwrap_or_else(|e| panic!("{}: {}", path, e));
+        }
+
+        conn.execute(SEARCH_FUNCTIONS).await.unwrap();
+        conn.close().await.unwrap();
+
+        let pool = PgPoolOptions::new().connect_with(options).await.unwrap();
+
+        Some(TestDatabase { admin, name, pool })
+    }
+
+    async fn drop(self) {
+        self.pool.close().await;
+
+        let mut conn = PgConnection::connect_with(&self.admin).await.unwrap();
+        conn.execute(format!("DROP DATABASE {} WITH (FORCE)", self.name).as_str()).await.unwrap();
+    }
+}
+
+/// Numbered scripts of `sql/`, in the order they are applied in production
+fn migrations() -> Vec<(String, String)> {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/sql");
+
+    let mut scripts: Vec<(u32, String)> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?;
+            let version = name.split_once('_')?.0.parse().ok()?;
+            Some((version, path.to_string_lossy().to_string()))
+        })
+        .collect();
+
+    scripts.sort();
+
+    scripts
+        .into_iter()
+        .map(|(_, path)| {
+            let script = std::fs::read_to_string(&path).unwrap();
+            (path, script)
+        })
+        .collect()
+}
+
+fn app_state(pool: &PgPool) -> web::Data<AppState> {
+    let mut cfg = Config::default();
+    cfg.auth.jwt_secret = SECRET.to_string();
+
+    web::Data::new(AppState {
+        db_pool: pool.clone(),
+        repo: Arc::new(PgRepository::new(pool.clone())),
+        cfg,
+        rebuild_jobs: RebuildJobs::default(),
+        metrics: Metrics::new().unwrap(),
+        flight_events: FlightEvents::default(),
+    })
+}
+
+fn admin_token() -> String {
+    jsonwebtoken::encode(
+        &Header::default(),
+        &Claims { sub: "test".to_string(), role: Role::Admin, exp: usize::MAX },
+        &EncodingKey::from_secret(SECRET.as_bytes())
+    ).unwrap()
+}
+
+async fn flight_id(pool: &PgPool, flight_no: &str, departure_date: &str) -> i32 {
+    sqlx::query_scalar("SELECT flight_id FROM flights WHERE flight_no = $1 AND (scheduled_departure AT TIME ZONE 'Europe/Moscow')::DATE = $2::DATE")
+        .bind(flight_no)
+        .bind(departure_date)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+fn route_uri(departure_date: &str) -> String {
+    format!(
+        "/api/route?source_type=City&source=Moscow&destination_type=Airport&destination=KZN\
+        &max_connections=1&connection_time_min=1&connection_time_max=6&departure_date={}&booking_class=Economy",
+        departure_date
+    )
+}
+
+fn paths(routes: &Value) -> Vec<Value> {
+    let mut paths: Vec<Value> = routes.as_array().unwrap().iter().map(|r| r["path"].clone()).collect();
+    paths.sort_by_key(|p| p.to_string());
+    paths
+}
+
+#[actix_web::test]
+async fn schema_is_ready_after_migrations() {
+    let Some(db) = TestDatabase::create().await else { return; };
+    let app = init_service(App::new().app_data(app_state(&db.pool)).configure(crate::routes)).await;
+
+    let res = call_service(&app, TestRequest::get().uri("/readyz").to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let version: Value = read_body_json(call_service(&app, TestRequest::get().uri("/version").to_request()).await).await;
+    assert_eq!(version["schema_version"], json!(10));
+
+    db.drop().await;
+}
+
+#[actix_web::test]
+async fn searches_direct_and_connecting_flights() {
+    let Some(db) = TestDatabase::create().await else { return; };
+    let app = init_service(App::new().app_data(app_state(&db.pool)).configure(crate::routes)).await;
+
+    let res = call_service(&app, TestRequest::get().uri(&route_uri("2017-08-16")).to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let routes: Value = read_body_json(res).await;
+    assert_eq!(paths(&routes), [json!(["DME", "KZN"]), json!(["SVO", "LED", "KZN"])]);
+    assert!(routes.as_array().unwrap().iter().all(|r| r["total_price"].as_i64().unwrap() > 0));
+
+    // The direct flight of August 17 is cancelled
+    let routes: Value = read_body_json(call_service(&app, TestRequest::get().uri(&route_uri("2017-08-17")).to_request()).await).await;
+    assert_eq!(paths(&routes), [json!(["SVO", "LED", "KZN"])]);
+
+    db.drop().await;
+}
+
+#[actix_web::test]
+async fn explores_every_reachable_destination() {
+    let Some(db) = TestDatabase::create().await else { return; };
+    let app = init_service(App::new().app_data(app_state(&db.pool)).configure(crate::routes)).await;
+
+    let req = TestRequest::get()
+        .uri("/api/explore?source_type=City&source=Moscow&max_connections=1&connection_time_min=1&connection_time_max=6\
+            &departure_date=2017-08-16&booking_class=Economy")
+        .to_request();
+
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let destinations: Value = read_body_json(res).await;
+    let found: Vec<&str> = destinations.as_array().unwrap().iter().map(|d| d["airport_code"].as_str().unwrap()).collect();
+    assert_eq!(found, ["AER", "KZN", "LED"]);
+
+    // The direct flight from Domodedovo beats the connection through St. Petersburg on every count
+    for criterion in ["earliest_arrival", "shortest", "cheapest"] {
+        assert_eq!(destinations[1][criterion]["path"], json!(["DME", "KZN"]), "{}", criterion);
+    }
+
+    let req = TestRequest::get()
+        .uri("/api/explore?source_type=City&source=Moscow&max_connections=1&connection_time_min=1&connection_time_max=6\
+            &departure_date=2017-08-16&booking_class=Economy&limit=2")
+        .to_request();
+
+    let destinations: Value = read_body_json(call_service(&app, req).await).await;
+    let found: Vec<&str> = destinations.as_array().unwrap().iter().map(|d| d["airport_code"].as_str().unwrap()).collect();
+    assert_eq!(found, ["AER", "KZN"]);
+
+    db.drop().await;
+}
+
+#[actix_web::test]
+async fn shows_flight_details_with_local_times() {
+    let Some(db) = TestDatabase::create().await else { return; };
+    let app = init_service(App::new().app_data(app_state(&db.pool)).configure(crate::routes)).await;
+
+    // Arrived five minutes late, with one Business and two to four Economy passengers
+    let req = TestRequest::get().uri("/api/flights?flight_no=PG0010&date=2017-08-14").to_request();
+    let flights: Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(flights.as_array().unwrap().len(), 1);
+
+    let flight = &flights[0];
+    assert_eq!(flight["status"], json!("Arrived"));
+    assert_eq!(flight["delay_minutes"], json!(5));
+    assert_eq!(flight["departure"]["timezone"], json!("Asia/Yekaterinburg"));
+    assert_eq!(flight["departure"]["scheduled_local"], json!("2017-08-14T08:00:00"));
+    assert_eq!(flight["arrival"]["scheduled_local"], json!("2017-08-14T08:30:00"));
+    assert_eq!(flight["aircraft"]["model"], json!("Boeing 737-300"));
+    assert_eq!(flight["seats_available"][0], json!({"fare_conditions": "Business", "seats": 17}));
+
+    let uri = format!("/api/flights/{}?lang=ru", flight["flight_id"]);
+    let flight: Value = read_body_json(call_service(&app, TestRequest::get().uri(&uri).to_request()).await).await;
+    assert_eq!(flight["arrival"]["city"], json!("Москва"));
+
+    db.drop().await;
+}
+
+#[actix_web::test]
+async fn shows_departures_board() {
+    let Some(db) = TestDatabase::create().await else { return; };
+    let app = init_service(App::new().app_data(app_state(&db.pool)).configure(crate::routes)).await;
+
+    // At 18:00 in St. Petersburg the afternoon flight to Kazan has landed and the Moscow one is boarding
+    let req = TestRequest::get().uri("/api/airports/LED/board?direction=Departures&hours_before=6").to_request();
+    let board: Value = read_body_json(call_service(&app, req).await).await;
+
+    let lines: Vec<(&str, &str, &str)> = board
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| (e["flight_no"].as_str().unwrap(), e["airport_code"].as_str().unwrap(), e["status"].as_str().unwrap()))
+        .collect();
+
+    assert_eq!(lines, [("PG0003", "KZN", "Arrived"), ("PG0002", "SVO", "Scheduled")]);
+    assert_eq!(board[0]["delay_minutes"], json!(5));
+    assert_eq!(board[0]["actual_local"], json!("2017-08-15T13:05:00"));
+
+    db.drop().await;
+}
+
+#[actix_web::test]
+async fn streams_flight_changes_made_in_sql() {
+    let Some(db) = TestDatabase::create().await else { return; };
+    let state = app_state(&db.pool);
+    let app = init_service(App::new().app_data(state.clone()).configure(crate::routes)).await;
+
+    let listener = actix_web::rt::spawn(crate::flight_events::listen(state.clone()));
+
+    let flight = flight_id(&db.pool, "PG0005", "2017-08-16").await;
+
+    let res = call_service(&app, TestRequest::get().uri(&format!("/api/flight_events?flight_ids={}", flight)).to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // Give the listener time to subscribe to the channel
+    actix_web::rt::time::sleep(Duration::from_millis(500)).await;
+
+    sqlx::query("UPDATE flights SET status = 'Delayed', gate = '12' WHERE flight_id = $1")
+        .bind(flight)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+    let mut body = Box::pin(res.into_body());
+    let chunk = actix_web::rt::time::timeout(Duration::from_secs(5), poll_fn(|cx| body.as_mut().poll_next(cx)))
+        .await
+        .expect("no event received")
+        .unwrap()
+        .unwrap();
+
+    let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+    let data: Value = serde_json::from_str(chunk.strip_prefix("event: flight\ndata: ").unwrap().trim_end()).unwrap();
+
+    assert_eq!(data["flight_id"], json!(flight));
+    assert_eq!(data["status"], json!("Delayed"));
+    assert_eq!(data["previous_status"], json!("Scheduled"));
+    assert_eq!(data["gate"], json!("12"));
+
+    listener.abort();
+    let _ = listener.await;
+
+    db.drop().await;
+}
+
+#[actix_web::test]
+async fn delays_and_cancels_flights_with_audit() {
+    let Some(db) = TestDatabase::create().await else { return; };
+    let app = init_service(App::new().app_data(app_state(&db.pool)).configure(crate::routes)).await;
+
+    let flight = flight_id(&db.pool, "PG0005", "2017-08-16").await;
+
+    let change = |body: Value| TestRequest::post()
+        .uri(&format!("/api/ops/flights/{}/status", flight))
+        .insert_header(("Authorization", format!("Bearer {}", admin_token())))
+        .set_json(body)
+        .to_request();
+
+    let scheduled: Value = read_body_json(call_service(&app, TestRequest::get().uri(&format!("/api/flights/{}", flight)).to_request()).await).await;
+    let scheduled_departure: DateTime<Utc> = serde_json::from_value(scheduled["departure"]["scheduled"].clone()).unwrap();
+
+    let res = call_service(&app, change(json!({"status": "Delayed", "estimated_departure": scheduled_departure + chrono::TimeDelta::minutes(90)}))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let delayed: Value = read_body_json(res).await;
+    assert_eq!(delayed["status"], json!("Delayed"));
+    assert_eq!(delayed["delay_minutes"], json!(90));
+    assert!(delayed["arrival"]["estimated_local"].is_string());
+
+    let res = call_service(&app, change(json!({"status": "Cancelled", "reason": "Crew shortage"}))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = call_service(&app, change(json!({"status": "On Time"}))).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    let req = TestRequest::get()
+        .uri(&format!("/api/ops/flights/{}/audit", flight))
+        .insert_header(("Authorization", format!("Bearer {}", admin_token())))
+        .to_request();
+
+    let audit: Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(audit.as_array().unwrap().len(), 2);
+    assert_eq!(audit[0]["previous_status"], json!("Scheduled"));
+    assert_eq!(audit[1]["status"], json!("Cancelled"));
+    assert_eq!(audit[1]["reason"], json!("Crew shortage"));
+    assert_eq!(audit[1]["changed_by"], json!("test"));
+
+    db.drop().await;
+}
+
+#[actix_web::test]
+async fn rebooks_cancelled_flight_on_the_next_day() {
+    let Some(db) = TestDatabase::create().await else { return; };
+    let app = init_service(App::new().app_data(app_state(&db.pool)).configure(crate::routes)).await;
+
+    let cancelled = flight_id(&db.pool, "PG0003", "2017-08-16").await;
+    let next_day = flight_id(&db.pool, "PG0003", "2017-08-17").await;
+
+    // Booked with dynamic pricing, unlike the tickets of the dataset
+    let req = TestRequest::post()
+        .uri("/api/create_booking")
+        .set_json(json!({
+            "passenger_name": "IVAN PETROV",
+            "passenger_id": "4510 123456",
+            "flight_ids": [cancelled],
+            "fare_conditions": "Economy",
+        }))
+        .to_request();
+
+    let booking: Value = read_body_json(call_service(&app, req).await).await;
+    let ticket_no = booking["ticker_no"].as_str().unwrap().to_string();
+
+    let pricing_query = "SELECT base_amount, amount, applied_rules FROM ticket_flight_pricing WHERE ticket_no = $1 AND flight_id = $2";
+
+    let (base_amount, amount, applied_rules): (i32, i32, Value) = sqlx::query_as(pricing_query)
+        .bind(&ticket_no)
+        .bind(cancelled)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    let paid: Decimal = sqlx::query_scalar("SELECT sum(amount) FROM ticket_flights WHERE flight_id = $1")
+        .bind(cancelled)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    let post = |uri: String, body: Value| TestRequest::post()
+        .uri(&uri)
+        .insert_header(("Authorization", format!("Bearer {}", admin_token())))
+        .set_json(body)
+        .to_request();
+
+    let res = call_service(&app, post(format!("/api/ops/flights/{}/status", cancelled), json!({"status": "Cancelled", "reason": "Aircraft damage"}))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // Flights confirmed on time are still offered
+    let res = call_service(&app, post(format!("/api/ops/flights/{}/status", next_day), json!({"status": "On Time"}))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = call_service(&app, post(format!("/api/ops/flights/{}/reaccommodate", cancelled), json!({}))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // The next day's flight has room for everyone in their own class
+    let report: Value = read_body_json(res).await;
+    let rebooked = report["rebooked"].as_array().unwrap();
+    assert!(rebooked.len() >= 3);
+    assert!(rebooked.iter().all(|r| r["flight_ids"] == json!([next_day])));
+    assert_eq!(rebooked.iter().filter(|r| r["fare_conditions"] == json!("Business")).count(), 1);
+    assert_eq!(report["unprotected"], json!([]));
+
+    let moved: Decimal = sqlx::query_scalar("SELECT sum(amount) FROM ticket_flights WHERE flight_id = $1 AND ticket_no = ANY($2)")
+        .bind(next_day)
+        .bind(rebooked.iter().map(|r| r["ticket_no"].as_str().unwrap().to_string()).collect::<Vec<String>>())
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    assert_eq!(moved, paid);
+
+    let pricing: (i32, i32, Value) = sqlx::query_as(pricing_query)
+        .bind(&ticket_no)
+        .bind(next_day)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    assert_eq!(pricing, (base_amount, amount, applied_rules));
+
+    let res = call_service(&app, post(format!("/api/ops/flights/{}/reaccommodate", cancelled), json!({}))).await;
+    let report: Value = read_body_json(res).await;
+    assert_eq!(report["rebooked"], json!([]));
+
+    db.drop().await;
+}
+
+#[actix_web::test]
+async fn books_and_checks_in_a_connection() {
+    let Some(db) = TestDatabase::create().await else { return; };
+    let app = init_service(App::new().app_data(app_state(&db.pool)).configure(crate::routes)).await;
+
+    let first_leg = flight_id(&db.pool, "PG0001", "2017-08-16").await;
+    let second_leg = flight_id(&db.pool, "PG0003", "2017-08-16").await;
+
+    let req = TestRequest::post()
+        .uri("/api/create_booking")
+        .set_json(json!({
+            "passenger_name": "IVAN PETROV",
+            "passenger_id": "4510 123456",
+            "flight_ids": [first_leg, second_leg],
+            "fare_conditions": "Economy",
+        }))
+        .to_request();
+
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let booking: Value = read_body_json(res).await;
+    let ticket_no = booking["ticker_no"].as_str().unwrap().to_string();
+    assert_eq!(booking["booking_id"].as_str().unwrap().len(), 6);
+    assert_eq!(ticket_no.len(), 13);
+
+    let (legs, amount): (i64, i64) = sqlx::query_as("SELECT count(*), sum(amount)::BIGINT FROM ticket_flights WHERE ticket_no = $1")
+        .bind(&ticket_no)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    assert_eq!(legs, 2);
+    assert_eq!(json!(amount), booking["total_price"]);
+
+    let check_in = || TestRequest::post()
+        .uri("/api/check_in")
+        .set_json(json!({"ticket_no": ticket_no, "flight_id": first_leg}))
+        .to_request();
+
+    let res = call_service(&app, check_in()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let result: Value = read_body_json(res).await;
+
+    let seat_no: String = sqlx::query_scalar("SELECT seat_no FROM boarding_passes WHERE ticket_no = $1 AND flight_id = $2")
+        .bind(&ticket_no)
+        .bind(first_leg)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    assert_eq!(result["seat_no"], json!(seat_no));
+    assert_eq!(call_service(&app, check_in()).await.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    db.drop().await;
+}
+
+#[actix_web::test]
+async fn writes_webhook_events_with_their_changes() {
+    let Some(db) = TestDatabase::create().await else { return; };
+    let state = app_state(&db.pool);
+    let app = init_service(App::new().app_data(state.clone()).configure(crate::routes)).await;
+
+    let received = web::Data::new(Mutex::new(Vec::<Value>::new()));
+    let receiver_data = received.clone();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let receiver_addr = listener.local_addr().unwrap();
+
+    let receiver = HttpServer::new(move || {
+        App::new()
+            .app_data(receiver_data.clone())
+            .route("/", web::post().to(|body: web::Json<Value>, received: web::Data<Mutex<Vec<Value>>>| async move {
+                received.lock().unwrap().push(body.into_inner());
+                HttpResponse::Ok().finish()
+            }))
+    })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+
+    let receiver_handle = receiver.handle();
+    actix_web::rt::spawn(receiver);
+
+    let req = TestRequest::post()
+        .uri("/api/admin/webhooks")
+        .insert_header(("Authorization", format!("Bearer {}", admin_token())))
+        .set_json(json!({"url": format!("http://{}/", receiver_addr), "secret": "0123456789abcdef", "event_types": ["booking.created"]}))
+        .to_request();
+    assert_eq!(call_service(&app, req).await.status(), StatusCode::CREATED);
+
+    let flight = flight_id(&db.pool, "PG0001", "2017-08-16").await;
+
+    let req = TestRequest::post()
+        .uri("/api/create_booking")
+        .set_json(json!({
+            "passenger_name": "IVAN PETROV",
+            "passenger_id": "4510 123456",
+            "flight_ids": [flight],
+            "fare_conditions": "Economy",
+        }))
+        .to_request();
+
+    let booking: Value = read_body_json(call_service(&app, req).await).await;
+    let ticket_no = booking["ticker_no"].as_str().unwrap().to_string();
+
+    let check_in = || TestRequest::post()
+        .uri("/api/check_in")
+        .set_json(json!({"ticket_no": ticket_no, "flight_id": flight}))
+        .to_request();
+
+    assert_eq!(call_service(&app, check_in()).await.status(), StatusCode::OK);
+    assert_eq!(call_service(&app, check_in()).await.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    // The failed check-in rolled its event back, only the booking has a subscriber
+    let events: Vec<String> = sqlx::query_scalar("SELECT event_type FROM webhook_outbox ORDER BY event_id")
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(events, ["booking.created", "passenger.checked_in"]);
+
+    assert_eq!(deliver_due(&reqwest::Client::new(), &state).await.unwrap(), 1);
+
+    let delivered: Vec<(String, i32)> = sqlx::query_as("SELECT status, attempts FROM webhook_deliveries")
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(delivered, [("Delivered".to_string(), 1)]);
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0]["event_type"], json!("booking.created"));
+    assert_eq!(received[0]["data"]["ticket_no"], json!(ticket_no));
+    assert_eq!(received[0]["data"]["flight_ids"], json!([flight]));
+    assert!(received[0]["data"].get("passenger_id").is_none());
+
+    receiver_handle.stop(true).await;
+    db.drop().await;
+}
+
+#[actix_web::test]
+async fn fails_to_list_fares_of_unknown_classes() {
+    let Some(db) = TestDatabase::create().await else { return; };
+    let app = init_service(App::new().app_data(app_state(&db.pool)).configure(crate::routes)).await;
+
+    db.pool.execute("INSERT INTO prices (flight_no, fare_conditions, amount) VALUES ('PG0001', 'Premium', 9000)").await.unwrap();
+
+    for uri in ["/api/admin/fares", "/api/admin/fares/export"] {
+        let req = TestRequest::get()
+            .uri(uri)
+            .insert_header(("Authorization", format!("Bearer {}", admin_token())))
+            .to_request();
+
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::INTERNAL_SERVER_ERROR, "{}", uri);
+    }
+
+    db.drop().await;
+}
+
+#[actix_web::test]
+async fn keeps_fares_written_during_a_price_rebuild() {
+    let Some(db) = TestDatabase::create().await else { return; };
+    let app = init_service(App::new().app_data(app_state(&db.pool)).configure(crate::routes)).await;
+
+    db.pool.execute("DELETE FROM prices").await.unwrap();
+
+    crate::prices::fill_shadow_table(&db.pool).await.unwrap();
+
+    let req = TestRequest::post()
+        .uri("/api/admin/fares")
+        .insert_header(("Authorization", format!("Bearer {}", admin_token())))
+        .set_json(json!({"flight_no": "PG0001", "fare_conditions": "Economy", "amount": 4321}))
+        .to_request();
+    assert_eq!(call_service(&app, req).await.status(), StatusCode::CREATED);
+
+    crate::prices::swap_shadow_table(&db.pool).await.unwrap();
+
+    let fares: Vec<(i32, bool)> = sqlx::query_as("SELECT amount, managed FROM prices WHERE flight_no = 'PG0001' AND fare_conditions = 'Economy'")
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+
+    assert_eq!(fares, [(4321, true)]);
+
+    db.drop().await;
+}
+
+#[actix_web::test]
+async fn rebuilds_prices_and_seat_classes() {
+    let Some(db) = TestDatabase::create().await else { return; };
+    let app = init_service(App::new().app_data(app_state(&db.pool)).configure(crate::routes)).await;
+
+    db.pool.execute("DELETE FROM prices; UPDATE seats_comfort SET fare_conditions = 'Economy'").await.unwrap();
+
+    let req = TestRequest::post()
+        .uri("/api/admin/fares")
+        .insert_header(("Authorization", format!("Bearer {}", admin_token())))
+        .set_json(json!({"flight_no": "PG0002", "fare_conditions": "Economy", "amount": 1234}))
+        .to_request();
+    assert_eq!(call_service(&app, req).await.status(), StatusCode::CREATED);
+
+    for uri in ["/api/admin/compute_prices", "/api/admin/compute_seats"] {
+        let req = TestRequest::post()
+            .uri(uri)
+            .insert_header(("Authorization", format!("Bearer {}", admin_token())))
+            .to_request();
+
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+        let job: Value = read_body_json(res).await;
+        let status_uri = format!("/api/admin/rebuild/{}", job["id"]);
+
+        let mut job = job;
+
+        for _ in 0..100 {
+            if job["status"] != json!("Running") {
+                break;
+            }
+
+            actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+
+            let req = TestRequest::get()
+                .uri(&status_uri)
+                .insert_header(("Authorization", format!("Bearer {}", admin_token())))
+                .to_request();
+
+            job = read_body_json(call_service(&app, req).await).await;
+        }
+
+        assert_eq!(job["status"], json!("Succeeded"), "{}: {}", uri, job);
+    }
+
+    let comfort_fare: i32 = sqlx::query_scalar("SELECT amount FROM prices WHERE flight_no = 'PG0001' AND fare_conditions = 'Comfort'")
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    assert_eq!(comfort_fare, 7800);
+
+    let managed_fare: i32 = sqlx::query_scalar("SELECT amount FROM prices WHERE flight_no = 'PG0002' AND fare_conditions = 'Economy' AND managed")
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    assert_eq!(managed_fare, 1234);
+
+    let comfort_seats: i64 = sqlx::query_scalar("SELECT count(*) FROM seats_comfort WHERE fare_conditions = 'Comfort'")
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    assert_eq!(comfort_seats, 1);
+
+    db.drop().await;
+}
+
+#[actix_web::test]
+async fn applies_cabin_layouts_blocking_every_seat() {
+    let Some(db) = TestDatabase::create().await else { return; };
+    let app = init_service(App::new().app_data(app_state(&db.pool)).configure(crate::routes)).await;
+
+    let seat_nos: Vec<String> = sqlx::query_scalar("SELECT seat_no FROM seats WHERE aircraft_code = 'SU9'")
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+
+    let layout = |tag: &str| json!({
+        "rows": [{"first_row": 1, "last_row": 99, "fare_conditions": "Economy"}],
+        "seat_tags": seat_nos.iter().map(|seat_no| json!({"seat_no": seat_no, "tag": tag})).collect::<Vec<_>>(),
+    });
+
+    let put = |body: Value| TestRequest::put()
+        .uri("/api/admin/cabin_layouts/SU9")
+        .insert_header(("Authorization", format!("Bearer {}", admin_token())))
+        .set_json(body)
+        .to_request();
+
+    assert_eq!(call_service(&app, put(layout("ExitRow"))).await.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(call_service(&app, put(layout("Blocked"))).await.status(), StatusCode::OK);
+
+    let apply = |aircraft_code: &str| TestRequest::post()
+        .uri(&format!("/api/admin/cabin_layouts/{}/apply", aircraft_code))
+        .insert_header(("Authorization", format!("Bearer {}", admin_token())))
+        .to_request();
+
+    let res = call_service(&app, apply("SU9")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(read_body_json::<Value, _>(res).await, json!("Ok, classified 0 seats"));
+
+    assert_eq!(call_service(&app, apply("733")).await.status(), StatusCode::NOT_FOUND);
+
+    let sold_seats: i64 = sqlx::query_scalar("SELECT count(*) FROM seats_comfort WHERE aircraft_code = 'SU9'")
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    assert_eq!(sold_seats, 0);
+
+    db.drop().await;
+}
@@ -0,0 +1,98 @@
+use std::future::{ready, Ready};
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use actix_web::{FromRequest, HttpRequest, HttpResponse, web};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use crate::validation::ValidationErrors;
+
+/// Languages of the names stored in `airports_data`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Lang {
+    #[default]
+    En,
+    Ru,
+}
+
+impl Lang {
+    /// Key of the name in the JSON columns of `airports_data`
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::En => { "en" }
+            Lang::Ru => { "ru" }
+        }
+    }
+
+    /// Matches the primary subtag, so `ru-RU` is Russian
+    fn from_tag(tag: &str) -> Option<Lang> {
+        match tag.split('-').next()?.trim().to_ascii_lowercase().as_str() {
+            "en" => { Some(Lang::En) }
+            "ru" => { Some(Lang::Ru) }
+            _ => { None }
+        }
+    }
+
+    /// The supported language with the highest quality in an `Accept-Language` header
+    fn from_accept_language(header: &str) -> Option<Lang> {
+        let mut ranges: Vec<(f32, Lang)> = header
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let lang = Lang::from_tag(parts.next()?)?;
+
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map(|q| q.parse().unwrap_or(0.0))
+                    .unwrap_or(1.0);
+
+                Some((quality, lang))
+            })
+            .filter(|(quality, _)| *quality > 0.0)
+            .collect();
+
+        // Stable, so equally preferred languages keep the header order
+        ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        ranges.first().map(|(_, lang)| *lang)
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LangParam {
+    /// Language of the names, takes precedence over `Accept-Language`
+    lang: Option<String>,
+}
+
+/// Extracts the language from the `lang` query parameter, then from `Accept-Language`,
+/// defaulting to English.
+impl FromRequest for Lang {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Lang, actix_web::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let param = web::Query::<LangParam>::from_query(req.query_string())
+            .ok()
+            .and_then(|x| x.into_inner().lang);
+
+        if let Some(param) = param {
+            return ready(match Lang::from_tag(&param) {
+                Some(lang) => { Ok(lang) }
+                None => {
+                    let mut errors = ValidationErrors::default();
+                    errors.add("lang", "Must be en or ru");
+                    Err(InternalError::from_response("Invalid language", HttpResponse::BadRequest().json(errors)).into())
+                }
+            });
+        }
+
+        let lang = req.headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|x| x.to_str().ok())
+            .and_then(Lang::from_accept_language)
+            .unwrap_or_default();
+
+        ready(Ok(lang))
+    }
+}
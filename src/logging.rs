@@ -0,0 +1,69 @@
+use std::future::Future;
+use std::time::Instant;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use tracing::Instrument;
+use tracing_subscriber::EnvFilter;
+use crate::config::{LogFormat, LoggingConfig};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+pub fn init(cfg: &LoggingConfig) {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(&cfg.level));
+
+    match cfg.format {
+        LogFormat::Text => { builder.init() }
+        LogFormat::Json => { builder.json().with_current_span(true).init() }
+    }
+}
+
+/// Runs every request inside a span carrying its id, taken from `X-Request-Id`
+/// or generated, echoes the id in the response and logs the outcome.
+pub fn request_span<S, B>(req: ServiceRequest, srv: &S) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let request_id = req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|x| x.to_str().ok())
+        .filter(|x| !x.is_empty() && x.len() <= 128)
+        .map(String::from)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %req.path(),
+    );
+
+    let timer = Instant::now();
+    let response = span.in_scope(|| srv.call(req));
+
+    async move {
+        let mut response = response.await;
+
+        let elapsed_ms = timer.elapsed().as_millis() as u64;
+
+        match &mut response {
+            Ok(r) => {
+                if let Ok(value) = HeaderValue::from_str(&request_id) {
+                    r.headers_mut().insert(REQUEST_ID_HEADER, value);
+                }
+
+                if r.status().is_server_error() {
+                    tracing::error!(status = r.status().as_u16(), elapsed_ms, "request failed");
+                } else {
+                    tracing::info!(status = r.status().as_u16(), elapsed_ms, "request finished");
+                }
+            }
+            Err(e) => {
+                tracing::warn!(status = e.as_response_error().status_code().as_u16(), elapsed_ms, error = %e, "request rejected");
+            }
+        }
+
+        response
+    }
+        .instrument(span)
+}
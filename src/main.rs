@@ -7,91 +7,211 @@ mod find_flights;
 mod types;
 mod booking;
 mod check_in;
+mod rebuild;
+mod auth;
+mod fares;
+mod pricing;
+mod quote;
+mod cabin_layouts;
+mod health;
+mod metrics;
+mod logging;
+mod openapi;
+mod validation;
+mod repository;
+mod pg_repository;
+mod lang;
+mod airport_search;
+mod nearby;
+mod explore;
+mod flight_status;
+mod board;
+mod flight_events;
+mod flight_ops;
+mod reaccommodation;
+mod webhooks;
+#[cfg(test)]
+mod memory_repository;
+#[cfg(test)]
+mod handler_tests;
+#[cfg(test)]
+mod integration_tests;
 
 use std::process::exit;
-use actix_web::{web, App, HttpServer, Responder, HttpResponse};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use actix_web::{web, App, HttpServer};
 use dotenv::dotenv;
-use serde::Serialize;
-use sqlx::postgres::PgPoolOptions;
+use sqlx::ConnectOptions;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 use crate::app_state::AppState;
+use crate::auth::{require_role, Role};
+use crate::cabin_layouts::{apply_cabin_layout, delete_cabin_layout, get_cabin_layout, preview_cabin_layout, replace_cabin_layout};
 use crate::config::Config;
-use crate::handlers::{check_in, create_booking, inbound_schedule, list_airports_within_city, list_all_airports, list_cities, list_routes, outbound_schedule};
+use crate::fares::{create_fare, delete_fare, export_fares, import_fares, list_fares, update_fare};
+use crate::health::{healthz, readyz, version};
+use crate::metrics::{metrics, track_request, Metrics};
+use crate::openapi::ApiDoc;
+use crate::pg_repository::PgRepository;
+use crate::airport_search::search_airports;
+use crate::nearby::nearby_airports;
+use crate::explore::explore;
+use crate::flight_status::{find_flights_by_number, get_flight};
+use crate::board::airport_board;
+use crate::flight_events::{flight_events_stream, flight_events_ws, FlightEvents};
+use crate::flight_ops::{change_flight_status, flight_status_audit};
+use crate::reaccommodation::reaccommodate_passengers;
+use crate::handlers::{check_in, create_booking, inbound_schedule, list_airport_details, list_airports_within_city, list_all_airports, list_cities, list_routes, outbound_schedule};
 use crate::prices::compute_prices;
+use crate::pricing::{list_pricing_rules, replace_pricing_rules};
+use crate::quote::create_quote;
+use crate::rebuild::{rebuild_status, RebuildJobs};
 use crate::seats::compute_seats;
+use crate::webhooks::{create_webhook, delete_webhook, list_dead_letters, list_webhooks, retry_dead_letter};
 
-#[derive(Serialize)]
-struct ListCitiesResponse {
-    cities: Vec<String>
-}
-
-#[derive(Serialize)]
-struct Airport {
-    code: Option<String>,
-    name: Option<String>,
-}
-
-async fn index(state: web::Data<AppState>) -> impl Responder {
-
-    match sqlx::query_as!(
-        Airport,
-        "
-        SELECT airport_code as code, airport_name as name
-        FROM airports
-        GROUP BY code, name
-        LIMIT 5
-        "
-    )
-        .fetch_all(&state.db_pool)
-        .await
-    {
-        Ok(result) => {
-            HttpResponse::Ok().json(result)
-        }
-        Err(_) => {
-            HttpResponse::InternalServerError().json("")
-        }
-    }
+/// Registers every route; shared with tests so that the OpenAPI document can be checked against it.
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg
+        .service(SwaggerUi::new("/api/docs/{_:.*}").url("/api/openapi.json", ApiDoc::openapi()))
+        .route("/healthz", web::get().to(healthz))
+        .route("/readyz", web::get().to(readyz))
+        .route("/version", web::get().to(version))
+        .route("/metrics", web::get().to(metrics))
+        .service(
+            web::scope("/api")
+                .wrap_fn(track_request)
+                .route("/cities", web::get().to(list_cities))
+                .route("/airports", web::get().to(list_all_airports))
+                .route("/airports/search", web::get().to(search_airports))
+                .route("/airports/nearby", web::get().to(nearby_airports))
+                .route("/airports/{airport_code}/board", web::get().to(airport_board))
+                .route("/city_airports/{city}", web::get().to(list_airports_within_city))
+                .route("/inbound/{airport_code}", web::get().to(inbound_schedule))
+                .route("/outbound/{airport_code}", web::get().to(outbound_schedule))
+                .route("/route", web::get().to(list_routes))
+                .route("/explore", web::get().to(explore))
+                .route("/flights", web::get().to(find_flights_by_number))
+                .route("/flights/{flight_id}", web::get().to(get_flight))
+                .route("/flight_events", web::get().to(flight_events_stream))
+                .route("/flight_events/ws", web::get().to(flight_events_ws))
+                .route("/quote", web::post().to(create_quote))
+                .route("/create_booking", web::post().to(create_booking))
+                .route("/check_in", web::post().to(check_in))
+                .service(
+                    web::scope("/ops")
+                        .wrap(require_role(Role::Operations))
+                        .route("/flights/{flight_id}/status", web::post().to(change_flight_status))
+                        .route("/flights/{flight_id}/audit", web::get().to(flight_status_audit))
+                        .route("/flights/{flight_id}/reaccommodate", web::post().to(reaccommodate_passengers))
+                )
+                .service(
+                    web::scope("/admin")
+                        .wrap(require_role(Role::Admin))
+                        .route("/airports", web::get().to(list_airport_details))
+                        .route("/compute_prices", web::post().to(compute_prices))
+                        .route("/compute_seats", web::post().to(compute_seats))
+                        .route("/rebuild/{job_id}", web::get().to(rebuild_status))
+                        .route("/fares", web::get().to(list_fares))
+                        .route("/fares", web::post().to(create_fare))
+                        .route("/fares/export", web::get().to(export_fares))
+                        .route("/fares/import", web::post().to(import_fares))
+                        .route("/fares/{flight_no}/{fare_conditions}", web::put().to(update_fare))
+                        .route("/fares/{flight_no}/{fare_conditions}", web::delete().to(delete_fare))
+                        .route("/pricing_rules", web::get().to(list_pricing_rules))
+                        .route("/pricing_rules", web::put().to(replace_pricing_rules))
+                        .route("/cabin_layouts/{aircraft_code}", web::get().to(get_cabin_layout))
+                        .route("/cabin_layouts/{aircraft_code}", web::put().to(replace_cabin_layout))
+                        .route("/cabin_layouts/{aircraft_code}", web::delete().to(delete_cabin_layout))
+                        .route("/cabin_layouts/{aircraft_code}/preview", web::get().to(preview_cabin_layout))
+                        .route("/cabin_layouts/{aircraft_code}/apply", web::post().to(apply_cabin_layout))
+                        .route("/webhooks", web::get().to(list_webhooks))
+                        .route("/webhooks", web::post().to(create_webhook))
+                        .route("/webhooks/dead_letters", web::get().to(list_dead_letters))
+                        .route("/webhooks/dead_letters/{delivery_id}/retry", web::post().to(retry_dead_letter))
+                        .route("/webhooks/{endpoint_id}", web::delete().to(delete_webhook))
+                )
+        );
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
 
-    let config = Config::init();
+    let config = Config::init().unwrap_or_else(|errors| {
+        eprintln!("Invalid configuration:");
+        for e in errors {
+            eprintln!("  {}", e);
+        }
+        exit(1);
+    });
+
+    logging::init(&config.logging);
+
+    let connect_options = PgConnectOptions::from_str(&config.database.url)
+        .unwrap_or_else(|e| {
+            tracing::error!(error = %e, "Invalid database URL");
+            exit(1);
+        })
+        .log_statements(log::LevelFilter::Debug)
+        .log_slow_statements(log::LevelFilter::Warn, Duration::from_millis(config.logging.slow_query_ms));
 
     let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&config.database_url)
+        .max_connections(config.database.max_connections)
+        .min_connections(config.database.min_connections)
+        .acquire_timeout(Duration::from_secs(config.database.acquire_timeout_secs))
+        .connect_with(connect_options)
         .await
         .unwrap_or_else(|e| {
-            println!("Error while connecting to DB: {}", e);
+            tracing::error!(error = %e, "Error while connecting to DB");
             exit(1);
         });
 
-    let server_addr = config.server_addr.clone();
+    let server_addr = config.server.addr.clone();
+    let workers = config.server.workers;
+
+    let state = web::Data::new(AppState {
+        db_pool: pool.clone(),
+        repo: Arc::new(PgRepository::new(pool)),
+        cfg: config,
+        rebuild_jobs: RebuildJobs::default(),
+        metrics: Metrics::new().expect("Can't register metrics"),
+        flight_events: FlightEvents::default(),
+    });
+
+    let listener = actix_web::rt::spawn(flight_events::listen(state.clone()));
+    let webhook_worker = state.cfg.webhooks.enabled.then(|| actix_web::rt::spawn(webhooks::deliver(state.clone())));
 
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(AppState {
-                db_pool: pool.clone(),
-                cfg: config.clone()
-            }))
-            .service(
-                web::scope("/api")
-                    .route("/cities", web::get().to(list_cities))
-                    .route("/airports", web::get().to(list_all_airports))
-                    .route("/city_airports/{city}", web::get().to(list_airports_within_city))
-                    .route("/inbound/{airport_code}", web::get().to(inbound_schedule))
-                    .route("/outbound/{airport_code}", web::get().to(outbound_schedule))
-                    .route("/route", web::get().to(list_routes))
-                    .route("/compute_prices", web::post().to(compute_prices))
-                    .route("/compute_seats", web::post().to(compute_seats))
-                    .route("/create_booking", web::post().to(create_booking))
-                    .route("/check_in", web::post().to(check_in))
-            )
-    })
+            .app_data(state.clone())
+            .wrap_fn(logging::request_span)
+            .configure(routes)
+    });
+
+    if let Some(workers) = workers {
+        server = server.workers(workers);
+    }
+
+    tracing::info!(addr = %server_addr, "Starting server");
+
+    let result = server
         .bind(server_addr.clone())
-        .expect(format!("Can't bind {}", &server_addr).as_str())
+        .unwrap_or_else(|_| panic!("Can't bind {}", &server_addr))
         .run()
-        .await
+        .await;
+
+    // Dropped while the runtime still runs, as the listener closes its connection on drop
+    listener.abort();
+    let _ = listener.await;
+
+    // Deliveries cut short are sent again once their lease expires
+    if let Some(worker) = webhook_worker {
+        worker.abort();
+        let _ = worker.await;
+    }
+
+    result
 }
@@ -0,0 +1,1047 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Mutex;
+use async_trait::async_trait;
+use serde_json::Value;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc};
+use crate::find_flights::{ExploreRecord, FlightRecord};
+use crate::lang::Lang;
+use crate::pricing::PricingRule;
+use crate::repository::{Aircraft, Airport, AirportDetails, AirportRepository, BoardingPass, BoardingPassRepository, BookingRepository, Coordinates, Fare, FareRepository, FlightAuditEntry, FlightDetails, FlightEndpoint, FlightLeg, FlightRepository, FlightSearch, FlightStatusChange, FlightTimes, InboundRoute, Names, NewBooking, OutboundRoute, PricingInputs, Rebooking, RepositoryError, RepositoryResult, SeatsAvailable, TicketItinerary, WebhookRepository};
+use crate::types::{BoardDirection, BookingClass, FlightState};
+use crate::webhooks::{DeadLetter, DeliveryOutcome, NewWebhook, OutboxEvent, PendingDelivery, Webhook, WebhookEventType};
+
+#[derive(Clone)]
+pub struct MemoryFlight {
+    pub flight_id: i32,
+    pub flight_no: String,
+    pub departure_airport: String,
+    pub arrival_airport: String,
+    pub scheduled_departure: DateTime<Utc>,
+    pub scheduled_arrival: DateTime<Utc>,
+    pub status: String,
+    pub aircraft_code: String,
+    pub estimated_departure: Option<DateTime<Utc>>,
+    pub estimated_arrival: Option<DateTime<Utc>>,
+    pub actual_departure: Option<DateTime<Utc>>,
+    pub actual_arrival: Option<DateTime<Utc>>,
+}
+
+struct MemorySeat {
+    aircraft_code: String,
+    seat_no: String,
+    fare_conditions: BookingClass,
+}
+
+struct MemoryTicketFlight {
+    ticket_no: String,
+    flight_id: i32,
+    fare_conditions: BookingClass,
+}
+
+struct MemoryWebhook {
+    webhook: Webhook,
+    secret: String,
+}
+
+struct MemoryOutboxEvent {
+    event_id: i64,
+    event_type: WebhookEventType,
+    payload: Value,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Dead,
+}
+
+/// Deliveries are scheduled on the wall clock, as the worker waits in real time
+struct MemoryDelivery {
+    delivery_id: i64,
+    event_id: i64,
+    endpoint_id: i32,
+    status: DeliveryStatus,
+    attempts: i32,
+    next_attempt_at: DateTime<Utc>,
+    last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct Data {
+    now: DateTime<Utc>,
+    airports: Vec<AirportDetails>,
+    aircraft: HashMap<String, (Names, i32)>,
+    flights: Vec<MemoryFlight>,
+    seats: Vec<MemorySeat>,
+    fares: Vec<Fare>,
+    pricing_rules: Vec<PricingRule>,
+    /// Passenger names by ticket number
+    tickets: HashMap<String, String>,
+    ticket_flights: Vec<MemoryTicketFlight>,
+    boarding_passes: Vec<BoardingPass>,
+    flight_status_audit: Vec<FlightAuditEntry>,
+    webhooks: Vec<MemoryWebhook>,
+    webhook_outbox: Vec<MemoryOutboxEvent>,
+    webhook_deliveries: Vec<MemoryDelivery>,
+}
+
+impl Data {
+    fn flight(&self, flight_id: i32) -> Option<&MemoryFlight> {
+        self.flights.iter().find(|f| f.flight_id == flight_id)
+    }
+
+    fn occupied_seats(&self, flight_id: i32, fare_conditions: BookingClass) -> i32 {
+        self.ticket_flights
+            .iter()
+            .filter(|t| t.flight_id == flight_id && t.fare_conditions == fare_conditions)
+            .count() as i32
+    }
+
+    fn capacity(&self, aircraft_code: &str, fare_conditions: BookingClass) -> i32 {
+        self.seats
+            .iter()
+            .filter(|s| s.aircraft_code == aircraft_code && s.fare_conditions == fare_conditions)
+            .count() as i32
+    }
+
+    /// Same conditions as the status and `free_seats(...) > 1` filters of the SQL search
+    fn bookable(&self, flight: &MemoryFlight, fare_conditions: BookingClass) -> bool {
+        ["Scheduled", "On Time", "Delayed"].contains(&flight.status.as_str())
+            && self.capacity(&flight.aircraft_code, fare_conditions) - self.occupied_seats(flight.flight_id, fare_conditions) > 1
+    }
+
+    fn endpoint(&self, airport_code: &str, lang: Lang, scheduled: DateTime<Utc>, estimated: Option<DateTime<Utc>>, actual: Option<DateTime<Utc>>) -> FlightEndpoint {
+        let airport = self.airports.iter().find(|a| a.code == airport_code);
+        let local = |time: DateTime<Utc>| (time + Duration::hours(MOSCOW_OFFSET_HOURS)).naive_utc();
+
+        FlightEndpoint {
+            airport_code: airport_code.to_string(),
+            airport_name: airport.and_then(|a| a.name.get(lang)),
+            city: airport.and_then(|a| a.city.get(lang)),
+            timezone: "Europe/Moscow".to_string(),
+            scheduled,
+            scheduled_local: local(scheduled),
+            estimated,
+            estimated_local: estimated.map(local),
+            actual,
+            actual_local: actual.map(local),
+        }
+    }
+
+    /// Writes the event to the outbox with a delivery per subscribed webhook, like the outbox trigger
+    fn publish(&mut self, event: OutboxEvent) {
+        let event_id = self.webhook_outbox.len() as i64 + 1;
+
+        for endpoint in &self.webhooks {
+            let types = &endpoint.webhook.event_types;
+
+            if types.is_empty() || types.contains(&event.event_type) {
+                self.webhook_deliveries.push(MemoryDelivery {
+                    delivery_id: self.webhook_deliveries.len() as i64 + 1,
+                    event_id,
+                    endpoint_id: endpoint.webhook.endpoint_id,
+                    status: DeliveryStatus::Pending,
+                    attempts: 0,
+                    next_attempt_at: Utc::now(),
+                    last_error: None,
+                });
+            }
+        }
+
+        self.webhook_outbox.push(MemoryOutboxEvent {
+            event_id,
+            event_type: event.event_type,
+            payload: event.payload,
+            created_at: self.now,
+        });
+    }
+
+    fn days_of_week(&self, flight_no: &str) -> Vec<i32> {
+        self.flights
+            .iter()
+            .filter(|f| f.flight_no == flight_no)
+            .map(|f| f.scheduled_departure.weekday().number_from_monday() as i32)
+            .collect::<BTreeSet<i32>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Moscow has kept UTC+3 all year since 2014
+const MOSCOW_OFFSET_HOURS: i64 = 3;
+
+fn is_named(names: &Names, name: &str) -> bool {
+    names.en.as_deref() == Some(name) || names.ru.as_deref() == Some(name)
+}
+
+/// Repository keeping everything in memory, for handler tests. Weekdays and
+/// schedule times are taken in UTC, as airports have no time zones here.
+#[derive(Default)]
+pub struct MemoryRepository {
+    data: Mutex<Data>,
+}
+
+impl MemoryRepository {
+    pub fn new(now: DateTime<Utc>) -> MemoryRepository {
+        let repo = MemoryRepository::default();
+        repo.data.lock().unwrap().now = now;
+        repo
+    }
+
+    /// Names are given in English and Russian, coordinates as latitude and
+    /// longitude. All airports are in Moscow time.
+    pub fn with_airport(self, code: &str, name: (&str, &str), city: (&str, &str), coordinates: (f64, f64)) -> MemoryRepository {
+        let names = |(en, ru): (&str, &str)| Names { en: Some(en.to_string()), ru: Some(ru.to_string()) };
+
+        self.data.lock().unwrap().airports.push(AirportDetails {
+            code: code.to_string(),
+            name: names(name),
+            city: names(city),
+            coordinates: Coordinates { latitude: coordinates.0, longitude: coordinates.1 },
+            timezone: "Europe/Moscow".to_string(),
+        });
+        self
+    }
+
+    pub fn with_aircraft(self, code: &str, model: (&str, &str), range: i32) -> MemoryRepository {
+        let model = Names { en: Some(model.0.to_string()), ru: Some(model.1.to_string()) };
+        self.data.lock().unwrap().aircraft.insert(code.to_string(), (model, range));
+        self
+    }
+
+    pub fn with_flight(self, flight: MemoryFlight) -> MemoryRepository {
+        self.data.lock().unwrap().flights.push(flight);
+        self
+    }
+
+    pub fn with_seats(self, aircraft_code: &str, fare_conditions: BookingClass, seat_nos: &[&str]) -> MemoryRepository {
+        self.data.lock().unwrap().seats.extend(seat_nos.iter().map(|seat_no| MemorySeat {
+            aircraft_code: aircraft_code.to_string(),
+            seat_no: seat_no.to_string(),
+            fare_conditions,
+        }));
+        self
+    }
+
+    pub fn with_fare(self, flight_no: &str, fare_conditions: BookingClass, amount: i32) -> MemoryRepository {
+        self.data.lock().unwrap().fares.push(Fare {
+            flight_no: flight_no.to_string(),
+            fare_conditions,
+            amount,
+        });
+        self
+    }
+
+    /// Adds a leg to the ticket, creating the ticket on its first leg
+    pub fn with_ticket(self, ticket_no: &str, passenger_name: &str, flight_id: i32, fare_conditions: BookingClass) -> MemoryRepository {
+        let mut data = self.data.lock().unwrap();
+        data.tickets.entry(ticket_no.to_string()).or_insert_with(|| passenger_name.to_string());
+        data.ticket_flights.push(MemoryTicketFlight {
+            ticket_no: ticket_no.to_string(),
+            flight_id,
+            fare_conditions,
+        });
+        drop(data);
+        self
+    }
+
+    pub fn ticket_count(&self) -> usize {
+        self.data.lock().unwrap().tickets.len()
+    }
+}
+
+#[async_trait]
+impl AirportRepository for MemoryRepository {
+    async fn cities(&self, lang: Lang) -> RepositoryResult<Vec<String>> {
+        let data = self.data.lock().unwrap();
+
+        Ok(data.airports.iter().filter_map(|a| a.city.get(lang)).collect::<BTreeSet<String>>().into_iter().collect())
+    }
+
+    async fn airports(&self, lang: Lang) -> RepositoryResult<Vec<Airport>> {
+        let data = self.data.lock().unwrap();
+
+        let mut airports: Vec<Airport> = data.airports
+            .iter()
+            .map(|a| Airport { code: Some(a.code.clone()), name: a.name.get(lang) })
+            .collect();
+
+        airports.sort_by(|a, b| a.code.cmp(&b.code));
+
+        Ok(airports)
+    }
+
+    async fn airports_in_city(&self, city: &str, lang: Lang) -> RepositoryResult<Vec<Airport>> {
+        let data = self.data.lock().unwrap();
+
+        let mut airports: Vec<Airport> = data.airports
+            .iter()
+            .filter(|a| is_named(&a.city, city))
+            .map(|a| Airport { code: Some(a.code.clone()), name: a.name.get(lang) })
+            .collect();
+
+        airports.sort_by(|a, b| a.code.cmp(&b.code));
+
+        Ok(airports)
+    }
+
+    async fn airport_details(&self) -> RepositoryResult<Vec<AirportDetails>> {
+        let data = self.data.lock().unwrap();
+
+        let mut airports = data.airports.clone();
+
+        airports.sort_by(|a, b| a.code.cmp(&b.code));
+
+        Ok(airports)
+    }
+
+    async fn airport_exists(&self, code: &str) -> RepositoryResult<bool> {
+        Ok(self.data.lock().unwrap().airports.iter().any(|a| a.code == code))
+    }
+
+    async fn city_exists(&self, city: &str) -> RepositoryResult<bool> {
+        Ok(self.data.lock().unwrap().airports.iter().any(|a| is_named(&a.city, city)))
+    }
+}
+
+#[async_trait]
+impl FlightRepository for MemoryRepository {
+    async fn now(&self) -> RepositoryResult<DateTime<Utc>> {
+        Ok(self.data.lock().unwrap().now)
+    }
+
+    async fn departure_date_range(&self) -> RepositoryResult<Option<(NaiveDate, NaiveDate)>> {
+        let data = self.data.lock().unwrap();
+
+        let dates = data.flights.iter().map(|f| f.scheduled_departure.date_naive());
+
+        Ok(dates.clone().min().zip(dates.max()))
+    }
+
+    async fn inbound_schedule(&self, airport_code: &str) -> RepositoryResult<Vec<InboundRoute>> {
+        let data = self.data.lock().unwrap();
+
+        let routes: BTreeMap<(String, NaiveTime, String), Vec<i32>> = data.flights
+            .iter()
+            .filter(|f| f.arrival_airport == airport_code)
+            .map(|f| (
+                (f.flight_no.clone(), f.scheduled_arrival.time(), f.departure_airport.clone()),
+                data.days_of_week(&f.flight_no)
+            ))
+            .collect();
+
+        Ok(
+            routes
+                .into_iter()
+                .map(|((flight_no, arrival_time, origin), days_of_week)| InboundRoute {
+                    flight_no: Some(flight_no),
+                    arrival_time: Some(arrival_time),
+                    origin: Some(origin),
+                    days_of_week: Some(days_of_week),
+                })
+                .collect()
+        )
+    }
+
+    async fn outbound_schedule(&self, airport_code: &str) -> RepositoryResult<Vec<OutboundRoute>> {
+        let data = self.data.lock().unwrap();
+
+        let routes: BTreeMap<(String, NaiveTime, String), Vec<i32>> = data.flights
+            .iter()
+            .filter(|f| f.departure_airport == airport_code)
+            .map(|f| (
+                (f.flight_no.clone(), f.scheduled_departure.time(), f.arrival_airport.clone()),
+                data.days_of_week(&f.flight_no)
+            ))
+            .collect();
+
+        Ok(
+            routes
+                .into_iter()
+                .map(|((flight_no, departure_time, destination), days_of_week)| OutboundRoute {
+                    flight_no: Some(flight_no),
+                    departure_time: Some(departure_time),
+                    destination: Some(destination),
+                    days_of_week: Some(days_of_week),
+                })
+                .collect()
+        )
+    }
+
+    /// Breadth-first version of the recursive query in `find_flights`
+    async fn find_flights(&self, search: &FlightSearch) -> RepositoryResult<Vec<FlightRecord>> {
+        struct Partial<'a> {
+            path: Vec<String>,
+            last: &'a MemoryFlight,
+            departure_time: DateTime<Utc>,
+            flight_ids: Vec<i32>,
+        }
+
+        let data = self.data.lock().unwrap();
+
+        let start = search.departure_date.and_time(NaiveTime::MIN).and_utc();
+
+        let mut queue: Vec<Partial> = data.flights
+            .iter()
+            .filter(|f| search.sources.contains(&f.departure_airport)
+                && f.scheduled_departure >= start
+                && f.scheduled_departure <= start + Duration::hours(24)
+                && data.bookable(f, search.booking_class))
+            .map(|f| Partial {
+                path: vec![f.departure_airport.clone()],
+                last: f,
+                departure_time: f.scheduled_departure,
+                flight_ids: vec![f.flight_id],
+            })
+            .collect();
+
+        let mut results = vec![];
+
+        while !queue.is_empty() {
+            let mut next = vec![];
+
+            for partial in queue {
+                let end_point = &partial.last.arrival_airport;
+                let connections = partial.flight_ids.len() as i32 - 1;
+
+                if search.destinations.as_ref().is_none_or(|d| d.contains(end_point)) {
+                    let mut path = partial.path.clone();
+                    path.push(end_point.clone());
+
+                    results.push(FlightRecord {
+                        path: Some(path),
+                        flight_ids: Some(partial.flight_ids.clone()),
+                        departure_time: Some(partial.departure_time),
+                        arrival_time: Some(partial.last.scheduled_arrival),
+                        connections: Some(connections),
+                    });
+                }
+
+                if connections >= search.max_connections {
+                    continue;
+                }
+
+                let earliest = partial.last.scheduled_arrival + Duration::hours(search.connection_time_min as i64);
+                let latest = partial.last.scheduled_arrival + Duration::hours(search.connection_time_max as i64);
+
+                for f in data.flights.iter().filter(|f| &f.departure_airport == end_point
+                    && !partial.path.contains(&f.arrival_airport)
+                    && f.scheduled_departure >= earliest
+                    && f.scheduled_departure <= latest
+                    && data.bookable(f, search.booking_class))
+                {
+                    let mut path = partial.path.clone();
+                    path.push(end_point.clone());
+
+                    let mut flight_ids = partial.flight_ids.clone();
+                    flight_ids.push(f.flight_id);
+
+                    next.push(Partial {
+                        path,
+                        last: f,
+                        departure_time: partial.departure_time,
+                        flight_ids,
+                    });
+                }
+            }
+
+            queue = next;
+        }
+
+        Ok(results)
+    }
+
+    /// Picks the best itineraries of the breadth-first search with the ordering of the SQL query
+    async fn explore_destinations(&self, search: &FlightSearch, limit: i64) -> RepositoryResult<Vec<ExploreRecord>> {
+        let flights = self.find_flights(search).await?;
+
+        let data = self.data.lock().unwrap();
+
+        let base_price = |flight: &FlightRecord| -> Option<i32> {
+            flight.flight_ids
+                .as_ref()?
+                .iter()
+                .map(|id| {
+                    let f = data.flights.iter().find(|f| f.flight_id == *id)?;
+                    data.fares
+                        .iter()
+                        .find(|x| x.flight_no == f.flight_no && x.fare_conditions == search.booking_class)
+                        .map(|x| x.amount)
+                })
+                .sum()
+        };
+
+        let mut by_destination: BTreeMap<String, Vec<FlightRecord>> = BTreeMap::new();
+
+        for flight in flights {
+            let Some(destination) = flight.path.as_ref().and_then(|p| p.last()).cloned() else { continue; };
+
+            if !search.sources.contains(&destination) {
+                by_destination.entry(destination).or_default().push(flight);
+            }
+        }
+
+        let record = |criterion: &str, flight: &FlightRecord| ExploreRecord {
+            criterion: Some(criterion.to_string()),
+            path: flight.path.clone(),
+            flight_ids: flight.flight_ids.clone(),
+            departure_time: flight.departure_time,
+            arrival_time: flight.arrival_time,
+        };
+
+        let duration = |flight: &FlightRecord| flight.arrival_time.zip(flight.departure_time).map(|(a, d)| a - d);
+
+        let mut records = vec![];
+
+        for itineraries in by_destination.values().take(limit as usize) {
+            if let Some(earliest) = itineraries.iter().min_by_key(|f| (f.arrival_time, duration(f), f.flight_ids.clone())) {
+                records.push(record("earliest_arrival", earliest));
+            }
+
+            if let Some(shortest) = itineraries.iter().min_by_key(|f| (duration(f), f.arrival_time, f.flight_ids.clone())) {
+                records.push(record("shortest", shortest));
+            }
+
+            if let Some(cheapest) = itineraries
+                .iter()
+                .filter_map(|f| Some((base_price(f)?, f)))
+                .min_by_key(|(price, f)| (*price, f.arrival_time, f.flight_ids.clone()))
+            {
+                records.push(record("cheapest", cheapest.1));
+            }
+        }
+
+        Ok(records)
+    }
+
+    async fn flights(&self, flight_ids: &[i32]) -> RepositoryResult<Vec<FlightLeg>> {
+        let data = self.data.lock().unwrap();
+
+        Ok(
+            flight_ids
+                .iter()
+                .filter_map(|id| data.flight(*id))
+                .map(|f| FlightLeg {
+                    flight_id: f.flight_id,
+                    departure_airport: f.departure_airport.clone(),
+                    arrival_airport: f.arrival_airport.clone(),
+                    scheduled_departure: f.scheduled_departure,
+                    scheduled_arrival: f.scheduled_arrival,
+                })
+                .collect()
+        )
+    }
+
+    async fn flight_details(&self, flight_ids: &[i32], lang: Lang) -> RepositoryResult<Vec<FlightDetails>> {
+        let data = self.data.lock().unwrap();
+
+        let mut flights: Vec<FlightDetails> = flight_ids
+            .iter()
+            .filter_map(|id| data.flight(*id))
+            .map(|f| {
+                let (model, range) = data.aircraft.get(&f.aircraft_code).cloned().unwrap_or_default();
+
+                FlightDetails {
+                    flight_id: f.flight_id,
+                    flight_no: f.flight_no.clone(),
+                    status: f.status.clone(),
+                    departure: data.endpoint(&f.departure_airport, lang, f.scheduled_departure, f.estimated_departure, f.actual_departure),
+                    arrival: data.endpoint(&f.arrival_airport, lang, f.scheduled_arrival, f.estimated_arrival, f.actual_arrival),
+                    aircraft: Aircraft { code: f.aircraft_code.clone(), model: model.get(lang), range },
+                    seats_available: [BookingClass::Business, BookingClass::Comfort, BookingClass::Economy]
+                        .into_iter()
+                        .filter(|c| data.capacity(&f.aircraft_code, *c) > 0)
+                        .map(|c| SeatsAvailable {
+                            fare_conditions: c,
+                            seats: data.capacity(&f.aircraft_code, c) - data.occupied_seats(f.flight_id, c),
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        flights.sort_by_key(|f| (f.departure.scheduled, f.flight_id));
+
+        Ok(flights)
+    }
+
+    async fn flight_ids_by_number(&self, flight_no: &str, date: NaiveDate) -> RepositoryResult<Vec<i32>> {
+        let data = self.data.lock().unwrap();
+
+        let mut flights: Vec<&MemoryFlight> = data.flights
+            .iter()
+            .filter(|f| f.flight_no == flight_no
+                && (f.scheduled_departure + Duration::hours(MOSCOW_OFFSET_HOURS)).date_naive() == date)
+            .collect();
+
+        flights.sort_by_key(|f| f.scheduled_departure);
+
+        Ok(flights.into_iter().map(|f| f.flight_id).collect())
+    }
+
+    async fn board_flight_ids(&self, airport_code: &str, direction: BoardDirection, from: DateTime<Utc>, to: DateTime<Utc>) -> RepositoryResult<Vec<i32>> {
+        let data = self.data.lock().unwrap();
+        let within = |time: DateTime<Utc>| time >= from && time <= to;
+
+        Ok(
+            data.flights
+                .iter()
+                .filter(|f| match direction {
+                    BoardDirection::Departures => {
+                        f.departure_airport == airport_code && (within(f.scheduled_departure) || f.estimated_departure.is_some_and(within) || f.actual_departure.is_some_and(within))
+                    }
+                    BoardDirection::Arrivals => {
+                        f.arrival_airport == airport_code && (within(f.scheduled_arrival) || f.estimated_arrival.is_some_and(within) || f.actual_arrival.is_some_and(within))
+                    }
+                })
+                .map(|f| f.flight_id)
+                .collect()
+        )
+    }
+
+    async fn flight_times(&self, flight_id: i32) -> RepositoryResult<Option<FlightTimes>> {
+        let data = self.data.lock().unwrap();
+
+        Ok(
+            data.flight(flight_id).and_then(|f| Some(FlightTimes {
+                status: FlightState::try_from(f.status.as_str()).ok()?,
+                scheduled_departure: f.scheduled_departure,
+                scheduled_arrival: f.scheduled_arrival,
+                estimated_departure: f.estimated_departure,
+                estimated_arrival: f.estimated_arrival,
+                actual_departure: f.actual_departure,
+                actual_arrival: f.actual_arrival,
+            }))
+        )
+    }
+
+    async fn change_flight_status(&self, change: &FlightStatusChange) -> RepositoryResult<bool> {
+        let mut data = self.data.lock().unwrap();
+        let times = &change.times;
+
+        let Some(flight) = data.flights
+            .iter_mut()
+            .find(|f| f.flight_id == change.flight_id && f.status == change.previous_status.as_str())
+        else {
+            return Ok(false);
+        };
+
+        flight.status = times.status.as_str().to_string();
+        flight.estimated_departure = times.estimated_departure;
+        flight.estimated_arrival = times.estimated_arrival;
+        flight.actual_departure = times.actual_departure;
+        flight.actual_arrival = times.actual_arrival;
+
+        let entry = FlightAuditEntry {
+            audit_id: data.flight_status_audit.len() as i64 + 1,
+            flight_id: change.flight_id,
+            changed_at: data.now,
+            changed_by: change.changed_by.clone(),
+            previous_status: change.previous_status,
+            status: times.status,
+            estimated_departure: times.estimated_departure,
+            estimated_arrival: times.estimated_arrival,
+            actual_departure: times.actual_departure,
+            actual_arrival: times.actual_arrival,
+            reason: change.reason.clone(),
+        };
+
+        data.flight_status_audit.push(entry);
+        data.publish(OutboxEvent::flight_status_changed(change));
+
+        Ok(true)
+    }
+
+    async fn flight_status_audit(&self, flight_id: i32) -> RepositoryResult<Vec<FlightAuditEntry>> {
+        let data = self.data.lock().unwrap();
+
+        Ok(data.flight_status_audit.iter().filter(|e| e.flight_id == flight_id).cloned().collect())
+    }
+
+    async fn flight_numbers(&self) -> RepositoryResult<HashSet<String>> {
+        Ok(self.data.lock().unwrap().flights.iter().map(|f| f.flight_no.clone()).collect())
+    }
+
+    async fn pricing_inputs(&self, flight_ids: &[i32], booking_class: BookingClass) -> RepositoryResult<HashMap<i32, PricingInputs>> {
+        let data = self.data.lock().unwrap();
+
+        Ok(
+            flight_ids
+                .iter()
+                .filter_map(|id| data.flight(*id))
+                .filter_map(|f| {
+                    let fare = data.fares.iter().find(|x| x.flight_no == f.flight_no && x.fare_conditions == booking_class)?;
+
+                    Some((f.flight_id, PricingInputs {
+                        base_amount: fare.amount,
+                        occupied_seats: data.occupied_seats(f.flight_id, booking_class),
+                        capacity: data.capacity(&f.aircraft_code, booking_class),
+                        days_to_departure: (f.scheduled_departure - data.now).num_days() as i32,
+                        weekday: f.scheduled_departure.weekday().number_from_monday() as i32,
+                    }))
+                })
+                .collect()
+        )
+    }
+}
+
+#[async_trait]
+impl FareRepository for MemoryRepository {
+    async fn fares(&self, flight_no: Option<&str>) -> RepositoryResult<Vec<Fare>> {
+        let data = self.data.lock().unwrap();
+
+        Ok(
+            data.fares
+                .iter()
+                .filter(|f| flight_no.map(|x| x == f.flight_no).unwrap_or(true))
+                .cloned()
+                .collect()
+        )
+    }
+
+    async fn create_fare(&self, fare: &Fare) -> RepositoryResult<()> {
+        let mut data = self.data.lock().unwrap();
+
+        if data.fares.iter().any(|f| f.flight_no == fare.flight_no && f.fare_conditions == fare.fare_conditions) {
+            return Err(RepositoryError::Duplicate);
+        }
+
+        data.fares.push(fare.clone());
+
+        Ok(())
+    }
+
+    async fn update_fare(&self, fare: &Fare) -> RepositoryResult<bool> {
+        let mut data = self.data.lock().unwrap();
+
+        match data.fares.iter_mut().find(|f| f.flight_no == fare.flight_no && f.fare_conditions == fare.fare_conditions) {
+            Some(f) => {
+                f.amount = fare.amount;
+                Ok(true)
+            }
+            None => { Ok(false) }
+        }
+    }
+
+    async fn delete_fare(&self, flight_no: &str, fare_conditions: BookingClass) -> RepositoryResult<bool> {
+        let mut data = self.data.lock().unwrap();
+
+        let count = data.fares.len();
+        data.fares.retain(|f| !(f.flight_no == flight_no && f.fare_conditions == fare_conditions));
+
+        Ok(data.fares.len() < count)
+    }
+
+    async fn upsert_fares(&self, fares: &[Fare]) -> RepositoryResult<u64> {
+        let mut data = self.data.lock().unwrap();
+
+        for fare in fares {
+            data.fares.retain(|f| !(f.flight_no == fare.flight_no && f.fare_conditions == fare.fare_conditions));
+            data.fares.push(fare.clone());
+        }
+
+        Ok(fares.len() as u64)
+    }
+
+    async fn pricing_rules(&self) -> RepositoryResult<Vec<PricingRule>> {
+        Ok(self.data.lock().unwrap().pricing_rules.clone())
+    }
+
+    async fn replace_pricing_rules(&self, rules: &[PricingRule]) -> RepositoryResult<()> {
+        self.data.lock().unwrap().pricing_rules = rules.to_vec();
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BookingRepository for MemoryRepository {
+    async fn create_booking(&self, booking: &NewBooking) -> RepositoryResult<()> {
+        let mut data = self.data.lock().unwrap();
+
+        if data.tickets.contains_key(&booking.ticket_no) {
+            return Err(RepositoryError::Duplicate);
+        }
+
+        data.tickets.insert(booking.ticket_no.clone(), booking.passenger_name.clone());
+
+        for leg in &booking.legs {
+            data.ticket_flights.push(MemoryTicketFlight {
+                ticket_no: booking.ticket_no.clone(),
+                flight_id: leg.flight_id,
+                fare_conditions: booking.fare_conditions,
+            });
+        }
+
+        data.publish(OutboxEvent::booking_created(booking));
+
+        Ok(())
+    }
+
+    async fn ticket_fare_conditions(&self, ticket_no: &str, flight_id: i32) -> RepositoryResult<Option<BookingClass>> {
+        let data = self.data.lock().unwrap();
+
+        Ok(
+            data.ticket_flights
+                .iter()
+                .find(|t| t.ticket_no == ticket_no && t.flight_id == flight_id)
+                .map(|t| t.fare_conditions)
+        )
+    }
+
+    async fn tickets_on_flight(&self, flight_id: i32) -> RepositoryResult<Vec<TicketItinerary>> {
+        let data = self.data.lock().unwrap();
+
+        let booked: BTreeMap<&str, BookingClass> = data.ticket_flights
+            .iter()
+            .filter(|t| t.flight_id == flight_id)
+            .map(|t| (t.ticket_no.as_str(), t.fare_conditions))
+            .collect();
+
+        Ok(
+            booked
+                .into_iter()
+                .map(|(ticket_no, fare_conditions)| {
+                    let mut legs: Vec<FlightLeg> = data.ticket_flights
+                        .iter()
+                        .filter(|t| t.ticket_no == ticket_no)
+                        .filter_map(|t| data.flight(t.flight_id))
+                        .map(|f| FlightLeg {
+                            flight_id: f.flight_id,
+                            departure_airport: f.departure_airport.clone(),
+                            arrival_airport: f.arrival_airport.clone(),
+                            scheduled_departure: f.scheduled_departure,
+                            scheduled_arrival: f.scheduled_arrival,
+                        })
+                        .collect();
+
+                    legs.sort_by_key(|l| l.scheduled_departure);
+
+                    TicketItinerary {
+                        ticket_no: ticket_no.to_string(),
+                        passenger_name: data.tickets.get(ticket_no).cloned().unwrap_or_default(),
+                        fare_conditions,
+                        legs,
+                    }
+                })
+                .collect()
+        )
+    }
+
+    async fn rebook_ticket(&self, rebooking: &Rebooking) -> RepositoryResult<bool> {
+        let mut data = self.data.lock().unwrap();
+
+        let has_seat = |flight_id: &i32| data.flight(*flight_id).is_some_and(|f| {
+            data.capacity(&f.aircraft_code, rebooking.fare_conditions) > data.occupied_seats(f.flight_id, rebooking.fare_conditions)
+        });
+
+        if !rebooking.added.iter().all(has_seat) {
+            return Ok(false);
+        }
+
+        let removed = |ticket_no: &str, flight_id: i32| ticket_no == rebooking.ticket_no && rebooking.removed.contains(&flight_id);
+
+        data.boarding_passes.retain(|b| !removed(&b.ticket_no, b.flight_id));
+        data.ticket_flights.retain(|t| !removed(&t.ticket_no, t.flight_id));
+
+        for flight_id in &rebooking.added {
+            data.ticket_flights.push(MemoryTicketFlight {
+                ticket_no: rebooking.ticket_no.clone(),
+                flight_id: *flight_id,
+                fare_conditions: rebooking.fare_conditions,
+            });
+        }
+
+        data.publish(OutboxEvent::ticket_rebooked(rebooking));
+
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl BoardingPassRepository for MemoryRepository {
+    async fn boarding_passes(&self, flight_id: i32) -> RepositoryResult<Vec<BoardingPass>> {
+        let data = self.data.lock().unwrap();
+
+        Ok(data.boarding_passes.iter().filter(|b| b.flight_id == flight_id).cloned().collect())
+    }
+
+    async fn cabin_seats(&self, flight_id: i32, fare_conditions: BookingClass) -> RepositoryResult<Vec<String>> {
+        let data = self.data.lock().unwrap();
+
+        let aircraft_code = match data.flight(flight_id) {
+            Some(f) => { f.aircraft_code.clone() }
+            None => { return Ok(vec![]); }
+        };
+
+        let mut seats: Vec<String> = data.seats
+            .iter()
+            .filter(|s| s.aircraft_code == aircraft_code && s.fare_conditions == fare_conditions)
+            .map(|s| s.seat_no.clone())
+            .collect();
+
+        seats.sort();
+
+        Ok(seats)
+    }
+
+    async fn create_boarding_pass(&self, boarding_pass: &BoardingPass) -> RepositoryResult<()> {
+        let mut data = self.data.lock().unwrap();
+
+        if data.boarding_passes.iter().any(|b| b.ticket_no == boarding_pass.ticket_no && b.flight_id == boarding_pass.flight_id) {
+            return Err(RepositoryError::Duplicate);
+        }
+
+        data.boarding_passes.push(boarding_pass.clone());
+        data.publish(OutboxEvent::passenger_checked_in(boarding_pass));
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for MemoryRepository {
+    async fn webhooks(&self) -> RepositoryResult<Vec<Webhook>> {
+        let data = self.data.lock().unwrap();
+
+        Ok(data.webhooks.iter().map(|w| w.webhook.clone()).collect())
+    }
+
+    async fn create_webhook(&self, webhook: &NewWebhook) -> RepositoryResult<Webhook> {
+        let mut data = self.data.lock().unwrap();
+
+        let created = Webhook {
+            endpoint_id: data.webhooks.iter().map(|w| w.webhook.endpoint_id).max().unwrap_or(0) + 1,
+            url: webhook.url.clone(),
+            event_types: webhook.event_types.clone(),
+            created_at: data.now,
+        };
+
+        data.webhooks.push(MemoryWebhook { webhook: created.clone(), secret: webhook.secret.clone() });
+
+        Ok(created)
+    }
+
+    async fn delete_webhook(&self, endpoint_id: i32) -> RepositoryResult<bool> {
+        let mut data = self.data.lock().unwrap();
+
+        let count = data.webhooks.len();
+        data.webhooks.retain(|w| w.webhook.endpoint_id != endpoint_id);
+        data.webhook_deliveries.retain(|d| d.endpoint_id != endpoint_id);
+
+        Ok(data.webhooks.len() < count)
+    }
+
+    async fn claim_deliveries(&self, limit: i64, lease: Duration) -> RepositoryResult<Vec<PendingDelivery>> {
+        let mut guard = self.data.lock().unwrap();
+        let data = &mut *guard;
+        let now = Utc::now();
+
+        let mut claimed = vec![];
+
+        for delivery in data.webhook_deliveries.iter_mut() {
+            if claimed.len() as i64 >= limit {
+                break;
+            }
+
+            if delivery.status != DeliveryStatus::Pending || delivery.next_attempt_at > now {
+                continue;
+            }
+
+            let (Some(event), Some(endpoint)) = (
+                data.webhook_outbox.iter().find(|e| e.event_id == delivery.event_id),
+                data.webhooks.iter().find(|w| w.webhook.endpoint_id == delivery.endpoint_id),
+            ) else {
+                continue;
+            };
+
+            delivery.next_attempt_at = now + lease;
+
+            claimed.push(PendingDelivery {
+                delivery_id: delivery.delivery_id,
+                event_id: event.event_id,
+                event_type: event.event_type,
+                payload: event.payload.clone(),
+                created_at: event.created_at,
+                url: endpoint.webhook.url.clone(),
+                secret: endpoint.secret.clone(),
+                attempts: delivery.attempts,
+            });
+        }
+
+        Ok(claimed)
+    }
+
+    async fn finish_delivery(&self, delivery_id: i64, outcome: &DeliveryOutcome) -> RepositoryResult<()> {
+        let mut data = self.data.lock().unwrap();
+
+        let Some(delivery) = data.webhook_deliveries.iter_mut().find(|d| d.delivery_id == delivery_id) else {
+            return Ok(());
+        };
+
+        delivery.attempts += 1;
+
+        match outcome {
+            DeliveryOutcome::Delivered => {
+                delivery.status = DeliveryStatus::Delivered;
+                delivery.last_error = None;
+            }
+            DeliveryOutcome::Retry { at, error } => {
+                delivery.next_attempt_at = *at;
+                delivery.last_error = Some(error.clone());
+            }
+            DeliveryOutcome::Dead { error } => {
+                delivery.status = DeliveryStatus::Dead;
+                delivery.last_error = Some(error.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn dead_letters(&self) -> RepositoryResult<Vec<DeadLetter>> {
+        let data = self.data.lock().unwrap();
+
+        Ok(
+            data.webhook_deliveries
+                .iter()
+                .filter(|d| d.status == DeliveryStatus::Dead)
+                .filter_map(|d| {
+                    let event = data.webhook_outbox.iter().find(|e| e.event_id == d.event_id)?;
+                    let endpoint = data.webhooks.iter().find(|w| w.webhook.endpoint_id == d.endpoint_id)?;
+
+                    Some(DeadLetter {
+                        delivery_id: d.delivery_id,
+                        endpoint_id: d.endpoint_id,
+                        url: endpoint.webhook.url.clone(),
+                        event_id: event.event_id,
+                        event_type: event.event_type,
+                        payload: event.payload.clone(),
+                        attempts: d.attempts,
+                        last_error: d.last_error.clone(),
+                        created_at: event.created_at,
+                    })
+                })
+                .collect()
+        )
+    }
+
+    async fn retry_dead_letter(&self, delivery_id: i64) -> RepositoryResult<bool> {
+        let mut data = self.data.lock().unwrap();
+
+        let Some(delivery) = data.webhook_deliveries
+            .iter_mut()
+            .find(|d| d.delivery_id == delivery_id && d.status == DeliveryStatus::Dead)
+        else {
+            return Ok(false);
+        };
+
+        delivery.status = DeliveryStatus::Pending;
+        delivery.attempts = 0;
+        delivery.next_attempt_at = Utc::now();
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,150 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{HttpResponse, Responder, web};
+use prometheus::{Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use crate::app_state::AppState;
+
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    pool_connections: IntGauge,
+    pool_idle_connections: IntGauge,
+    pool_max_connections: IntGauge,
+    pub route_search_results: Histogram,
+    pub route_search_duration: Histogram,
+    pub bookings_created: IntCounter,
+    pub check_ins_created: IntCounter,
+    pub webhook_deliveries: IntCounterVec,
+    pub rebuild_duration: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Metrics, prometheus::Error> {
+        let registry = Registry::new_custom(Some("flights".to_string()), None)?;
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route and status"),
+            &["method", "route", "status"]
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency by route"),
+            &["method", "route"]
+        )?;
+        let pool_connections = IntGauge::new("db_pool_connections", "Open database connections")?;
+        let pool_idle_connections = IntGauge::new("db_pool_idle_connections", "Idle database connections")?;
+        let pool_max_connections = IntGauge::new("db_pool_max_connections", "Database pool size limit")?;
+        let route_search_results = Histogram::with_opts(
+            HistogramOpts::new("route_search_results", "Itineraries found per route search")
+                .buckets(vec![0.0, 1.0, 5.0, 10.0, 50.0, 100.0, 500.0, 1000.0])
+        )?;
+        let route_search_duration = Histogram::with_opts(
+            HistogramOpts::new("route_search_duration_seconds", "Route search query time")
+        )?;
+        let bookings_created = IntCounter::new("bookings_created_total", "Bookings created")?;
+        let check_ins_created = IntCounter::new("check_ins_created_total", "Passengers checked in")?;
+        let webhook_deliveries = IntCounterVec::new(
+            Opts::new("webhook_deliveries_total", "Webhook delivery attempts by outcome"),
+            &["outcome"]
+        )?;
+        let rebuild_duration = HistogramVec::new(
+            HistogramOpts::new("rebuild_duration_seconds", "Duration of prices and seats rebuild jobs")
+                .buckets(vec![1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0]),
+            &["kind", "status"]
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(pool_connections.clone()))?;
+        registry.register(Box::new(pool_idle_connections.clone()))?;
+        registry.register(Box::new(pool_max_connections.clone()))?;
+        registry.register(Box::new(route_search_results.clone()))?;
+        registry.register(Box::new(route_search_duration.clone()))?;
+        registry.register(Box::new(bookings_created.clone()))?;
+        registry.register(Box::new(check_ins_created.clone()))?;
+        registry.register(Box::new(webhook_deliveries.clone()))?;
+        registry.register(Box::new(rebuild_duration.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            http_requests,
+            http_request_duration,
+            pool_connections,
+            pool_idle_connections,
+            pool_max_connections,
+            route_search_results,
+            route_search_duration,
+            bookings_created,
+            check_ins_created,
+            webhook_deliveries,
+            rebuild_duration,
+        })
+    }
+
+    /// `route` is the matched route pattern, so that path parameters don't blow up label cardinality.
+    pub fn observe_request(&self, method: &Method, route: Option<&str>, status: StatusCode, elapsed: Duration) {
+        let route = route.unwrap_or("unmatched");
+
+        self.http_requests
+            .with_label_values(&[method.as_str(), route, status.as_str()])
+            .inc();
+        self.http_request_duration
+            .with_label_values(&[method.as_str(), route])
+            .observe(elapsed.as_secs_f64());
+    }
+}
+
+/// Middleware function recording count and latency of every request by matched route.
+pub fn track_request<S, B>(req: ServiceRequest, srv: &S) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let timer = Instant::now();
+    let method = req.method().clone();
+    let route = req.match_pattern();
+    let state = req.app_data::<web::Data<AppState>>().cloned();
+
+    let response = srv.call(req);
+
+    async move {
+        let response = response.await;
+
+        let status = match &response {
+            Ok(r) => { r.status() }
+            Err(e) => { e.as_response_error().status_code() }
+        };
+
+        if let Some(state) = state {
+            state.metrics.observe_request(&method, route.as_deref(), status, timer.elapsed());
+        }
+
+        response
+    }
+}
+
+#[utoipa::path(
+    get, path = "/metrics", tag = "health",
+    responses((status = 200, description = "Prometheus text exposition", body = String, content_type = "text/plain"))
+)]
+pub async fn metrics(state: web::Data<AppState>) -> impl Responder {
+    let metrics = &state.metrics;
+
+    metrics.pool_connections.set(state.db_pool.size() as i64);
+    metrics.pool_idle_connections.set(state.db_pool.num_idle() as i64);
+    metrics.pool_max_connections.set(state.db_pool.options().get_max_connections() as i64);
+
+    let mut buffer = vec![];
+
+    match TextEncoder::new().encode(&metrics.registry.gather(), &mut buffer) {
+        Ok(()) => {
+            HttpResponse::Ok()
+                .content_type(prometheus::TEXT_FORMAT)
+                .body(buffer)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::str::FromStr;
+use actix_web::{HttpResponse, Responder, web};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::app_state::AppState;
+use crate::lang::{Lang, LangParam};
+use crate::repository::{AirportDetails, Coordinates, Repository, RepositoryResult};
+use crate::validation::{validate, Validate, ValidationErrors};
+
+pub const DEFAULT_RADIUS_KM: f64 = 200.0;
+pub const MAX_RADIUS_KM: f64 = 2000.0;
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Great-circle distance by the haversine formula
+pub fn distance_km(a: Coordinates, b: Coordinates) -> f64 {
+    let (lat_a, lat_b) = (a.latitude.to_radians(), b.latitude.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b.longitude - a.longitude).to_radians();
+
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_KM * h.sqrt().min(1.0).asin()
+}
+
+/// Circle around a point, written `latitude,longitude[,radius_km]` in route
+/// search, e.g. `55.75,37.62,200`.
+pub struct Area {
+    pub center: Coordinates,
+    pub radius_km: f64,
+}
+
+impl Area {
+    pub fn check(&self, errors: &mut ValidationErrors, field: &str) {
+        if !(-90.0..=90.0).contains(&self.center.latitude) {
+            errors.add(field, "Latitude must be between -90 and 90");
+        }
+
+        if !(-180.0..=180.0).contains(&self.center.longitude) {
+            errors.add(field, "Longitude must be between -180 and 180");
+        }
+
+        if !(self.radius_km > 0.0 && self.radius_km <= MAX_RADIUS_KM) {
+            errors.add(field, format!("Radius must be greater than 0 and at most {} km", MAX_RADIUS_KM));
+        }
+    }
+}
+
+impl FromStr for Area {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let numbers: Vec<f64> = s
+            .split(',')
+            .map(|x| x.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("{:?} is not latitude,longitude or latitude,longitude,radius_km", s))?;
+
+        match numbers[..] {
+            [latitude, longitude] => {
+                Ok(Area { center: Coordinates { latitude, longitude }, radius_km: DEFAULT_RADIUS_KM })
+            }
+            [latitude, longitude, radius_km] => {
+                Ok(Area { center: Coordinates { latitude, longitude }, radius_km })
+            }
+            _ => {
+                Err(format!("{:?} is not latitude,longitude or latitude,longitude,radius_km", s))
+            }
+        }
+    }
+}
+
+/// Airports inside the area with their distance to its center, nearest first
+pub async fn airports_within(area: &Area, repo: &dyn Repository) -> RepositoryResult<Vec<(f64, AirportDetails)>> {
+    let mut airports: Vec<(f64, AirportDetails)> = repo.airport_details()
+        .await?
+        .into_iter()
+        .map(|airport| (distance_km(area.center, airport.coordinates), airport))
+        .filter(|(distance, _)| *distance <= area.radius_km)
+        .collect();
+
+    airports.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.code.cmp(&b.1.code)));
+
+    Ok(airports)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NearbyAirportsParameters {
+    latitude: f64,
+    longitude: f64,
+    /// Search radius in kilometers, 200 by default
+    radius_km: Option<f64>,
+}
+
+impl NearbyAirportsParameters {
+    fn area(&self) -> Area {
+        Area {
+            center: Coordinates { latitude: self.latitude, longitude: self.longitude },
+            radius_km: self.radius_km.unwrap_or(DEFAULT_RADIUS_KM),
+        }
+    }
+}
+
+impl Validate for NearbyAirportsParameters {
+    async fn validate(&self, _: &AppState, errors: &mut ValidationErrors) -> RepositoryResult<()> {
+        if !(-90.0..=90.0).contains(&self.latitude) {
+            errors.add("latitude", "Must be between -90 and 90");
+        }
+
+        if !(-180.0..=180.0).contains(&self.longitude) {
+            errors.add("longitude", "Must be between -180 and 180");
+        }
+
+        if let Some(radius_km) = self.radius_km {
+            if !(radius_km > 0.0 && radius_km <= MAX_RADIUS_KM) {
+                errors.add("radius_km", format!("Must be greater than 0 and at most {}", MAX_RADIUS_KM));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct NearbyAirport {
+    code: String,
+    name: Option<String>,
+    city: Option<String>,
+    coordinates: Coordinates,
+    timezone: String,
+    /// Great-circle distance from the given point, rounded to 0.1 km
+    distance_km: f64,
+}
+
+#[utoipa::path(
+    get, path = "/api/airports/nearby", tag = "locations",
+    params(NearbyAirportsParameters, LangParam, ("Accept-Language" = Option<String>, Header)),
+    responses(
+        (status = 200, description = "Airports within the radius, nearest first", body = Vec<NearbyAirport>),
+        (status = 400, description = "Invalid fields", body = ValidationErrors)
+    )
+)]
+pub async fn nearby_airports(parameters: web::Query<NearbyAirportsParameters>, lang: Lang, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = validate(&*parameters, &state).await {
+        return response;
+    }
+
+    match airports_within(&parameters.area(), &*state.repo).await {
+        Ok(airports) => {
+            let result: Vec<NearbyAirport> = airports
+                .into_iter()
+                .map(|(distance, airport)| NearbyAirport {
+                    name: airport.name.get(lang),
+                    city: airport.city.get(lang),
+                    code: airport.code,
+                    coordinates: airport.coordinates,
+                    timezone: airport.timezone,
+                    distance_km: (distance * 10.0).round() / 10.0,
+                })
+                .collect();
+
+            HttpResponse::Ok().json(result)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+/// OpenAPI document of every route registered in `main`. Regenerate
+/// `openapi.json` with `UPDATE_OPENAPI=1 cargo test openapi` after changing handlers.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Flights booking API"),
+    paths(
+        crate::health::healthz,
+        crate::health::readyz,
+        crate::health::version,
+        crate::metrics::metrics,
+        crate::handlers::list_cities,
+        crate::handlers::list_all_airports,
+        crate::airport_search::search_airports,
+        crate::nearby::nearby_airports,
+        crate::handlers::list_airports_within_city,
+        crate::handlers::inbound_schedule,
+        crate::handlers::outbound_schedule,
+        crate::handlers::list_routes,
+        crate::explore::explore,
+        crate::flight_status::get_flight,
+        crate::flight_status::find_flights_by_number,
+        crate::board::airport_board,
+        crate::flight_events::flight_events_stream,
+        crate::flight_events::flight_events_ws,
+        crate::flight_ops::change_flight_status,
+        crate::flight_ops::flight_status_audit,
+        crate::reaccommodation::reaccommodate_passengers,
+        crate::quote::create_quote,
+        crate::handlers::create_booking,
+        crate::handlers::check_in,
+        crate::handlers::list_airport_details,
+        crate::prices::compute_prices,
+        crate::seats::compute_seats,
+        crate::rebuild::rebuild_status,
+        crate::fares::list_fares,
+        crate::fares::create_fare,
+        crate::fares::export_fares,
+        crate::fares::import_fares,
+        crate::fares::update_fare,
+        crate::fares::delete_fare,
+        crate::pricing::list_pricing_rules,
+        crate::pricing::replace_pricing_rules,
+        crate::cabin_layouts::get_cabin_layout,
+        crate::cabin_layouts::replace_cabin_layout,
+        crate::cabin_layouts::delete_cabin_layout,
+        crate::cabin_layouts::preview_cabin_layout,
+        crate::cabin_layouts::apply_cabin_layout,
+        crate::webhooks::list_webhooks,
+        crate::webhooks::create_webhook,
+        crate::webhooks::delete_webhook,
+        crate::webhooks::list_dead_letters,
+        crate::webhooks::retry_dead_letter,
+    ),
+    modifiers(&BearerAuth),
+    tags(
+        (name = "health", description = "Probes, version and metrics"),
+        (name = "locations", description = "Cities, airports and schedules"),
+        (name = "search", description = "Itinerary search"),
+        (name = "flights", description = "Flight status and details, airport boards and live flight events"),
+        (name = "booking", description = "Quotes, bookings and check-in"),
+        (name = "operations", description = "Flight status changes, their audit and re-accommodation of passengers, `Operations` role and above"),
+        (name = "admin", description = "Airports, fares, pricing rules, cabin layouts, rebuilds and webhooks, `Admin` role only"),
+    )
+)]
+pub struct ApiDoc;
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme("bearer", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App, HttpResponse};
+    use jsonwebtoken::{EncodingKey, Header};
+    use sqlx::postgres::PgPoolOptions;
+    use utoipa::OpenApi;
+    use crate::app_state::AppState;
+    use crate::auth::{Claims, Role};
+    use crate::config::Config;
+    use crate::flight_events::FlightEvents;
+    use crate::memory_repository::MemoryRepository;
+    use crate::metrics::Metrics;
+    use crate::rebuild::RebuildJobs;
+    use super::ApiDoc;
+
+    const SPEC_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+    #[test]
+    fn openapi_json_is_up_to_date() {
+        let generated = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(SPEC_PATH, &generated).unwrap();
+        }
+
+        let committed = std::fs::read_to_string(SPEC_PATH).unwrap_or_default();
+
+        assert!(
+            committed == generated,
+            "openapi.json is out of date, run `UPDATE_OPENAPI=1 cargo test openapi` and commit the result"
+        );
+    }
+
+    /// Answered by the test app for paths no route matches
+    const UNROUTED: StatusCode = StatusCode::NOT_IMPLEMENTED;
+
+    /// Every documented operation must hit a registered route: a typo in a
+    /// path gets the default service and a wrong method gets `405` from the resource.
+    #[actix_web::test]
+    async fn documented_operations_are_routed() {
+        let mut cfg = Config::default();
+        cfg.auth.jwt_secret = "secret".to_string();
+
+        // Never connected to, handlers needing the database fail with 500
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(std::time::Duration::from_millis(100))
+            .connect_lazy("postgres://localhost:1/none")
+            .unwrap();
+
+        let state = web::Data::new(AppState {
+            db_pool: pool,
+            repo: Arc::new(MemoryRepository::default()),
+            cfg,
+            rebuild_jobs: RebuildJobs::default(),
+            metrics: Metrics::new().unwrap(),
+            flight_events: FlightEvents::default(),
+        });
+
+        let token = jsonwebtoken::encode(
+            &Header::default(),
+            &Claims { sub: "test".to_string(), role: Role::Admin, exp: usize::MAX },
+            &EncodingKey::from_secret(b"secret")
+        ).unwrap();
+
+        let app = init_service(
+            App::new()
+                .app_data(state)
+                .configure(crate::routes)
+                .default_service(web::to(|| async { HttpResponse::new(UNROUTED) }))
+        ).await;
+
+        for (path, item) in ApiDoc::openapi().paths.paths {
+            let uri = path.replace(['{', '}'], "");
+
+            let operations = [
+                (Method::GET, &item.get),
+                (Method::POST, &item.post),
+                (Method::PUT, &item.put),
+                (Method::DELETE, &item.delete),
+            ];
+
+            for (method, _) in operations.into_iter().filter(|(_, op)| op.is_some()) {
+                let req = TestRequest::default()
+                    .method(method.clone())
+                    .uri(&uri)
+                    .insert_header(("Authorization", format!("Bearer {}", token)))
+                    .to_request();
+
+                let status = call_service(&app, req).await.status();
+
+                assert!(
+                    status != UNROUTED && status != StatusCode::METHOD_NOT_ALLOWED,
+                    "{} {} is documented but not routed ({})", method, path, status
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,1198 @@
+use std::collections::{HashMap, HashSet};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use sqlx::types::{Decimal, Json};
+use tracing::Instrument;
+use crate::find_flights::{explore_destinations, find_flights, ExploreRecord, FlightRecord};
+use crate::lang::Lang;
+use crate::pricing::{PricingRule, RuleKind};
+use crate::repository::{Aircraft, Airport, AirportDetails, AirportRepository, BoardingPass, BoardingPassRepository, BookingRepository, Coordinates, Fare, FareRepository, FlightAuditEntry, FlightDetails, FlightEndpoint, FlightLeg, FlightRepository, FlightSearch, FlightStatusChange, FlightTimes, InboundRoute, Names, NewBooking, OutboundRoute, PricingInputs, Rebooking, RepositoryResult, SeatsAvailable, TicketItinerary, WebhookRepository};
+use crate::types::{BoardDirection, BookingClass, FlightState};
+use crate::webhooks::{DeadLetter, DeliveryOutcome, NewWebhook, OutboxEvent, PendingDelivery, Webhook, WebhookEventType};
+
+/// Statuses outside the `flights` check constraint are reported as decoding errors
+fn flight_state(status: &str) -> Result<FlightState, sqlx::Error> {
+    FlightState::try_from(status).map_err(|e| sqlx::Error::Decode(e.into()))
+}
+
+/// Fare classes outside the ones the application knows are reported as decoding errors
+fn booking_class(fare_conditions: &str) -> Result<BookingClass, sqlx::Error> {
+    BookingClass::try_from(fare_conditions).map_err(|e| sqlx::Error::Decode(e.into()))
+}
+
+/// Writes the event in the transaction of the change, the outbox trigger fans it out to the subscribed webhooks
+async fn insert_outbox_event(transaction: &mut Transaction<'_, Postgres>, event: OutboxEvent) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "
+        INSERT INTO webhook_outbox (event_type, payload)
+        VALUES      ($1, $2);
+        ",
+        event.event_type.as_str(), event.payload
+    )
+        .execute(&mut **transaction)
+        .instrument(tracing::info_span!("sql", query = "insert_webhook_event"))
+        .await?;
+
+    Ok(())
+}
+
+pub struct PgRepository {
+    pool: PgPool,
+}
+
+impl PgRepository {
+    pub fn new(pool: PgPool) -> PgRepository {
+        PgRepository { pool }
+    }
+}
+
+#[async_trait]
+impl AirportRepository for PgRepository {
+    async fn cities(&self, lang: Lang) -> RepositoryResult<Vec<String>> {
+        let cities = sqlx::query!(
+            "SELECT DISTINCT city ->> $1 AS city FROM airports_data ORDER BY 1",
+            lang.code()
+        )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .filter_map(|x| x.city)
+            .collect();
+
+        Ok(cities)
+    }
+
+    async fn airports(&self, lang: Lang) -> RepositoryResult<Vec<Airport>> {
+        let airports = sqlx::query_as!(
+            Airport,
+            "SELECT airport_name ->> $1 AS name, airport_code AS \"code?\" FROM airports_data ORDER BY airport_code",
+            lang.code()
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(airports)
+    }
+
+    async fn airports_in_city(&self, city: &str, lang: Lang) -> RepositoryResult<Vec<Airport>> {
+        let airports = sqlx::query_as!(
+            Airport,
+            "
+            SELECT airport_name ->> $2 AS name, airport_code AS \"code?\"
+            FROM airports_data
+            WHERE EXISTS(SELECT 1 FROM jsonb_each_text(city) WHERE value = $1)
+            ORDER BY airport_code
+            ",
+            city, lang.code()
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(airports)
+    }
+
+    async fn airport_details(&self) -> RepositoryResult<Vec<AirportDetails>> {
+        let airports = sqlx::query!(
+            "
+            SELECT
+                airport_code,
+                airport_name ->> 'en' AS name_en,
+                airport_name ->> 'ru' AS name_ru,
+                city ->> 'en' AS city_en,
+                city ->> 'ru' AS city_ru,
+                coordinates[1] AS \"latitude!\",
+                coordinates[0] AS \"longitude!\",
+                timezone
+            FROM airports_data
+            ORDER BY airport_code
+            "
+        )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|x| AirportDetails {
+                code: x.airport_code,
+                name: Names { en: x.name_en, ru: x.name_ru },
+                city: Names { en: x.city_en, ru: x.city_ru },
+                coordinates: Coordinates { latitude: x.latitude, longitude: x.longitude },
+                timezone: x.timezone,
+            })
+            .collect();
+
+        Ok(airports)
+    }
+
+    async fn airport_exists(&self, code: &str) -> RepositoryResult<bool> {
+        let exists = sqlx::query!(
+            "SELECT EXISTS(SELECT 1 FROM airports WHERE airport_code = $1) AS exists",
+            code
+        )
+            .fetch_one(&self.pool)
+            .await?
+            .exists
+            .unwrap_or(false);
+
+        Ok(exists)
+    }
+
+    async fn city_exists(&self, city: &str) -> RepositoryResult<bool> {
+        let exists = sqlx::query!(
+            "
+            SELECT EXISTS(
+                SELECT 1 FROM airports_data, jsonb_each_text(city)
+                WHERE value = $1
+            ) AS exists
+            ",
+            city
+        )
+            .fetch_one(&self.pool)
+            .await?
+            .exists
+            .unwrap_or(false);
+
+        Ok(exists)
+    }
+}
+
+#[async_trait]
+impl FlightRepository for PgRepository {
+    async fn now(&self) -> RepositoryResult<DateTime<Utc>> {
+        let now = sqlx::query!("SELECT bookings.now() AS \"now!\"")
+            .fetch_one(&self.pool)
+            .await?
+            .now;
+
+        Ok(now)
+    }
+
+    async fn departure_date_range(&self) -> RepositoryResult<Option<(NaiveDate, NaiveDate)>> {
+        let range = sqlx::query!(
+            "
+            SELECT
+                (min(scheduled_departure) AT TIME ZONE 'UTC')::DATE AS first_date,
+                (max(scheduled_departure) AT TIME ZONE 'UTC')::DATE AS last_date
+            FROM flights
+            "
+        )
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(range.first_date.zip(range.last_date))
+    }
+
+    async fn inbound_schedule(&self, airport_code: &str) -> RepositoryResult<Vec<InboundRoute>> {
+        let routes = sqlx::query_as!(
+            InboundRoute,
+            "
+            SELECT DISTINCT
+                flights_v.flight_no AS flight_no,
+                days_of_week,
+                scheduled_arrival::TIME AS arrival_time,
+                flights_v.departure_airport AS origin
+            FROM flights_v
+            JOIN routes ON flights_v.flight_no = routes.flight_no
+            WHERE flights_v.arrival_airport=$1
+            ",
+            airport_code
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(routes)
+    }
+
+    async fn outbound_schedule(&self, airport_code: &str) -> RepositoryResult<Vec<OutboundRoute>> {
+        let routes = sqlx::query_as!(
+            OutboundRoute,
+            "
+            SELECT DISTINCT
+                flights_v.flight_no AS flight_no,
+                days_of_week,
+                scheduled_departure::TIME AS departure_time,
+                flights_v.arrival_airport AS destination
+            FROM flights_v
+            JOIN routes ON flights_v.flight_no = routes.flight_no
+            WHERE flights_v.departure_airport=$1
+            ",
+            airport_code
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(routes)
+    }
+
+    async fn find_flights(&self, search: &FlightSearch) -> RepositoryResult<Vec<FlightRecord>> {
+        Ok(find_flights(search, &self.pool).await?)
+    }
+
+    async fn explore_destinations(&self, search: &FlightSearch, limit: i64) -> RepositoryResult<Vec<ExploreRecord>> {
+        Ok(explore_destinations(search, limit, &self.pool).await?)
+    }
+
+    async fn flights(&self, flight_ids: &[i32]) -> RepositoryResult<Vec<FlightLeg>> {
+        let flights = sqlx::query_as!(
+            FlightLeg,
+            "
+            SELECT flight_id, departure_airport, arrival_airport, scheduled_departure, scheduled_arrival
+            FROM flights
+            WHERE flight_id = ANY($1::INT[])
+            ",
+            flight_ids
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(flights)
+    }
+
+    async fn flight_details(&self, flight_ids: &[i32], lang: Lang) -> RepositoryResult<Vec<FlightDetails>> {
+        let mut seats: HashMap<i32, Vec<SeatsAvailable>> = HashMap::new();
+
+        let rows = sqlx::query!(
+            "
+            SELECT
+                f.flight_id,
+                cabin.fare_conditions,
+                free_seats(occupied_seats(f.flight_id, cabin.fare_conditions), f.aircraft_code, cabin.fare_conditions) AS \"seats!\"
+            FROM flights f
+                CROSS JOIN LATERAL (
+                    SELECT DISTINCT fare_conditions FROM seats_comfort
+                    WHERE seats_comfort.aircraft_code = f.aircraft_code
+                ) AS cabin
+            WHERE f.flight_id = ANY($1::INT[])
+            ORDER BY f.flight_id, cabin.fare_conditions
+            ",
+            flight_ids
+        )
+            .fetch_all(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "seats_available"))
+            .await?;
+
+        for row in rows {
+            if let Ok(fare_conditions) = BookingClass::try_from(row.fare_conditions.as_str()) {
+                seats.entry(row.flight_id).or_default().push(SeatsAvailable { fare_conditions, seats: row.seats });
+            }
+        }
+
+        let flights = sqlx::query!(
+            "
+            SELECT
+                f.flight_id AS \"flight_id!\",
+                f.flight_no AS \"flight_no!\",
+                f.status AS \"status!\",
+                f.departure_airport AS \"departure_airport!\",
+                dep.airport_name ->> $2 AS departure_airport_name,
+                dep.city ->> $2 AS departure_city,
+                dep.timezone AS departure_timezone,
+                f.scheduled_departure AS \"scheduled_departure!\",
+                f.scheduled_departure_local AS \"scheduled_departure_local!\",
+                times.estimated_departure,
+                timezone(dep.timezone, times.estimated_departure) AS estimated_departure_local,
+                f.actual_departure,
+                f.actual_departure_local,
+                f.arrival_airport AS \"arrival_airport!\",
+                arr.airport_name ->> $2 AS arrival_airport_name,
+                arr.city ->> $2 AS arrival_city,
+                arr.timezone AS arrival_timezone,
+                f.scheduled_arrival AS \"scheduled_arrival!\",
+                f.scheduled_arrival_local AS \"scheduled_arrival_local!\",
+                times.estimated_arrival,
+                timezone(arr.timezone, times.estimated_arrival) AS estimated_arrival_local,
+                f.actual_arrival,
+                f.actual_arrival_local,
+                f.aircraft_code AS \"aircraft_code!\",
+                aircraft.model ->> $2 AS model,
+                aircraft.range
+            FROM flights_v f
+                JOIN flights times ON times.flight_id = f.flight_id
+                JOIN airports_data dep ON dep.airport_code = f.departure_airport
+                JOIN airports_data arr ON arr.airport_code = f.arrival_airport
+                JOIN aircrafts_data aircraft ON aircraft.aircraft_code = f.aircraft_code
+            WHERE f.flight_id = ANY($1::INT[])
+            ORDER BY f.scheduled_departure, f.flight_id
+            ",
+            flight_ids, lang.code()
+        )
+            .fetch_all(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "flight_details"))
+            .await?
+            .into_iter()
+            .map(|x| FlightDetails {
+                seats_available: seats.remove(&x.flight_id).unwrap_or_default(),
+                flight_id: x.flight_id,
+                flight_no: x.flight_no,
+                status: x.status,
+                departure: FlightEndpoint {
+                    airport_code: x.departure_airport,
+                    airport_name: x.departure_airport_name,
+                    city: x.departure_city,
+                    timezone: x.departure_timezone,
+                    scheduled: x.scheduled_departure,
+                    scheduled_local: x.scheduled_departure_local,
+                    estimated: x.estimated_departure,
+                    estimated_local: x.estimated_departure_local,
+                    actual: x.actual_departure,
+                    actual_local: x.actual_departure_local,
+                },
+                arrival: FlightEndpoint {
+                    airport_code: x.arrival_airport,
+                    airport_name: x.arrival_airport_name,
+                    city: x.arrival_city,
+                    timezone: x.arrival_timezone,
+                    scheduled: x.scheduled_arrival,
+                    scheduled_local: x.scheduled_arrival_local,
+                    estimated: x.estimated_arrival,
+                    estimated_local: x.estimated_arrival_local,
+                    actual: x.actual_arrival,
+                    actual_local: x.actual_arrival_local,
+                },
+                aircraft: Aircraft {
+                    code: x.aircraft_code,
+                    model: x.model,
+                    range: x.range,
+                },
+            })
+            .collect();
+
+        Ok(flights)
+    }
+
+    async fn flight_ids_by_number(&self, flight_no: &str, date: NaiveDate) -> RepositoryResult<Vec<i32>> {
+        let flight_ids = sqlx::query!(
+            "
+            SELECT flight_id AS \"flight_id!\" FROM flights_v
+            WHERE flight_no = $1 AND scheduled_departure_local::DATE = $2
+            ORDER BY scheduled_departure
+            ",
+            flight_no, date
+        )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|x| x.flight_id)
+            .collect();
+
+        Ok(flight_ids)
+    }
+
+    async fn board_flight_ids(&self, airport_code: &str, direction: BoardDirection, from: DateTime<Utc>, to: DateTime<Utc>) -> RepositoryResult<Vec<i32>> {
+        let flight_ids = sqlx::query!(
+            "
+            SELECT flight_id FROM flights
+            WHERE ($2 AND departure_airport = $1 AND (scheduled_departure BETWEEN $3 AND $4 OR estimated_departure BETWEEN $3 AND $4 OR actual_departure BETWEEN $3 AND $4))
+               OR (NOT $2 AND arrival_airport = $1 AND (scheduled_arrival BETWEEN $3 AND $4 OR estimated_arrival BETWEEN $3 AND $4 OR actual_arrival BETWEEN $3 AND $4))
+            ",
+            airport_code, direction == BoardDirection::Departures, from, to
+        )
+            .fetch_all(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "board_flight_ids"))
+            .await?
+            .into_iter()
+            .map(|x| x.flight_id)
+            .collect();
+
+        Ok(flight_ids)
+    }
+
+    async fn flight_times(&self, flight_id: i32) -> RepositoryResult<Option<FlightTimes>> {
+        let row = sqlx::query!(
+            "
+            SELECT status, scheduled_departure, scheduled_arrival, estimated_departure, estimated_arrival, actual_departure, actual_arrival
+            FROM flights
+            WHERE flight_id = $1
+            ",
+            flight_id
+        )
+            .fetch_optional(&self.pool)
+            .await?;
+
+        let times = match row {
+            Some(x) => {
+                Some(FlightTimes {
+                    status: flight_state(&x.status)?,
+                    scheduled_departure: x.scheduled_departure,
+                    scheduled_arrival: x.scheduled_arrival,
+                    estimated_departure: x.estimated_departure,
+                    estimated_arrival: x.estimated_arrival,
+                    actual_departure: x.actual_departure,
+                    actual_arrival: x.actual_arrival,
+                })
+            }
+            None => { None }
+        };
+
+        Ok(times)
+    }
+
+    async fn change_flight_status(&self, change: &FlightStatusChange) -> RepositoryResult<bool> {
+        let times = &change.times;
+
+        let mut transaction = self.pool.begin().await?;
+
+        let updated = sqlx::query!(
+            "
+            UPDATE flights
+            SET status = $3, estimated_departure = $4, estimated_arrival = $5, actual_departure = $6, actual_arrival = $7
+            WHERE flight_id = $1 AND status = $2
+            ",
+            change.flight_id, change.previous_status.as_str(), times.status.as_str(),
+            times.estimated_departure, times.estimated_arrival, times.actual_departure, times.actual_arrival
+        )
+            .execute(&mut *transaction)
+            .instrument(tracing::info_span!("sql", query = "update_flight_status"))
+            .await?
+            .rows_affected();
+
+        if updated == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            "
+            INSERT INTO flight_status_audit (flight_id, changed_by, previous_status, status, estimated_departure, estimated_arrival, actual_departure, actual_arrival, reason)
+            VALUES      ($1, $2, $3, $4, $5, $6, $7, $8, $9);
+            ",
+            change.flight_id, change.changed_by, change.previous_status.as_str(), times.status.as_str(),
+            times.estimated_departure, times.estimated_arrival, times.actual_departure, times.actual_arrival, change.reason
+        )
+            .execute(&mut *transaction)
+            .instrument(tracing::info_span!("sql", query = "insert_flight_status_audit"))
+            .await?;
+
+        insert_outbox_event(&mut transaction, OutboxEvent::flight_status_changed(change)).await?;
+
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn flight_status_audit(&self, flight_id: i32) -> RepositoryResult<Vec<FlightAuditEntry>> {
+        let rows = sqlx::query!(
+            "
+            SELECT audit_id, flight_id, changed_at, changed_by, previous_status, status,
+                   estimated_departure, estimated_arrival, actual_departure, actual_arrival, reason
+            FROM flight_status_audit
+            WHERE flight_id = $1
+            ORDER BY audit_id
+            ",
+            flight_id
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut entries = Vec::with_capacity(rows.len());
+
+        for x in rows {
+            entries.push(FlightAuditEntry {
+                audit_id: x.audit_id,
+                flight_id: x.flight_id,
+                changed_at: x.changed_at,
+                changed_by: x.changed_by,
+                previous_status: flight_state(&x.previous_status)?,
+                status: flight_state(&x.status)?,
+                estimated_departure: x.estimated_departure,
+                estimated_arrival: x.estimated_arrival,
+                actual_departure: x.actual_departure,
+                actual_arrival: x.actual_arrival,
+                reason: x.reason,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    async fn flight_numbers(&self) -> RepositoryResult<HashSet<String>> {
+        let flight_numbers = sqlx::query!("SELECT DISTINCT flight_no FROM flights")
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|x| x.flight_no)
+            .collect();
+
+        Ok(flight_numbers)
+    }
+
+    async fn pricing_inputs(&self, flight_ids: &[i32], booking_class: BookingClass) -> RepositoryResult<HashMap<i32, PricingInputs>> {
+        let inputs = sqlx::query!(
+            "
+            SELECT
+                flights_v.flight_id,
+                prices.amount AS base_amount,
+                occupied_seats(flights_v.flight_id, $2) AS occupied_seats,
+                (
+                    SELECT count(*) FROM seats_comfort
+                    WHERE seats_comfort.aircraft_code = flights_v.aircraft_code
+                      AND seats_comfort.fare_conditions = $2
+                )::INT AS capacity,
+                date_part('day', flights_v.scheduled_departure - bookings.now())::INT AS days_to_departure,
+                date_part('isodow', flights_v.scheduled_departure_local)::INT AS weekday
+            FROM flights_v
+            JOIN prices ON flights_v.flight_no = prices.flight_no
+            WHERE flights_v.flight_id = ANY($1::INT[])
+              AND prices.fare_conditions = $2
+            ",
+            flight_ids,
+            String::from(booking_class)
+        )
+            .fetch_all(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "pricing_inputs"))
+            .await?
+            .into_iter()
+            .filter_map(|x| Some((x.flight_id?, PricingInputs {
+                base_amount: x.base_amount,
+                occupied_seats: x.occupied_seats.unwrap_or(0),
+                capacity: x.capacity.unwrap_or(0),
+                days_to_departure: x.days_to_departure.unwrap_or(0),
+                weekday: x.weekday.unwrap_or(0),
+            })))
+            .collect();
+
+        Ok(inputs)
+    }
+}
+
+#[async_trait]
+impl FareRepository for PgRepository {
+    async fn fares(&self, flight_no: Option<&str>) -> RepositoryResult<Vec<Fare>> {
+        let fares = sqlx::query!(
+            "
+            SELECT flight_no, fare_conditions, amount
+            FROM prices
+            WHERE $1::CHAR(6) IS NULL OR flight_no = $1
+            ORDER BY flight_no, fare_conditions
+            ",
+            flight_no
+        )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|x| Ok(Fare {
+                flight_no: x.flight_no,
+                fare_conditions: booking_class(&x.fare_conditions)?,
+                amount: x.amount,
+            }))
+            .collect::<Result<Vec<Fare>, sqlx::Error>>()?;
+
+        Ok(fares)
+    }
+
+    async fn create_fare(&self, fare: &Fare) -> RepositoryResult<()> {
+        sqlx::query!(
+            "
+            INSERT INTO prices (flight_no, fare_conditions, amount, managed)
+            VALUES      ($1, $2, $3, true);
+            ",
+            fare.flight_no, String::from(fare.fare_conditions), fare.amount
+        )
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn update_fare(&self, fare: &Fare) -> RepositoryResult<bool> {
+        let result = sqlx::query!(
+            "
+            UPDATE prices
+            SET amount = $3, managed = true
+            WHERE flight_no = $1 AND fare_conditions = $2
+            ",
+            fare.flight_no, String::from(fare.fare_conditions), fare.amount
+        )
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_fare(&self, flight_no: &str, fare_conditions: BookingClass) -> RepositoryResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM prices WHERE flight_no = $1 AND fare_conditions = $2",
+            flight_no, String::from(fare_conditions)
+        )
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn upsert_fares(&self, fares: &[Fare]) -> RepositoryResult<u64> {
+        let flight_nos: Vec<String> = fares.iter().map(|f| f.flight_no.clone()).collect();
+        let fare_conditions: Vec<String> = fares.iter().map(|f| String::from(f.fare_conditions)).collect();
+        let amounts: Vec<i32> = fares.iter().map(|f| f.amount).collect();
+
+        let result = sqlx::query!(
+            "
+            INSERT INTO prices (flight_no, fare_conditions, amount, managed)
+            SELECT *, true FROM UNNEST($1::CHAR(6)[], $2::VARCHAR[], $3::INT[])
+            ON CONFLICT (flight_no, fare_conditions) DO UPDATE SET amount = EXCLUDED.amount, managed = true
+            ",
+            flight_nos.as_slice(), fare_conditions.as_slice(), amounts.as_slice()
+        )
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn pricing_rules(&self) -> RepositoryResult<Vec<PricingRule>> {
+        let rules = sqlx::query!(
+            "SELECT kind, min_value, max_value, multiplier FROM pricing_rules ORDER BY rule_id"
+        )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .filter_map(|x| Some(PricingRule {
+                kind: RuleKind::try_from(x.kind.as_str()).ok()?,
+                min_value: x.min_value,
+                max_value: x.max_value,
+                multiplier: x.multiplier,
+            }))
+            .collect();
+
+        Ok(rules)
+    }
+
+    async fn replace_pricing_rules(&self, rules: &[PricingRule]) -> RepositoryResult<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!("DELETE FROM pricing_rules").execute(&mut *transaction).await?;
+
+        for rule in rules {
+            sqlx::query!(
+                "
+                INSERT INTO pricing_rules (kind, min_value, max_value, multiplier)
+                VALUES      ($1, $2, $3, $4);
+                ",
+                String::from(rule.kind), rule.min_value, rule.max_value, rule.multiplier
+            )
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BookingRepository for PgRepository {
+    async fn create_booking(&self, booking: &NewBooking) -> RepositoryResult<()> {
+        let total_price: i32 = booking.legs.iter().map(|q| q.amount).sum();
+
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!(
+            "
+            INSERT INTO bookings (book_ref, book_date, total_amount)
+            VALUES      ($1, bookings.now(), $2);
+            ",
+            booking.book_ref, Decimal::from(total_price)
+        )
+            .execute(&mut *transaction)
+            .instrument(tracing::info_span!("sql", query = "insert_booking"))
+            .await?;
+
+        sqlx::query!(
+            "
+            INSERT INTO tickets (ticket_no, book_ref, passenger_id, passenger_name)
+            VALUES      ($1, $2, $3, $4);
+            ",
+            booking.ticket_no, booking.book_ref, booking.passenger_id, booking.passenger_name
+        )
+            .execute(&mut *transaction)
+            .instrument(tracing::info_span!("sql", query = "insert_ticket"))
+            .await?;
+
+        for quote in &booking.legs {
+            sqlx::query!(
+                "
+                INSERT INTO ticket_flights (ticket_no, flight_id, fare_conditions, amount)
+                VALUES      ($1, $2, $3, $4);
+                ",
+                booking.ticket_no, quote.flight_id, String::from(booking.fare_conditions), Decimal::from(quote.amount)
+            )
+                .execute(&mut *transaction)
+                .instrument(tracing::info_span!("sql", query = "insert_ticket_flight"))
+                .await?;
+
+            sqlx::query!(
+                "
+                INSERT INTO ticket_flight_pricing (ticket_no, flight_id, base_amount, amount, applied_rules)
+                VALUES      ($1, $2, $3, $4, $5);
+                ",
+                booking.ticket_no, quote.flight_id, quote.base_amount, quote.amount, Json(&quote.applied_rules) as _
+            )
+                .execute(&mut *transaction)
+                .instrument(tracing::info_span!("sql", query = "insert_ticket_flight_pricing"))
+                .await?;
+        }
+
+        insert_outbox_event(&mut transaction, OutboxEvent::booking_created(booking)).await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn ticket_fare_conditions(&self, ticket_no: &str, flight_id: i32) -> RepositoryResult<Option<BookingClass>> {
+        let fare_conditions = sqlx::query!(
+            "
+            SELECT fare_conditions FROM ticket_flights
+            WHERE ticket_no = $1 AND flight_id = $2;
+            ",
+            ticket_no, flight_id
+        )
+            .fetch_optional(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "ticket_fare_conditions"))
+            .await?
+            .and_then(|x| BookingClass::try_from(x.fare_conditions.as_str()).ok());
+
+        Ok(fare_conditions)
+    }
+
+    async fn tickets_on_flight(&self, flight_id: i32) -> RepositoryResult<Vec<TicketItinerary>> {
+        let rows = sqlx::query!(
+            "
+            SELECT
+                t.ticket_no,
+                t.passenger_name,
+                own.fare_conditions,
+                f.flight_id,
+                f.departure_airport,
+                f.arrival_airport,
+                f.scheduled_departure,
+                f.scheduled_arrival
+            FROM ticket_flights own
+                JOIN tickets t ON t.ticket_no = own.ticket_no
+                JOIN ticket_flights leg ON leg.ticket_no = own.ticket_no
+                JOIN flights f ON f.flight_id = leg.flight_id
+            WHERE own.flight_id = $1
+            ORDER BY t.ticket_no, f.scheduled_departure
+            ",
+            flight_id
+        )
+            .fetch_all(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "tickets_on_flight"))
+            .await?;
+
+        let mut tickets: Vec<TicketItinerary> = vec![];
+
+        for x in rows {
+            let leg = FlightLeg {
+                flight_id: x.flight_id,
+                departure_airport: x.departure_airport,
+                arrival_airport: x.arrival_airport,
+                scheduled_departure: x.scheduled_departure,
+                scheduled_arrival: x.scheduled_arrival,
+            };
+
+            match tickets.last_mut() {
+                Some(ticket) if ticket.ticket_no == x.ticket_no => { ticket.legs.push(leg) }
+                _ => {
+                    let Ok(fare_conditions) = BookingClass::try_from(x.fare_conditions.as_str()) else { continue; };
+
+                    tickets.push(TicketItinerary {
+                        ticket_no: x.ticket_no,
+                        passenger_name: x.passenger_name,
+                        fare_conditions,
+                        legs: vec![leg],
+                    });
+                }
+            }
+        }
+
+        Ok(tickets)
+    }
+
+    async fn rebook_ticket(&self, rebooking: &Rebooking) -> RepositoryResult<bool> {
+        let fare_conditions = String::from(rebooking.fare_conditions);
+
+        let mut transaction = self.pool.begin().await?;
+
+        // Locking the new flights serializes concurrent rebookings onto them
+        let free = sqlx::query!(
+            "
+            SELECT free_seats(occupied_seats(flight_id, $2), aircraft_code, $2) AS \"seats!\"
+            FROM flights
+            WHERE flight_id = ANY($1::INT[])
+            FOR UPDATE
+            ",
+            &rebooking.added, fare_conditions
+        )
+            .fetch_all(&mut *transaction)
+            .instrument(tracing::info_span!("sql", query = "lock_rebooked_flights"))
+            .await?;
+
+        if free.len() != rebooking.added.len() || free.iter().any(|x| x.seats < 1) {
+            return Ok(false);
+        }
+
+        let paid = sqlx::query_scalar!(
+            "
+            SELECT COALESCE(sum(amount), 0) AS \"amount!\" FROM ticket_flights
+            WHERE ticket_no = $1 AND flight_id = ANY($2::INT[])
+            ",
+            rebooking.ticket_no, &rebooking.removed
+        )
+            .fetch_one(&mut *transaction)
+            .await?;
+
+        // Pricing trail of the removed legs, carried over to the new ones
+        let pricing = sqlx::query!(
+            "
+            SELECT sum(base_amount)::INT AS base_amount,
+                   sum(amount)::INT AS amount,
+                   (SELECT COALESCE(jsonb_agg(rule), '[]')
+                    FROM ticket_flight_pricing p, jsonb_array_elements(p.applied_rules) AS rule
+                    WHERE p.ticket_no = $1 AND p.flight_id = ANY($2::INT[])) AS applied_rules
+            FROM ticket_flight_pricing
+            WHERE ticket_no = $1 AND flight_id = ANY($2::INT[])
+            ",
+            rebooking.ticket_no, &rebooking.removed
+        )
+            .fetch_one(&mut *transaction)
+            .await?;
+
+        sqlx::query!(
+            "DELETE FROM boarding_passes WHERE ticket_no = $1 AND flight_id = ANY($2::INT[])",
+            rebooking.ticket_no, &rebooking.removed
+        )
+            .execute(&mut *transaction)
+            .await?;
+
+        sqlx::query!(
+            "DELETE FROM ticket_flight_pricing WHERE ticket_no = $1 AND flight_id = ANY($2::INT[])",
+            rebooking.ticket_no, &rebooking.removed
+        )
+            .execute(&mut *transaction)
+            .await?;
+
+        sqlx::query!(
+            "DELETE FROM ticket_flights WHERE ticket_no = $1 AND flight_id = ANY($2::INT[])",
+            rebooking.ticket_no, &rebooking.removed
+        )
+            .execute(&mut *transaction)
+            .instrument(tracing::info_span!("sql", query = "delete_rebooked_legs"))
+            .await?;
+
+        let legs = Decimal::from(rebooking.added.len());
+        let share = (paid / legs).round_dp(2);
+
+        for (i, flight_id) in rebooking.added.iter().enumerate() {
+            // The last leg takes the rounding remainder
+            let is_last = i + 1 == rebooking.added.len();
+            let amount = if is_last { paid - share * (legs - Decimal::ONE) } else { share };
+
+            sqlx::query!(
+                "
+                INSERT INTO ticket_flights (ticket_no, flight_id, fare_conditions, amount)
+                VALUES      ($1, $2, $3, $4);
+                ",
+                rebooking.ticket_no, flight_id, fare_conditions, amount
+            )
+                .execute(&mut *transaction)
+                .instrument(tracing::info_span!("sql", query = "insert_ticket_flight"))
+                .await?;
+
+            // Tickets booked before dynamic pricing have no trail to carry over
+            if let (Some(base_amount), Some(priced_amount)) = (pricing.base_amount, pricing.amount) {
+                let split = |total: i32| {
+                    let share = total / rebooking.added.len() as i32;
+                    if is_last { total - share * (rebooking.added.len() as i32 - 1) } else { share }
+                };
+
+                sqlx::query!(
+                    "
+                    INSERT INTO ticket_flight_pricing (ticket_no, flight_id, base_amount, amount, applied_rules)
+                    VALUES      ($1, $2, $3, $4, $5);
+                    ",
+                    rebooking.ticket_no, flight_id, split(base_amount), split(priced_amount), pricing.applied_rules
+                )
+                    .execute(&mut *transaction)
+                    .instrument(tracing::info_span!("sql", query = "insert_ticket_flight_pricing"))
+                    .await?;
+            }
+        }
+
+        insert_outbox_event(&mut transaction, OutboxEvent::ticket_rebooked(rebooking)).await?;
+
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl BoardingPassRepository for PgRepository {
+    async fn boarding_passes(&self, flight_id: i32) -> RepositoryResult<Vec<BoardingPass>> {
+        let boarding_passes = sqlx::query_as!(
+            BoardingPass,
+            "
+            SELECT ticket_no, flight_id, boarding_no, seat_no FROM boarding_passes
+            WHERE flight_id = $1
+            ORDER BY boarding_no
+            ",
+            flight_id
+        )
+            .fetch_all(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "boarding_passes"))
+            .await?;
+
+        Ok(boarding_passes)
+    }
+
+    async fn cabin_seats(&self, flight_id: i32, fare_conditions: BookingClass) -> RepositoryResult<Vec<String>> {
+        let seats = sqlx::query!(
+            "
+            SELECT seat_no
+            FROM seats_comfort
+            JOIN flights ON seats_comfort.aircraft_code = flights.aircraft_code
+            WHERE flights.flight_id = $1 AND seats_comfort.fare_conditions = $2
+            ORDER BY seat_no
+            ",
+            flight_id, String::from(fare_conditions)
+        )
+            .fetch_all(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "cabin_seats"))
+            .await?
+            .into_iter()
+            .map(|x| x.seat_no)
+            .collect();
+
+        Ok(seats)
+    }
+
+    async fn create_boarding_pass(&self, boarding_pass: &BoardingPass) -> RepositoryResult<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!(
+            "
+            INSERT INTO boarding_passes (ticket_no, flight_id, boarding_no, seat_no)
+            VALUES      ($1, $2, $3, $4);
+            ",
+            boarding_pass.ticket_no, boarding_pass.flight_id, boarding_pass.boarding_no, boarding_pass.seat_no
+        )
+            .execute(&mut *transaction)
+            .instrument(tracing::info_span!("sql", query = "insert_boarding_pass"))
+            .await?;
+
+        insert_outbox_event(&mut transaction, OutboxEvent::passenger_checked_in(boarding_pass)).await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+}
+
+/// Event types outside the ones the application writes are reported as decoding errors
+fn webhook_event_type(event_type: &str) -> Result<WebhookEventType, sqlx::Error> {
+    WebhookEventType::try_from(event_type).map_err(|e| sqlx::Error::Decode(e.into()))
+}
+
+fn webhook_event_types(event_types: &[String]) -> Result<Vec<WebhookEventType>, sqlx::Error> {
+    event_types.iter().map(|x| webhook_event_type(x)).collect()
+}
+
+#[async_trait]
+impl WebhookRepository for PgRepository {
+    async fn webhooks(&self) -> RepositoryResult<Vec<Webhook>> {
+        let rows = sqlx::query!(
+            "
+            SELECT endpoint_id, url, event_types, created_at FROM webhook_endpoints
+            ORDER BY endpoint_id
+            "
+        )
+            .fetch_all(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "webhooks"))
+            .await?;
+
+        let mut webhooks = vec![];
+
+        for x in rows {
+            webhooks.push(Webhook {
+                endpoint_id: x.endpoint_id,
+                url: x.url,
+                event_types: webhook_event_types(&x.event_types)?,
+                created_at: x.created_at,
+            });
+        }
+
+        Ok(webhooks)
+    }
+
+    async fn create_webhook(&self, webhook: &NewWebhook) -> RepositoryResult<Webhook> {
+        let event_types: Vec<String> = webhook.event_types.iter().map(|x| x.as_str().to_string()).collect();
+
+        let x = sqlx::query!(
+            "
+            INSERT INTO webhook_endpoints (url, secret, event_types)
+            VALUES      ($1, $2, $3)
+            RETURNING endpoint_id, created_at
+            ",
+            webhook.url, webhook.secret, &event_types
+        )
+            .fetch_one(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "insert_webhook"))
+            .await?;
+
+        Ok(Webhook {
+            endpoint_id: x.endpoint_id,
+            url: webhook.url.clone(),
+            event_types: webhook.event_types.clone(),
+            created_at: x.created_at,
+        })
+    }
+
+    async fn delete_webhook(&self, endpoint_id: i32) -> RepositoryResult<bool> {
+        let deleted = sqlx::query!("DELETE FROM webhook_endpoints WHERE endpoint_id = $1", endpoint_id)
+            .execute(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "delete_webhook"))
+            .await?
+            .rows_affected();
+
+        Ok(deleted > 0)
+    }
+
+    async fn claim_deliveries(&self, limit: i64, lease: Duration) -> RepositoryResult<Vec<PendingDelivery>> {
+        // Skipping locked rows lets several workers claim disjoint batches
+        let rows = sqlx::query!(
+            "
+            WITH claimed AS (
+                UPDATE webhook_deliveries
+                SET next_attempt_at = now() + make_interval(secs => $2)
+                WHERE delivery_id IN (
+                    SELECT delivery_id FROM webhook_deliveries
+                    WHERE status = 'Pending' AND next_attempt_at <= now()
+                    ORDER BY next_attempt_at, delivery_id
+                    LIMIT $1
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING delivery_id, event_id, endpoint_id, attempts
+            )
+            SELECT c.delivery_id, c.attempts, o.event_id, o.event_type, o.payload, o.created_at, e.url, e.secret
+            FROM claimed c
+                JOIN webhook_outbox o ON o.event_id = c.event_id
+                JOIN webhook_endpoints e ON e.endpoint_id = c.endpoint_id
+            ORDER BY c.delivery_id
+            ",
+            limit, lease.num_seconds() as f64
+        )
+            .fetch_all(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "claim_webhook_deliveries"))
+            .await?;
+
+        let mut deliveries = vec![];
+
+        for x in rows {
+            deliveries.push(PendingDelivery {
+                delivery_id: x.delivery_id,
+                event_id: x.event_id,
+                event_type: webhook_event_type(&x.event_type)?,
+                payload: x.payload,
+                created_at: x.created_at,
+                url: x.url,
+                secret: x.secret,
+                attempts: x.attempts,
+            });
+        }
+
+        Ok(deliveries)
+    }
+
+    async fn finish_delivery(&self, delivery_id: i64, outcome: &DeliveryOutcome) -> RepositoryResult<()> {
+        let query = match outcome {
+            DeliveryOutcome::Delivered => {
+                sqlx::query!(
+                    "
+                    UPDATE webhook_deliveries
+                    SET status = 'Delivered', attempts = attempts + 1, delivered_at = now(), last_error = NULL
+                    WHERE delivery_id = $1
+                    ",
+                    delivery_id
+                )
+            }
+            DeliveryOutcome::Retry { at, error } => {
+                sqlx::query!(
+                    "
+                    UPDATE webhook_deliveries
+                    SET attempts = attempts + 1, next_attempt_at = $2, last_error = $3
+                    WHERE delivery_id = $1
+                    ",
+                    delivery_id, at, error
+                )
+            }
+            DeliveryOutcome::Dead { error } => {
+                sqlx::query!(
+                    "
+                    UPDATE webhook_deliveries
+                    SET status = 'Dead', attempts = attempts + 1, last_error = $2
+                    WHERE delivery_id = $1
+                    ",
+                    delivery_id, error
+                )
+            }
+        };
+
+        query
+            .execute(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "finish_webhook_delivery"))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn dead_letters(&self) -> RepositoryResult<Vec<DeadLetter>> {
+        let rows = sqlx::query!(
+            "
+            SELECT d.delivery_id, d.endpoint_id, e.url, o.event_id, o.event_type, o.payload, d.attempts, d.last_error, o.created_at
+            FROM webhook_deliveries d
+                JOIN webhook_outbox o ON o.event_id = d.event_id
+                JOIN webhook_endpoints e ON e.endpoint_id = d.endpoint_id
+            WHERE d.status = 'Dead'
+            ORDER BY d.delivery_id
+            "
+        )
+            .fetch_all(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "webhook_dead_letters"))
+            .await?;
+
+        let mut dead_letters = vec![];
+
+        for x in rows {
+            dead_letters.push(DeadLetter {
+                delivery_id: x.delivery_id,
+                endpoint_id: x.endpoint_id,
+                url: x.url,
+                event_id: x.event_id,
+                event_type: webhook_event_type(&x.event_type)?,
+                payload: x.payload,
+                attempts: x.attempts,
+                last_error: x.last_error,
+                created_at: x.created_at,
+            });
+        }
+
+        Ok(dead_letters)
+    }
+
+    async fn retry_dead_letter(&self, delivery_id: i64) -> RepositoryResult<bool> {
+        let updated = sqlx::query!(
+            "
+            UPDATE webhook_deliveries
+            SET status = 'Pending', attempts = 0, next_attempt_at = now()
+            WHERE delivery_id = $1 AND status = 'Dead'
+            ",
+            delivery_id
+        )
+            .execute(&self.pool)
+            .instrument(tracing::info_span!("sql", query = "retry_webhook_dead_letter"))
+            .await?
+            .rows_affected();
+
+        Ok(updated > 0)
+    }
+}
@@ -1,30 +1,32 @@
-use std::time::Instant;
-use actix_web::{HttpResponse, Responder, web};
+use actix_web::{Responder, web};
 use sqlx::PgPool;
 use crate::app_state::AppState;
+use crate::rebuild::{RebuildJob, RebuildKind, spawn_rebuild};
 
-async fn create_table(pool: &PgPool) {
-    sqlx::query!("DROP TABLE IF EXISTS prices").execute(pool).await.unwrap();
-    
-    sqlx::query!(
+// The shadow table does not exist at compile time, so statements touching it
+// can't be checked by `query!`.
+pub(crate) async fn fill_shadow_table(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query("DROP TABLE IF EXISTS prices_new").execute(pool).await?;
+
+    sqlx::query(
         "
-        CREATE TABLE prices
+        CREATE TABLE prices_new
         (
             flight_no       CHAR(6)                 NOT NULL,
             fare_conditions VARCHAR(10)             NOT NULL,
             amount          INT                     NOT NULL,
+            managed         BOOLEAN                 NOT NULL DEFAULT false,
 
             PRIMARY KEY (flight_no, fare_conditions)
         );
         "
     )
         .execute(pool)
-        .await
-        .unwrap();
+        .await?;
 
-    sqlx::query!(
+    sqlx::query(
         "
-        INSERT INTO prices (flight_no, fare_conditions, amount)
+        INSERT INTO prices_new (flight_no, fare_conditions, amount)
         SELECT DISTINCT flight_no, fare_conditions, min(DISTINCT amount) AS price
         FROM ticket_flights
                  JOIN flights ON ticket_flights.flight_id = flights.flight_id
@@ -33,12 +35,11 @@ async fn create_table(pool: &PgPool) {
         "
     )
         .execute(pool)
-        .await
-        .unwrap();
+        .await?;
 
-    sqlx::query!(
+    sqlx::query(
         "
-        INSERT INTO prices (flight_no, fare_conditions, amount)
+        INSERT INTO prices_new (flight_no, fare_conditions, amount)
         SELECT DISTINCT flight_no, 'Comfort' as fare_conditions, max(DISTINCT amount) AS comfort_price
         FROM ticket_flights
                  JOIN flights ON ticket_flights.flight_id = flights.flight_id
@@ -47,14 +48,49 @@ async fn create_table(pool: &PgPool) {
         "
     )
         .execute(pool)
-        .await
-        .unwrap();
+        .await?;
+
+    Ok(())
 }
 
+/// Replaces `prices` by the shadow table. Fares managed by admins are copied
+/// over the derived ones while `prices` is locked, so that fare writes made
+/// during the rebuild are kept.
+pub(crate) async fn swap_shadow_table(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query("LOCK TABLE prices IN EXCLUSIVE MODE").execute(&mut *transaction).await?;
+
+    sqlx::query(
+        "
+        INSERT INTO prices_new (flight_no, fare_conditions, amount, managed)
+        SELECT flight_no, fare_conditions, amount, managed
+        FROM prices
+        WHERE managed
+        ON CONFLICT (flight_no, fare_conditions) DO UPDATE SET amount = EXCLUDED.amount, managed = true;
+        "
+    )
+        .execute(&mut *transaction)
+        .await?;
+
+    sqlx::query("DROP TABLE IF EXISTS prices").execute(&mut *transaction).await?;
+    sqlx::query("ALTER TABLE prices_new RENAME TO prices").execute(&mut *transaction).await?;
+    sqlx::query("ALTER INDEX prices_new_pkey RENAME TO prices_pkey").execute(&mut *transaction).await?;
+
+    transaction.commit().await
+}
+
+#[utoipa::path(
+    post, path = "/api/admin/compute_prices", tag = "admin",
+    security(("bearer" = [])),
+    responses(
+        (status = 202, description = "Rebuild of the fares derived from sold tickets started, fares created, updated or imported by admins are kept", body = RebuildJob),
+        (status = 409, description = "Another rebuild is running", body = String, content_type = "text/plain")
+    )
+)]
 pub async fn compute_prices(state: web::Data<AppState>) -> impl Responder {
-    let timer = Instant::now();
-    
-    create_table(&state.db_pool).await;
-    
-    HttpResponse::Ok().json(format!("Ok, create prices table in {:?}", timer.elapsed()))
-}
\ No newline at end of file
+    spawn_rebuild(state, RebuildKind::Prices, |state| async move {
+        fill_shadow_table(&state.db_pool).await?;
+        swap_shadow_table(&state.db_pool).await
+    })
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use actix_web::{HttpResponse, Responder, web};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::app_state::AppState;
+use crate::config::PricingConfig;
+use crate::repository::{PricingInputs, Repository, RepositoryResult};
+use crate::types::BookingClass;
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug, ToSchema)]
+pub enum RuleKind {
+    LoadFactor,
+    DaysToDeparture,
+    Weekday,
+}
+
+impl From<RuleKind> for String {
+    fn from(value: RuleKind) -> Self {
+        match value {
+            RuleKind::LoadFactor => { "LoadFactor".to_string() }
+            RuleKind::DaysToDeparture => { "DaysToDeparture".to_string() }
+            RuleKind::Weekday => { "Weekday".to_string() }
+        }
+    }
+}
+
+impl TryFrom<&str> for RuleKind {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "LoadFactor" => { Ok(RuleKind::LoadFactor) }
+            "DaysToDeparture" => { Ok(RuleKind::DaysToDeparture) }
+            "Weekday" => { Ok(RuleKind::Weekday) }
+            _ => { Err(format!("Unknown pricing rule kind {}", value)) }
+        }
+    }
+}
+
+/// Multiplies the base fare by `multiplier` percent when the measured value
+/// (load factor percent, days to departure or ISO weekday) is within `[min_value, max_value]`.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct PricingRule {
+    pub kind: RuleKind,
+    pub min_value: i32,
+    pub max_value: i32,
+    pub multiplier: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct AppliedRule {
+    #[serde(flatten)]
+    pub rule: PricingRule,
+    pub value: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct FlightQuote {
+    pub flight_id: i32,
+    pub base_amount: i32,
+    pub amount: i32,
+    pub occupied_seats: i32,
+    pub capacity: i32,
+    pub applied_rules: Vec<AppliedRule>,
+}
+
+impl FlightQuote {
+    pub fn has_free_seats(&self) -> bool {
+        self.occupied_seats < self.capacity
+    }
+}
+
+fn apply_rules(flight_id: i32, inputs: PricingInputs, rules: &[PricingRule]) -> FlightQuote {
+    let load_factor = match inputs.capacity {
+        0 => { 100 }
+        capacity => { inputs.occupied_seats * 100 / capacity }
+    };
+
+    let mut applied_rules = vec![];
+
+    for (kind, value) in [
+        (RuleKind::LoadFactor, load_factor),
+        (RuleKind::DaysToDeparture, inputs.days_to_departure),
+        (RuleKind::Weekday, inputs.weekday),
+    ] {
+        if let Some(rule) = rules.iter().find(|r| r.kind == kind && r.min_value <= value && value <= r.max_value) {
+            applied_rules.push(AppliedRule {
+                rule: rule.clone(),
+                value,
+            });
+        }
+    }
+
+    let amount = applied_rules
+        .iter()
+        .fold(inputs.base_amount as f64, |amount, applied| amount * applied.rule.multiplier as f64 / 100.0)
+        .round() as i32;
+
+    FlightQuote {
+        flight_id,
+        base_amount: inputs.base_amount,
+        amount,
+        occupied_seats: inputs.occupied_seats,
+        capacity: inputs.capacity,
+        applied_rules,
+    }
+}
+
+/// Prices the given flights in `booking_class` at the current booking time.
+/// Flights without a base fare for the class are absent from the result.
+pub async fn quote_flights(flight_ids: &[i32], booking_class: BookingClass, pricing: &PricingConfig, repo: &dyn Repository) -> RepositoryResult<HashMap<i32, FlightQuote>> {
+    let rules = if pricing.dynamic { repo.pricing_rules().await? } else { vec![] };
+
+    let quotes = repo.pricing_inputs(flight_ids, booking_class)
+        .await?
+        .into_iter()
+        .map(|(flight_id, inputs)| (flight_id, apply_rules(flight_id, inputs, &rules)))
+        .collect();
+
+    Ok(quotes)
+}
+
+#[utoipa::path(
+    get, path = "/api/admin/pricing_rules", tag = "admin",
+    security(("bearer" = [])),
+    responses((status = 200, body = Vec<PricingRule>))
+)]
+pub async fn list_pricing_rules(state: web::Data<AppState>) -> impl Responder {
+    match state.repo.pricing_rules().await {
+        Ok(rules) => {
+            HttpResponse::Ok().json(rules)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+/// Replaces the whole rule set atomically, keeping the order of the request.
+#[utoipa::path(
+    put, path = "/api/admin/pricing_rules", tag = "admin",
+    security(("bearer" = [])),
+    request_body = Vec<PricingRule>,
+    responses(
+        (status = 200, body = Vec<PricingRule>),
+        (status = 400, description = "Invalid rule", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn replace_pricing_rules(rules: web::Json<Vec<PricingRule>>, state: web::Data<AppState>) -> impl Responder {
+    if let Some(rule) = rules.iter().find(|r| r.min_value > r.max_value || r.multiplier <= 0) {
+        return HttpResponse::BadRequest().body(format!("Invalid pricing rule {:?}", rule));
+    }
+
+    match state.repo.replace_pricing_rules(&rules).await {
+        Ok(()) => {
+            HttpResponse::Ok().json(rules.into_inner())
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use actix_web::{HttpResponse, Responder, web};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::app_state::AppState;
+use crate::pricing::{quote_flights, FlightQuote};
+use crate::repository::RepositoryResult;
+use crate::types::BookingClass;
+use crate::validation::{check_itinerary, check_passengers, validate, Validate, ValidationErrors};
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct QuoteParameters {
+    flight_ids: Vec<i32>,
+    booking_class: BookingClass,
+    passengers: u8,
+}
+
+impl Validate for QuoteParameters {
+    async fn validate(&self, state: &AppState, errors: &mut ValidationErrors) -> RepositoryResult<()> {
+        check_passengers(errors, "passengers", self.passengers);
+        check_itinerary(errors, "flight_ids", &self.flight_ids, &*state.repo).await
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct QuoteResult {
+    legs: Vec<FlightQuote>,
+    passengers: u8,
+    price_per_passenger: i32,
+    total_price: i32,
+    expires_at: DateTime<Utc>,
+    quote_token: String,
+}
+
+/// Audience of quote tokens, required when decoding them so that they can't be
+/// confused with API tokens signed with the same secret
+pub const QUOTE_AUDIENCE: &str = "quote";
+
+/// Payload of a signed quote token: the priced legs a booking may be created with.
+#[derive(Serialize, Deserialize)]
+pub struct QuoteClaims {
+    pub aud: String,
+    pub flight_ids: Vec<i32>,
+    pub booking_class: BookingClass,
+    pub legs: Vec<FlightQuote>,
+    pub exp: i64,
+}
+
+#[derive(Debug)]
+pub struct QuoteMismatch;
+
+impl Display for QuoteMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Quote was issued for other flights or fare class")
+    }
+}
+
+impl Error for QuoteMismatch {}
+
+fn decode_quote_token(token: &str, secret: &str) -> Result<QuoteClaims, jsonwebtoken::errors::Error> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_audience(&[QUOTE_AUDIENCE]);
+    validation.set_required_spec_claims(&["exp", "aud"]);
+
+    jsonwebtoken::decode::<QuoteClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &validation
+    )
+        .map(|data| data.claims)
+}
+
+/// Checks that the token was signed by us, has not expired and was issued for
+/// exactly these flights and class, returning the locked-in legs. Quotes are
+/// not single-use: a quote covers several passengers, booked one per request,
+/// so its prices hold for any number of bookings until it expires.
+pub fn verify_quote_token(token: &str, secret: &str, flight_ids: &[i32], booking_class: BookingClass) -> Result<Vec<FlightQuote>, Box<dyn Error>> {
+    let claims = decode_quote_token(token, secret)?;
+
+    if claims.flight_ids != flight_ids || claims.booking_class != booking_class {
+        return Err(Box::new(QuoteMismatch));
+    }
+
+    Ok(claims.legs)
+}
+
+#[utoipa::path(
+    post, path = "/api/quote", tag = "booking",
+    request_body = QuoteParameters,
+    responses(
+        (status = 200, description = "Prices locked until the quote expires, for every booking made with its token", body = QuoteResult),
+        (status = 400, description = "Invalid fields", body = ValidationErrors),
+        (status = 404, description = "Some flight has no fare for the class", body = String, content_type = "text/plain"),
+        (status = 409, description = "Some flight has not enough free seats", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn create_quote(parameters: web::Json<QuoteParameters>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = validate(&*parameters, &state).await {
+        return response;
+    }
+
+    let quotes = match quote_flights(parameters.flight_ids.as_slice(), parameters.booking_class, &state.cfg.pricing, &*state.repo).await {
+        Ok(q) => { q }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    let mut legs = vec![];
+
+    for flight_id in &parameters.flight_ids {
+        match quotes.get(flight_id) {
+            Some(q) if q.capacity - q.occupied_seats >= parameters.passengers as i32 => {
+                legs.push(q.clone());
+            }
+            Some(_) => {
+                return HttpResponse::Conflict().body(format!("Flight {} has not enough free seats", flight_id));
+            }
+            None => {
+                return HttpResponse::NotFound().body(format!("Flight {} has no fare for this class", flight_id));
+            }
+        }
+    }
+
+    let price_per_passenger: i32 = legs.iter().map(|q| q.amount).sum();
+
+    let expires_at = Utc::now() + Duration::minutes(state.cfg.pricing.quote_ttl_minutes);
+
+    let claims = QuoteClaims {
+        aud: QUOTE_AUDIENCE.to_string(),
+        flight_ids: parameters.flight_ids.clone(),
+        booking_class: parameters.booking_class,
+        legs: legs.clone(),
+        exp: expires_at.timestamp(),
+    };
+
+    let quote_token = match jsonwebtoken::encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &EncodingKey::from_secret(state.cfg.auth.jwt_secret.as_bytes())
+    ) {
+        Ok(t) => { t }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    HttpResponse::Ok().json(QuoteResult {
+        legs,
+        passengers: parameters.passengers,
+        price_per_passenger,
+        total_price: price_per_passenger * parameters.passengers as i32,
+        expires_at,
+        quote_token,
+    })
+}
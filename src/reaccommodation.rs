@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use actix_web::{HttpResponse, Responder, web};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use crate::app_state::AppState;
+use crate::config::SearchConfig;
+use crate::repository::{FlightLeg, FlightSearch, Rebooking, Repository, RepositoryResult, TicketItinerary};
+use crate::types::{AirportCode, BookingClass, FlightState};
+
+/// Shortest connection offered in a new itinerary
+const MIN_CONNECTION_HOURS: i32 = 1;
+
+#[derive(Serialize, ToSchema)]
+pub struct RebookedPassenger {
+    ticket_no: String,
+    passenger_name: String,
+    /// The cancelled flight and the connections booked after it
+    replaced_flight_ids: Vec<i32>,
+    flight_ids: Vec<i32>,
+    path: Vec<String>,
+    /// The class booked before, or a higher one if it was full
+    fare_conditions: BookingClass,
+    arrival_time: DateTime<Utc>,
+}
+
+/// Passenger left without an itinerary to the destination, to be handled by an agent
+#[derive(Serialize, ToSchema)]
+pub struct UnprotectedPassenger {
+    ticket_no: String,
+    passenger_name: String,
+    fare_conditions: BookingClass,
+    origin: AirportCode,
+    destination: AirportCode,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReaccommodationReport {
+    flight_id: i32,
+    rebooked: Vec<RebookedPassenger>,
+    unprotected: Vec<UnprotectedPassenger>,
+    /// Error that stopped the run, passengers not listed are still on the flight
+    error: Option<String>,
+}
+
+#[derive(Clone)]
+struct Alternative {
+    flight_ids: Vec<i32>,
+    path: Vec<String>,
+    departure_time: DateTime<Utc>,
+    arrival_time: DateTime<Utc>,
+    fare_conditions: BookingClass,
+}
+
+/// Legs of the ticket to replace: the cancelled flight and the connections
+/// following it up to the destination of the journey, with the leg flown
+/// before them if any
+fn affected_legs(ticket: &TicketItinerary, flight_id: i32, max_connection: Duration) -> (Option<&FlightLeg>, &[FlightLeg]) {
+    let Some(start) = ticket.legs.iter().position(|l| l.flight_id == flight_id) else { return (None, &[]); };
+
+    let mut end = start + 1;
+
+    while let Some(next) = ticket.legs.get(end) {
+        let previous = &ticket.legs[end - 1];
+
+        if next.departure_airport != previous.arrival_airport || next.scheduled_departure - previous.scheduled_arrival > max_connection {
+            break;
+        }
+
+        end += 1;
+    }
+
+    (start.checked_sub(1).map(|i| &ticket.legs[i]), &ticket.legs[start..end])
+}
+
+/// Itineraries between two airports in one class departing from `first_day` through `last_day`
+async fn search(origin: &str, destination: &str, fare_conditions: BookingClass, first_day: NaiveDate, last_day: NaiveDate, cfg: &SearchConfig, repo: &dyn Repository) -> RepositoryResult<Vec<Alternative>> {
+    let mut alternatives = vec![];
+
+    for date in first_day.iter_days().take_while(|d| *d <= last_day) {
+        let search = FlightSearch {
+            sources: vec![origin.to_string()],
+            destinations: Some(vec![destination.to_string()]),
+            departure_date: date,
+            max_connections: cfg.max_connections as i32,
+            connection_time_min: MIN_CONNECTION_HOURS,
+            connection_time_max: cfg.max_connection_time_hours as i32,
+            booking_class: fare_conditions,
+        };
+
+        for flight in repo.find_flights(&search).await? {
+            let (Some(flight_ids), Some(path), Some(departure_time), Some(arrival_time)) = (flight.flight_ids, flight.path, flight.departure_time, flight.arrival_time) else {
+                continue;
+            };
+
+            alternatives.push(Alternative { flight_ids, path, departure_time, arrival_time, fare_conditions });
+        }
+    }
+
+    Ok(alternatives)
+}
+
+/// Rebooks every ticket of the cancelled flight on the itinerary arriving
+/// first at its destination, in the same class or the lowest higher one
+/// with free seats. The new itinerary leaves at least an hour after the
+/// previous leg of the ticket lands, and at the latest on the day after
+/// the cancelled flight, so that late flights get next-morning
+/// alternatives. Tickets already moved are no longer on the flight, so
+/// running it again only retries the unprotected passengers.
+///
+/// A repository error stops the run, the report then lists the passengers
+/// handled before it.
+pub async fn reaccommodate(flight_id: i32, cfg: &SearchConfig, repo: &dyn Repository) -> ReaccommodationReport {
+    let mut report = ReaccommodationReport { flight_id, rebooked: vec![], unprotected: vec![], error: None };
+
+    if let Err(e) = rebook_passengers(flight_id, cfg, repo, &mut report).await {
+        report.error = Some(e.to_string());
+    }
+
+    report
+}
+
+async fn rebook_passengers(flight_id: i32, cfg: &SearchConfig, repo: &dyn Repository, report: &mut ReaccommodationReport) -> RepositoryResult<()> {
+    let now = repo.now().await?;
+    let max_connection = Duration::hours(cfg.max_connection_time_hours as i64);
+
+    // Searches are shared by the passengers of the same journey; itineraries
+    // found full when rebooking are dropped from them
+    let mut searches: HashMap<(AirportCode, AirportCode, BookingClass, NaiveDate), Vec<Alternative>> = HashMap::new();
+
+    for ticket in repo.tickets_on_flight(flight_id).await? {
+        let (previous, legs) = affected_legs(&ticket, flight_id, max_connection);
+        let (Some(first), Some(last)) = (legs.first(), legs.last()) else { continue; };
+
+        // Itineraries that have not departed yet and leave the passenger time to connect
+        let earliest_departure = previous
+            .map(|p| p.scheduled_arrival + Duration::hours(MIN_CONNECTION_HOURS as i64))
+            .map_or(now, |t| t.max(now));
+        let first_day = earliest_departure.date_naive();
+        let last_day = first.scheduled_departure.date_naive().max(first_day) + Duration::days(1);
+
+        let origin = first.departure_airport.clone();
+        let destination = last.arrival_airport.clone();
+        let classes: Vec<BookingClass> = [BookingClass::Economy, BookingClass::Comfort, BookingClass::Business]
+            .into_iter()
+            .filter(|c| *c >= ticket.fare_conditions)
+            .collect();
+
+        let mut candidates = vec![];
+
+        for fare_conditions in classes {
+            let key = (origin.clone(), destination.clone(), fare_conditions, first_day);
+
+            if !searches.contains_key(&key) {
+                let found = search(&origin, &destination, fare_conditions, first_day, last_day, cfg, repo).await?;
+                searches.insert(key.clone(), found);
+            }
+
+            candidates.extend(searches[&key].iter().filter(|a| a.departure_time >= earliest_departure).cloned());
+        }
+
+        candidates.sort_by(|a, b| {
+            a.arrival_time.cmp(&b.arrival_time)
+                .then(a.fare_conditions.cmp(&b.fare_conditions))
+                .then(a.flight_ids.len().cmp(&b.flight_ids.len()))
+        });
+
+        let replaced_flight_ids: Vec<i32> = legs.iter().map(|l| l.flight_id).collect();
+        let mut rebooked = None;
+
+        for candidate in candidates {
+            let rebooking = Rebooking {
+                ticket_no: ticket.ticket_no.clone(),
+                removed: replaced_flight_ids.clone(),
+                added: candidate.flight_ids.clone(),
+                fare_conditions: candidate.fare_conditions,
+            };
+
+            if repo.rebook_ticket(&rebooking).await? {
+                rebooked = Some(candidate);
+                break;
+            }
+
+            for ((o, d, c, _), alternatives) in searches.iter_mut() {
+                if *o == origin && *d == destination && *c == candidate.fare_conditions {
+                    alternatives.retain(|a| a.flight_ids != candidate.flight_ids);
+                }
+            }
+        }
+
+        match rebooked {
+            Some(alternative) => {
+                report.rebooked.push(RebookedPassenger {
+                    ticket_no: ticket.ticket_no,
+                    passenger_name: ticket.passenger_name,
+                    replaced_flight_ids,
+                    flight_ids: alternative.flight_ids,
+                    path: alternative.path,
+                    fare_conditions: alternative.fare_conditions,
+                    arrival_time: alternative.arrival_time,
+                });
+            }
+            None => {
+                report.unprotected.push(UnprotectedPassenger {
+                    ticket_no: ticket.ticket_no,
+                    passenger_name: ticket.passenger_name,
+                    fare_conditions: ticket.fare_conditions,
+                    origin,
+                    destination,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    post, path = "/api/ops/flights/{flight_id}/reaccommodate", tag = "operations",
+    security(("bearer" = [])),
+    params(("flight_id" = i32, Path)),
+    responses(
+        (status = 200, description = "Passengers rebooked and left without an alternative", body = ReaccommodationReport),
+        (status = 404, description = "Unknown flight", body = String, content_type = "text/plain"),
+        (status = 409, description = "The flight is not cancelled", body = String, content_type = "text/plain"),
+        (status = 500, description = "Stopped by an error, with the passengers handled before it", body = ReaccommodationReport)
+    )
+)]
+pub async fn reaccommodate_passengers(path: web::Path<i32>, state: web::Data<AppState>) -> impl Responder {
+    let flight_id = path.into_inner();
+
+    match state.repo.flight_times(flight_id).await {
+        Ok(Some(times)) if times.status == FlightState::Cancelled => {}
+        Ok(Some(_)) => {
+            return HttpResponse::Conflict().body(format!("Flight {} is not cancelled", flight_id));
+        }
+        Ok(None) => {
+            return HttpResponse::NotFound().body(format!("Unknown flight {}", flight_id));
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    }
+
+    let report = reaccommodate(flight_id, &state.cfg.search, &*state.repo).await;
+
+    match &report.error {
+        None => {
+            tracing::info!(flight_id, rebooked = report.rebooked.len(), unprotected = report.unprotected.len(), "Passengers re-accommodated");
+            HttpResponse::Ok().json(report)
+        }
+        Some(e) => {
+            tracing::error!(flight_id, rebooked = report.rebooked.len(), unprotected = report.unprotected.len(), error = %e, "Re-accommodation stopped");
+            HttpResponse::InternalServerError().json(report)
+        }
+    }
+}
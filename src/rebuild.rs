@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Instant;
+use actix_web::{HttpResponse, Responder, web};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use crate::app_state::AppState;
+
+#[derive(Serialize, Copy, Clone, PartialEq, Debug, ToSchema)]
+pub enum RebuildKind {
+    Prices,
+    Seats,
+}
+
+#[derive(Serialize, Copy, Clone, PartialEq, ToSchema)]
+pub enum RebuildStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct RebuildJob {
+    pub id: u64,
+    pub kind: RebuildKind,
+    pub status: RebuildStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_ms: Option<u128>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct RebuildInProgress {
+    pub job_id: u64,
+}
+
+impl Display for RebuildInProgress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rebuild job {} is still running", self.job_id)
+    }
+}
+
+impl Error for RebuildInProgress {}
+
+#[derive(Default)]
+struct JobsInner {
+    next_id: u64,
+    running: Option<u64>,
+    jobs: HashMap<u64, RebuildJob>,
+}
+
+/// Registry of background rebuild jobs. At most one rebuild runs at a time,
+/// because seats classification reads the prices table.
+#[derive(Default)]
+pub struct RebuildJobs {
+    inner: Mutex<JobsInner>,
+}
+
+impl RebuildJobs {
+    pub fn start(&self, kind: RebuildKind) -> Result<RebuildJob, RebuildInProgress> {
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some(job_id) = inner.running {
+            return Err(RebuildInProgress { job_id });
+        }
+
+        inner.next_id += 1;
+
+        let job = RebuildJob {
+            id: inner.next_id,
+            kind,
+            status: RebuildStatus::Running,
+            started_at: Utc::now(),
+            finished_at: None,
+            duration_ms: None,
+            error: None,
+        };
+
+        inner.running = Some(job.id);
+        inner.jobs.insert(job.id, job.clone());
+
+        Ok(job)
+    }
+
+    pub fn finish(&self, id: u64, elapsed_ms: u128, result: Result<(), String>) {
+        let mut inner = self.inner.lock().unwrap();
+
+        if inner.running == Some(id) {
+            inner.running = None;
+        }
+
+        if let Some(job) = inner.jobs.get_mut(&id) {
+            job.finished_at = Some(Utc::now());
+            job.duration_ms = Some(elapsed_ms);
+
+            match result {
+                Ok(()) => { job.status = RebuildStatus::Succeeded }
+                Err(e) => {
+                    job.status = RebuildStatus::Failed;
+                    job.error = Some(e);
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Option<RebuildJob> {
+        self.inner.lock().unwrap().jobs.get(&id).cloned()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.inner.lock().unwrap().running.is_some()
+    }
+}
+
+/// Registers a rebuild job and runs `build` in the background, answering
+/// immediately with `202 Accepted` or `409 Conflict` if another rebuild is running.
+pub fn spawn_rebuild<F, Fut>(state: web::Data<AppState>, kind: RebuildKind, build: F) -> HttpResponse
+where
+    F: FnOnce(web::Data<AppState>) -> Fut + 'static,
+    Fut: Future<Output = Result<(), sqlx::Error>> + 'static,
+{
+    let job = match state.rebuild_jobs.start(kind) {
+        Ok(job) => { job }
+        Err(e) => {
+            return HttpResponse::Conflict().body(e.to_string());
+        }
+    };
+
+    let job_id = job.id;
+
+    tracing::info!(job_id, ?kind, "Rebuild started");
+
+    actix_web::rt::spawn(async move {
+        let timer = Instant::now();
+
+        // Built in its own task so that a panic fails the job instead of leaving it running forever
+        let result = match actix_web::rt::spawn(build(state.clone())).await {
+            Ok(result) => { result.map_err(|e| e.to_string()) }
+            Err(e) => { Err(format!("Rebuild aborted: {}", e)) }
+        };
+
+        let status = if result.is_ok() { "Succeeded" } else { "Failed" };
+
+        match &result {
+            Ok(()) => { tracing::info!(job_id, ?kind, elapsed_ms = timer.elapsed().as_millis() as u64, "Rebuild finished") }
+            Err(e) => { tracing::error!(job_id, ?kind, error = %e, "Rebuild failed") }
+        }
+
+        state.metrics.rebuild_duration
+            .with_label_values(&[format!("{:?}", kind).as_str(), status])
+            .observe(timer.elapsed().as_secs_f64());
+
+        state.rebuild_jobs.finish(job_id, timer.elapsed().as_millis(), result);
+    });
+
+    HttpResponse::Accepted().json(job)
+}
+
+#[utoipa::path(
+    get, path = "/api/admin/rebuild/{job_id}", tag = "admin",
+    security(("bearer" = [])),
+    params(("job_id" = u64, Path)),
+    responses(
+        (status = 200, body = RebuildJob),
+        (status = 404, description = "Unknown job")
+    )
+)]
+pub async fn rebuild_status(path: web::Path<u64>, state: web::Data<AppState>) -> impl Responder {
+    match state.rebuild_jobs.get(path.into_inner()) {
+        Some(job) => {
+            HttpResponse::Ok().json(job)
+        }
+        None => {
+            HttpResponse::NotFound().json("")
+        }
+    }
+}
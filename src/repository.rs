@@ -0,0 +1,355 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::find_flights::{ExploreRecord, FlightRecord};
+use crate::lang::Lang;
+use crate::pricing::{FlightQuote, PricingRule};
+use crate::types::{AirportCode, BoardDirection, BookingClass, FlightState};
+use crate::webhooks::{DeadLetter, DeliveryOutcome, NewWebhook, PendingDelivery, Webhook};
+
+#[derive(Debug)]
+pub enum RepositoryError {
+    /// A row with the same key already exists
+    Duplicate,
+    Database(sqlx::Error),
+}
+
+impl Display for RepositoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepositoryError::Duplicate => { f.write_str("Row already exists") }
+            RepositoryError::Database(e) => { write!(f, "{}", e) }
+        }
+    }
+}
+
+impl Error for RepositoryError {}
+
+impl From<sqlx::Error> for RepositoryError {
+    fn from(value: sqlx::Error) -> Self {
+        match value {
+            sqlx::Error::Database(e) if e.is_unique_violation() => { RepositoryError::Duplicate }
+            e => { RepositoryError::Database(e) }
+        }
+    }
+}
+
+pub type RepositoryResult<T> = Result<T, RepositoryError>;
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct Airport {
+    pub code: Option<String>,
+    pub name: Option<String>,
+}
+
+/// A name in every language of the dataset
+#[derive(Serialize, Clone, Default, ToSchema)]
+pub struct Names {
+    pub en: Option<String>,
+    pub ru: Option<String>,
+}
+
+impl Names {
+    pub fn get(&self, lang: Lang) -> Option<String> {
+        match lang {
+            Lang::En => { self.en.clone() }
+            Lang::Ru => { self.ru.clone() }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, ToSchema)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// An airport with its names in every language
+#[derive(Serialize, Clone, ToSchema)]
+pub struct AirportDetails {
+    pub code: String,
+    pub name: Names,
+    pub city: Names,
+    pub coordinates: Coordinates,
+    pub timezone: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct InboundRoute {
+    pub flight_no: Option<String>,
+    pub arrival_time: Option<NaiveTime>,
+    pub origin: Option<String>,
+    pub days_of_week: Option<Vec<i32>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OutboundRoute {
+    pub flight_no: Option<String>,
+    pub departure_time: Option<NaiveTime>,
+    pub destination: Option<String>,
+    pub days_of_week: Option<Vec<i32>>,
+}
+
+/// Itineraries departing from any of `sources` on `departure_date` and arriving at
+/// any of `destinations`, or anywhere if `None`, with every leg having free seats
+/// in `booking_class`.
+pub struct FlightSearch {
+    pub sources: Vec<AirportCode>,
+    pub destinations: Option<Vec<AirportCode>>,
+    pub departure_date: NaiveDate,
+    pub max_connections: i32,
+    pub connection_time_min: i32,
+    pub connection_time_max: i32,
+    pub booking_class: BookingClass,
+}
+
+/// One end of a flight, times given both in UTC and local to the airport
+#[derive(Serialize, Clone, ToSchema)]
+pub struct FlightEndpoint {
+    pub airport_code: String,
+    pub airport_name: Option<String>,
+    pub city: Option<String>,
+    pub timezone: String,
+    pub scheduled: DateTime<Utc>,
+    pub scheduled_local: NaiveDateTime,
+    /// Announced when the flight is delayed
+    pub estimated: Option<DateTime<Utc>>,
+    pub estimated_local: Option<NaiveDateTime>,
+    pub actual: Option<DateTime<Utc>>,
+    pub actual_local: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct Aircraft {
+    pub code: String,
+    pub model: Option<String>,
+    /// Maximum flight range in kilometers
+    pub range: i32,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct SeatsAvailable {
+    pub fare_conditions: BookingClass,
+    pub seats: i32,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct FlightDetails {
+    pub flight_id: i32,
+    pub flight_no: String,
+    pub status: String,
+    pub departure: FlightEndpoint,
+    pub arrival: FlightEndpoint,
+    pub aircraft: Aircraft,
+    /// Seats not yet sold in every class of the cabin
+    pub seats_available: Vec<SeatsAvailable>,
+}
+
+/// Status and times of a flight, as changed by operations control
+#[derive(Clone)]
+pub struct FlightTimes {
+    pub status: FlightState,
+    pub scheduled_departure: DateTime<Utc>,
+    pub scheduled_arrival: DateTime<Utc>,
+    pub estimated_departure: Option<DateTime<Utc>>,
+    pub estimated_arrival: Option<DateTime<Utc>>,
+    pub actual_departure: Option<DateTime<Utc>>,
+    pub actual_arrival: Option<DateTime<Utc>>,
+}
+
+/// New status and times of a flight, written with an audit entry
+pub struct FlightStatusChange {
+    pub flight_id: i32,
+    pub previous_status: FlightState,
+    pub times: FlightTimes,
+    pub reason: Option<String>,
+    pub changed_by: String,
+}
+
+/// A status change with the times written and who made it
+#[derive(Serialize, Clone, ToSchema)]
+pub struct FlightAuditEntry {
+    pub audit_id: i64,
+    pub flight_id: i32,
+    pub changed_at: DateTime<Utc>,
+    /// Subject of the token used for the change
+    pub changed_by: String,
+    pub previous_status: FlightState,
+    pub status: FlightState,
+    pub estimated_departure: Option<DateTime<Utc>>,
+    pub estimated_arrival: Option<DateTime<Utc>>,
+    pub actual_departure: Option<DateTime<Utc>>,
+    pub actual_arrival: Option<DateTime<Utc>>,
+    pub reason: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct FlightLeg {
+    pub flight_id: i32,
+    pub departure_airport: String,
+    pub arrival_airport: String,
+    pub scheduled_departure: DateTime<Utc>,
+    pub scheduled_arrival: DateTime<Utc>,
+}
+
+/// Everything the pricing engine needs to know about a flight in one class.
+pub struct PricingInputs {
+    pub base_amount: i32,
+    pub occupied_seats: i32,
+    pub capacity: i32,
+    pub days_to_departure: i32,
+    pub weekday: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct Fare {
+    pub flight_no: String,
+    pub fare_conditions: BookingClass,
+    pub amount: i32,
+}
+
+/// A single-passenger booking with a ticket for every priced leg.
+pub struct NewBooking {
+    pub book_ref: String,
+    pub ticket_no: String,
+    pub passenger_id: String,
+    pub passenger_name: String,
+    pub fare_conditions: BookingClass,
+    pub legs: Vec<FlightQuote>,
+}
+
+/// A ticket booked on some flight, with every leg of the ticket by departure
+pub struct TicketItinerary {
+    pub ticket_no: String,
+    pub passenger_name: String,
+    /// Class booked on that flight
+    pub fare_conditions: BookingClass,
+    pub legs: Vec<FlightLeg>,
+}
+
+/// Legs of a ticket replaced by other flights, booked in `fare_conditions`
+pub struct Rebooking {
+    pub ticket_no: String,
+    pub removed: Vec<i32>,
+    pub added: Vec<i32>,
+    pub fare_conditions: BookingClass,
+}
+
+#[derive(Clone)]
+pub struct BoardingPass {
+    pub ticket_no: String,
+    pub flight_id: i32,
+    pub boarding_no: i32,
+    pub seat_no: String,
+}
+
+#[async_trait]
+pub trait AirportRepository {
+    async fn cities(&self, lang: Lang) -> RepositoryResult<Vec<String>>;
+    async fn airports(&self, lang: Lang) -> RepositoryResult<Vec<Airport>>;
+    /// The city may be named in any language, airport names are in `lang`
+    async fn airports_in_city(&self, city: &str, lang: Lang) -> RepositoryResult<Vec<Airport>>;
+    async fn airport_details(&self) -> RepositoryResult<Vec<AirportDetails>>;
+    async fn airport_exists(&self, code: &str) -> RepositoryResult<bool>;
+    /// The city may be named in any language
+    async fn city_exists(&self, city: &str) -> RepositoryResult<bool>;
+}
+
+#[async_trait]
+pub trait FlightRepository {
+    /// Current time of the dataset, which may be frozen in the past
+    async fn now(&self) -> RepositoryResult<DateTime<Utc>>;
+    /// First and last dates with scheduled departures, in UTC
+    async fn departure_date_range(&self) -> RepositoryResult<Option<(NaiveDate, NaiveDate)>>;
+    async fn inbound_schedule(&self, airport_code: &str) -> RepositoryResult<Vec<InboundRoute>>;
+    async fn outbound_schedule(&self, airport_code: &str) -> RepositoryResult<Vec<OutboundRoute>>;
+    async fn find_flights(&self, search: &FlightSearch) -> RepositoryResult<Vec<FlightRecord>>;
+    /// Earliest arriving, shortest and cheapest by base fares itinerary to each of the first
+    /// `limit` destinations outside `sources` by code, `destinations` is ignored
+    async fn explore_destinations(&self, search: &FlightSearch, limit: i64) -> RepositoryResult<Vec<ExploreRecord>>;
+    /// Flights with the given ids, unknown ids are skipped
+    async fn flights(&self, flight_ids: &[i32]) -> RepositoryResult<Vec<FlightLeg>>;
+    /// Flights with the given ids ordered by departure, names in `lang`, unknown ids are skipped
+    async fn flight_details(&self, flight_ids: &[i32], lang: Lang) -> RepositoryResult<Vec<FlightDetails>>;
+    /// Flights with the number departing on the date, local to the departure airport
+    async fn flight_ids_by_number(&self, flight_no: &str, date: NaiveDate) -> RepositoryResult<Vec<i32>>;
+    /// Flights departing from or arriving at the airport with a scheduled,
+    /// estimated or actual time of that side between `from` and `to`
+    async fn board_flight_ids(&self, airport_code: &str, direction: BoardDirection, from: DateTime<Utc>, to: DateTime<Utc>) -> RepositoryResult<Vec<i32>>;
+    /// Status and times of the flight, `None` if there is no such flight
+    async fn flight_times(&self, flight_id: i32) -> RepositoryResult<Option<FlightTimes>>;
+    /// Writes the new status and times with an audit entry, unless a concurrent
+    /// change has moved the flight away from `previous_status`, returning false then
+    async fn change_flight_status(&self, change: &FlightStatusChange) -> RepositoryResult<bool>;
+    /// Status changes of the flight, oldest first
+    async fn flight_status_audit(&self, flight_id: i32) -> RepositoryResult<Vec<FlightAuditEntry>>;
+    async fn flight_numbers(&self) -> RepositoryResult<HashSet<String>>;
+    /// Flights without a fare in `booking_class` are absent from the result
+    async fn pricing_inputs(&self, flight_ids: &[i32], booking_class: BookingClass) -> RepositoryResult<HashMap<i32, PricingInputs>>;
+}
+
+/// Fares written here are managed by admins, price rebuilds keep them.
+#[async_trait]
+pub trait FareRepository {
+    async fn fares(&self, flight_no: Option<&str>) -> RepositoryResult<Vec<Fare>>;
+    async fn create_fare(&self, fare: &Fare) -> RepositoryResult<()>;
+    /// Returns false if there is no such fare
+    async fn update_fare(&self, fare: &Fare) -> RepositoryResult<bool>;
+    /// Returns false if there is no such fare
+    async fn delete_fare(&self, flight_no: &str, fare_conditions: BookingClass) -> RepositoryResult<bool>;
+    /// Inserts or updates all fares at once, returning the number of rows written
+    async fn upsert_fares(&self, fares: &[Fare]) -> RepositoryResult<u64>;
+    async fn pricing_rules(&self) -> RepositoryResult<Vec<PricingRule>>;
+    async fn replace_pricing_rules(&self, rules: &[PricingRule]) -> RepositoryResult<()>;
+}
+
+#[async_trait]
+pub trait BookingRepository {
+    /// Writes the booking, its ticket and legs atomically
+    async fn create_booking(&self, booking: &NewBooking) -> RepositoryResult<()>;
+    /// Class the ticket is booked in on the flight, if it is booked on it at all
+    async fn ticket_fare_conditions(&self, ticket_no: &str, flight_id: i32) -> RepositoryResult<Option<BookingClass>>;
+    /// Tickets booked on the flight, by ticket number
+    async fn tickets_on_flight(&self, flight_id: i32) -> RepositoryResult<Vec<TicketItinerary>>;
+    /// Replaces the legs atomically, dropping their boarding passes and spreading
+    /// what was paid for them evenly over the new legs. Returns false, changing
+    /// nothing, if some new flight has no free seat left in the class.
+    async fn rebook_ticket(&self, rebooking: &Rebooking) -> RepositoryResult<bool>;
+}
+
+#[async_trait]
+pub trait BoardingPassRepository {
+    async fn boarding_passes(&self, flight_id: i32) -> RepositoryResult<Vec<BoardingPass>>;
+    /// Seats of the flight's aircraft in the class, in seat map order
+    async fn cabin_seats(&self, flight_id: i32, fare_conditions: BookingClass) -> RepositoryResult<Vec<String>>;
+    async fn create_boarding_pass(&self, boarding_pass: &BoardingPass) -> RepositoryResult<()>;
+}
+
+/// Bookings, check-ins, flight status changes and rebookings also write their
+/// webhook event to the outbox, in the same transaction.
+#[async_trait]
+pub trait WebhookRepository {
+    async fn webhooks(&self) -> RepositoryResult<Vec<Webhook>>;
+    async fn create_webhook(&self, webhook: &NewWebhook) -> RepositoryResult<Webhook>;
+    /// Returns false if there is no such webhook
+    async fn delete_webhook(&self, endpoint_id: i32) -> RepositoryResult<bool>;
+    /// Pending deliveries due now, oldest first, postponed by `lease` so that
+    /// other workers skip them while they are sent
+    async fn claim_deliveries(&self, limit: i64, lease: Duration) -> RepositoryResult<Vec<PendingDelivery>>;
+    async fn finish_delivery(&self, delivery_id: i64, outcome: &DeliveryOutcome) -> RepositoryResult<()>;
+    async fn dead_letters(&self) -> RepositoryResult<Vec<DeadLetter>>;
+    /// Makes a dead delivery pending again with no attempts, returns false if there is no such dead delivery
+    async fn retry_dead_letter(&self, delivery_id: i64) -> RepositoryResult<bool>;
+}
+
+/// Storage used by the handlers: Postgres in production, in memory in tests.
+pub trait Repository: AirportRepository + FlightRepository + FareRepository + BookingRepository + BoardingPassRepository + WebhookRepository + Send + Sync {}
+
+impl<T> Repository for T
+where
+    T: AirportRepository + FlightRepository + FareRepository + BookingRepository + BoardingPassRepository + WebhookRepository + Send + Sync
+{}
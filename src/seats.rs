@@ -1,40 +1,40 @@
-use std::time::Instant;
-use actix_web::{HttpResponse, Responder, web};
+use actix_web::{Responder, web};
 use sqlx::PgPool;
 use crate::app_state::AppState;
+use crate::rebuild::{RebuildJob, RebuildKind, spawn_rebuild};
 
-async fn create_table(pool: &PgPool) {
-    sqlx::query!("DROP TABLE IF EXISTS seats_comfort").execute(pool).await.unwrap();
+// The shadow table does not exist at compile time, so statements touching it
+// can't be checked by `query!`.
+async fn create_table(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query("DROP TABLE IF EXISTS seats_comfort_new").execute(pool).await?;
 
-    sqlx::query!(
+    sqlx::query(
         "
-        CREATE TABLE seats_comfort
+        CREATE TABLE seats_comfort_new
         (
             aircraft_code   CHAR(3)         NOT NULL,
             seat_no         VARCHAR(4)      NOT NULL,
             fare_conditions VARCHAR(10)     NOT NULL,
-        
+
             PRIMARY KEY (aircraft_code, seat_no)
         );
         "
     )
         .execute(pool)
-        .await
-        .unwrap();
+        .await?;
 
-    sqlx::query!(
+    sqlx::query(
         "
-        INSERT INTO seats_comfort
+        INSERT INTO seats_comfort_new
         SELECT * FROM seats;
         "
     )
         .execute(pool)
-        .await
-        .unwrap();
+        .await?;
 
-    sqlx::query!(
+    sqlx::query(
         "
-        UPDATE seats_comfort
+        UPDATE seats_comfort_new
         SET fare_conditions = 'Comfort'
         FROM
         (
@@ -46,19 +46,53 @@ async fn create_table(pool: &PgPool) {
                      JOIN prices ON flights.flight_no = prices.flight_no AND ticket_flights.amount = prices.amount
             WHERE prices.fare_conditions = 'Comfort'
         ) AS comfort_seats_query
-        WHERE seats_comfort.seat_no = comfort_seats_query.seat_no
-                AND seats_comfort.aircraft_code = comfort_seats_query.aircraft_code
+        WHERE seats_comfort_new.seat_no = comfort_seats_query.seat_no
+                AND seats_comfort_new.aircraft_code = comfort_seats_query.aircraft_code
         "
     )
         .execute(pool)
-        .await
-        .unwrap();
-}
+        .await?;
 
-pub async fn compute_seats(state: web::Data<AppState>) -> impl Responder {
-    let timer = Instant::now();
+    // Aircraft with an admin-defined cabin layout are classified by it instead of history
+    sqlx::query(
+        "
+        DELETE FROM seats_comfort_new
+        WHERE aircraft_code IN (SELECT aircraft_code FROM cabin_layouts)
+        "
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        "
+        INSERT INTO seats_comfort_new (aircraft_code, seat_no, fare_conditions)
+        SELECT aircraft_code, seat_no, fare_conditions
+        FROM seats_layout_v
+        WHERE NOT blocked
+        "
+    )
+        .execute(pool)
+        .await?;
 
-    create_table(&state.db_pool).await;
+    let mut transaction = pool.begin().await?;
 
-    HttpResponse::Ok().json(format!("Ok, create seats table in {:?}", timer.elapsed()))
-}
\ No newline at end of file
+    sqlx::query("DROP TABLE IF EXISTS seats_comfort").execute(&mut *transaction).await?;
+    sqlx::query("ALTER TABLE seats_comfort_new RENAME TO seats_comfort").execute(&mut *transaction).await?;
+    sqlx::query("ALTER INDEX seats_comfort_new_pkey RENAME TO seats_comfort_pkey").execute(&mut *transaction).await?;
+
+    transaction.commit().await
+}
+
+#[utoipa::path(
+    post, path = "/api/admin/compute_seats", tag = "admin",
+    security(("bearer" = [])),
+    responses(
+        (status = 202, body = RebuildJob),
+        (status = 409, description = "Another rebuild is running", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn compute_seats(state: web::Data<AppState>) -> impl Responder {
+    spawn_rebuild(state, RebuildKind::Seats, |state| async move {
+        create_table(&state.db_pool).await
+    })
+}
@@ -1,15 +1,30 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Clone, Serialize, Deserialize)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
 pub enum LocationType {
     #[serde(rename = "City")]
     CITY,
 
     #[serde(rename = "Airport")]
     AIRPORT,
+
+    /// Airports within a circle given as `latitude,longitude[,radius_km]`,
+    /// the radius being 200 km by default
+    #[serde(rename = "Coordinates")]
+    COORDINATES,
+}
+
+/// Side of an airport board
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug, ToSchema)]
+pub enum BoardDirection {
+    Departures,
+    Arrivals,
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+/// Classes are ordered from Economy up to Business
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, ToSchema)]
 pub enum BookingClass {
     Economy,
     Comfort,
@@ -26,4 +41,73 @@ impl From<BookingClass> for String {
     }
 }
 
+impl TryFrom<&str> for BookingClass {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "Economy" => { Ok(BookingClass::Economy) }
+            "Comfort" => { Ok(BookingClass::Comfort) }
+            "Business" => { Ok(BookingClass::Business) }
+            _ => { Err(format!("Unknown booking class {}", value)) }
+        }
+    }
+}
+
+/// Status of a flight as stored in `flights.status`
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug, ToSchema)]
+pub enum FlightState {
+    Scheduled,
+    #[serde(rename = "On Time")]
+    OnTime,
+    Delayed,
+    Departed,
+    Arrived,
+    Cancelled,
+}
+
+impl FlightState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FlightState::Scheduled => { "Scheduled" }
+            FlightState::OnTime => { "On Time" }
+            FlightState::Delayed => { "Delayed" }
+            FlightState::Departed => { "Departed" }
+            FlightState::Arrived => { "Arrived" }
+            FlightState::Cancelled => { "Cancelled" }
+        }
+    }
+
+    /// Flights go Scheduled, On Time, Delayed, Departed and Arrived, possibly
+    /// skipping On Time or Delayed, and may be cancelled until they depart.
+    /// A delayed flight may be delayed again.
+    pub fn can_become(self, next: FlightState) -> bool {
+        use FlightState::*;
+
+        matches!(
+            (self, next),
+            (Scheduled, OnTime | Delayed | Departed | Cancelled)
+                | (OnTime, Delayed | Departed | Cancelled)
+                | (Delayed, Delayed | Departed | Cancelled)
+                | (Departed, Arrived)
+        )
+    }
+}
+
+impl TryFrom<&str> for FlightState {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "Scheduled" => { Ok(FlightState::Scheduled) }
+            "On Time" => { Ok(FlightState::OnTime) }
+            "Delayed" => { Ok(FlightState::Delayed) }
+            "Departed" => { Ok(FlightState::Departed) }
+            "Arrived" => { Ok(FlightState::Arrived) }
+            "Cancelled" => { Ok(FlightState::Cancelled) }
+            _ => { Err(format!("Unknown flight status {}", value)) }
+        }
+    }
+}
+
 pub type AirportCode = String;
@@ -0,0 +1,221 @@
+use std::collections::{HashMap, HashSet};
+use actix_web::HttpResponse;
+use chrono::NaiveDate;
+use serde::Serialize;
+use utoipa::ToSchema;
+use crate::app_state::AppState;
+use crate::config::SearchConfig;
+use crate::repository::{FlightLeg, Repository, RepositoryResult};
+
+pub const MAX_PASSENGERS: u8 = 9;
+
+#[derive(Serialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Body of a `400 Bad Request` answer to a request with invalid fields.
+#[derive(Serialize, ToSchema, Default)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        });
+    }
+
+    /// Database lookups are skipped for fields already found malformed
+    pub fn has(&self, field: &str) -> bool {
+        self.errors.iter().any(|e| e.field == field)
+    }
+}
+
+pub trait Validate {
+    async fn validate(&self, state: &AppState, errors: &mut ValidationErrors) -> RepositoryResult<()>;
+}
+
+/// Answers `400` with every field error found, or `500` if a lookup failed.
+pub async fn validate<T: Validate>(value: &T, state: &AppState) -> Result<(), HttpResponse> {
+    let mut errors = ValidationErrors::default();
+
+    if let Err(e) = value.validate(state, &mut errors).await {
+        return Err(HttpResponse::InternalServerError().body(e.to_string()));
+    }
+
+    if errors.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(HttpResponse::BadRequest().json(errors))
+    }
+}
+
+pub fn check_iata_code(errors: &mut ValidationErrors, field: &str, code: &str) {
+    if code.len() != 3 || !code.chars().all(|c| c.is_ascii_uppercase()) {
+        errors.add(field, format!("{:?} is not an IATA airport code of three capital letters", code));
+    }
+}
+
+/// Latin letters as printed in the travel document, given and family names
+/// separated by spaces, hyphens or apostrophes, e.g. `IVAN PETROV-VODKIN`.
+pub fn check_passenger_name(errors: &mut ValidationErrors, field: &str, name: &str) {
+    let words: Vec<&str> = name.split(' ').collect();
+
+    let well_formed = name.len() <= 100
+        && words.len() >= 2
+        && words.iter().all(|w| {
+            w.split(['-', '\''])
+                .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_uppercase()))
+        });
+
+    if !well_formed {
+        errors.add(field, "Must be given and family names in capital Latin letters separated by single spaces");
+    }
+}
+
+/// Passport series and number, e.g. `4510 123456`.
+pub fn check_passenger_id(errors: &mut ValidationErrors, field: &str, passenger_id: &str) {
+    let well_formed = match passenger_id.split_once(' ') {
+        Some((series, number)) => {
+            series.len() == 4 && number.len() == 6
+                && series.chars().chain(number.chars()).all(|c| c.is_ascii_digit())
+        }
+        None => { false }
+    };
+
+    if !well_formed {
+        errors.add(field, "Must be a document series of 4 digits and a number of 6 digits separated by a space");
+    }
+}
+
+pub fn check_ticket_no(errors: &mut ValidationErrors, field: &str, ticket_no: &str) {
+    if ticket_no.len() != 13 || !ticket_no.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        errors.add(field, "Must be 13 letters, digits or underscores");
+    }
+}
+
+pub fn check_passengers(errors: &mut ValidationErrors, field: &str, passengers: u8) {
+    if passengers == 0 || passengers > MAX_PASSENGERS {
+        errors.add(field, format!("Must be between 1 and {}", MAX_PASSENGERS));
+    }
+}
+
+pub async fn check_airport_exists(errors: &mut ValidationErrors, field: &str, code: &str, repo: &dyn Repository) -> RepositoryResult<()> {
+    if !repo.airport_exists(code).await? {
+        errors.add(field, format!("Unknown airport {}", code));
+    }
+
+    Ok(())
+}
+
+pub async fn check_city_exists(errors: &mut ValidationErrors, field: &str, city: &str, repo: &dyn Repository) -> RepositoryResult<()> {
+    if !repo.city_exists(city).await? {
+        errors.add(field, format!("No airports in {:?}", city));
+    }
+
+    Ok(())
+}
+
+/// Connection limits shared by route search and explore mode
+pub fn check_search_limits(errors: &mut ValidationErrors, limits: &SearchConfig, max_connections: u8, connection_time_min: u8, connection_time_max: u8) {
+    if max_connections > limits.max_connections {
+        errors.add("max_connections", format!("Must not exceed {}", limits.max_connections));
+    }
+
+    if connection_time_max > limits.max_connection_time_hours {
+        errors.add("connection_time_max", format!("Must not exceed {} hours", limits.max_connection_time_hours));
+    }
+
+    if connection_time_min > connection_time_max {
+        errors.add("connection_time_min", "Must not exceed connection_time_max");
+    }
+}
+
+/// Departures can be searched from the current booking date up to the last scheduled flight.
+pub async fn check_departure_date(errors: &mut ValidationErrors, field: &str, date: NaiveDate, repo: &dyn Repository) -> RepositoryResult<()> {
+    let first_date = repo.now().await?.date_naive();
+
+    if let Some((_, last_date)) = repo.departure_date_range().await? {
+        if date < first_date || date > last_date {
+            errors.add(field, format!("Must be between {} and {}", first_date, last_date));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the list is a non-empty itinerary of distinct existing flights,
+/// each departing from the previous arrival airport after the previous arrival.
+pub async fn check_itinerary(errors: &mut ValidationErrors, field: &str, flight_ids: &[i32], repo: &dyn Repository) -> RepositoryResult<()> {
+    if flight_ids.is_empty() {
+        errors.add(field, "At least one flight is required");
+        return Ok(());
+    }
+
+    let mut seen = HashSet::new();
+
+    if let Some(duplicate) = flight_ids.iter().find(|id| !seen.insert(**id)) {
+        errors.add(field, format!("Flight {} is listed more than once", duplicate));
+        return Ok(());
+    }
+
+    let flights: HashMap<i32, FlightLeg> = repo.flights(flight_ids)
+        .await?
+        .into_iter()
+        .map(|x| (x.flight_id, x))
+        .collect();
+
+    let unknown: Vec<String> = flight_ids
+        .iter()
+        .filter(|id| !flights.contains_key(id))
+        .map(|id| id.to_string())
+        .collect();
+
+    if !unknown.is_empty() {
+        errors.add(field, format!("Unknown flights {}", unknown.join(", ")));
+        return Ok(());
+    }
+
+    for pair in flight_ids.windows(2) {
+        let (previous, next) = (&flights[&pair[0]], &flights[&pair[1]]);
+
+        if previous.arrival_airport != next.departure_airport {
+            errors.add(field, format!(
+                "Flight {} arrives at {} but flight {} departs from {}",
+                pair[0], previous.arrival_airport, pair[1], next.departure_airport
+            ));
+        } else if next.scheduled_departure <= previous.scheduled_arrival {
+            errors.add(field, format!("Flight {} departs before flight {} arrives", pair[1], pair[0]));
+        }
+    }
+
+    Ok(())
+}
+
+/// Airport code taken from the request path
+pub struct AirportCodeParam<'a>(pub &'a str);
+
+impl Validate for AirportCodeParam<'_> {
+    async fn validate(&self, state: &AppState, errors: &mut ValidationErrors) -> RepositoryResult<()> {
+        check_iata_code(errors, "airport_code", self.0);
+
+        if !errors.has("airport_code") {
+            check_airport_exists(errors, "airport_code", self.0, &*state.repo).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// City name taken from the request path
+pub struct CityParam<'a>(pub &'a str);
+
+impl Validate for CityParam<'_> {
+    async fn validate(&self, state: &AppState, errors: &mut ValidationErrors) -> RepositoryResult<()> {
+        check_city_exists(errors, "city", self.0, &*state.repo).await
+    }
+}
@@ -0,0 +1,400 @@
+use std::time::Duration;
+use actix_web::rt::time::sleep;
+use actix_web::{HttpResponse, Responder, web};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use utoipa::ToSchema;
+use crate::app_state::AppState;
+use crate::config::WebhooksConfig;
+use crate::repository::{BoardingPass, FlightStatusChange, NewBooking, Rebooking, RepositoryResult};
+use crate::types::FlightState;
+use crate::validation::{validate, Validate, ValidationErrors};
+
+pub const MIN_SECRET_LENGTH: usize = 16;
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug, ToSchema)]
+pub enum WebhookEventType {
+    #[serde(rename = "booking.created")]
+    BookingCreated,
+    #[serde(rename = "passenger.checked_in")]
+    PassengerCheckedIn,
+    /// Every status change but cancellations
+    #[serde(rename = "flight.status_changed")]
+    FlightStatusChanged,
+    #[serde(rename = "flight.cancelled")]
+    FlightCancelled,
+    /// A ticket moved off a cancelled flight
+    #[serde(rename = "ticket.rebooked")]
+    TicketRebooked,
+}
+
+impl WebhookEventType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookEventType::BookingCreated => { "booking.created" }
+            WebhookEventType::PassengerCheckedIn => { "passenger.checked_in" }
+            WebhookEventType::FlightStatusChanged => { "flight.status_changed" }
+            WebhookEventType::FlightCancelled => { "flight.cancelled" }
+            WebhookEventType::TicketRebooked => { "ticket.rebooked" }
+        }
+    }
+}
+
+impl TryFrom<&str> for WebhookEventType {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        serde_json::from_value(Value::from(value)).map_err(|_| format!("Unknown webhook event type {}", value))
+    }
+}
+
+/// Event written to the outbox in the transaction of the change it describes
+pub struct OutboxEvent {
+    pub event_type: WebhookEventType,
+    pub payload: Value,
+}
+
+impl OutboxEvent {
+    pub fn booking_created(booking: &NewBooking) -> OutboxEvent {
+        OutboxEvent {
+            event_type: WebhookEventType::BookingCreated,
+            payload: json!({
+                "book_ref": booking.book_ref,
+                "ticket_no": booking.ticket_no,
+                "passenger_name": booking.passenger_name,
+                "fare_conditions": booking.fare_conditions,
+                "flight_ids": booking.legs.iter().map(|q| q.flight_id).collect::<Vec<i32>>(),
+                "total_amount": booking.legs.iter().map(|q| q.amount).sum::<i32>(),
+            }),
+        }
+    }
+
+    pub fn passenger_checked_in(boarding_pass: &BoardingPass) -> OutboxEvent {
+        OutboxEvent {
+            event_type: WebhookEventType::PassengerCheckedIn,
+            payload: json!({
+                "ticket_no": boarding_pass.ticket_no,
+                "flight_id": boarding_pass.flight_id,
+                "boarding_no": boarding_pass.boarding_no,
+                "seat_no": boarding_pass.seat_no,
+            }),
+        }
+    }
+
+    pub fn flight_status_changed(change: &FlightStatusChange) -> OutboxEvent {
+        let times = &change.times;
+
+        OutboxEvent {
+            event_type: match times.status {
+                FlightState::Cancelled => { WebhookEventType::FlightCancelled }
+                _ => { WebhookEventType::FlightStatusChanged }
+            },
+            payload: json!({
+                "flight_id": change.flight_id,
+                "previous_status": change.previous_status,
+                "status": times.status,
+                "estimated_departure": times.estimated_departure,
+                "estimated_arrival": times.estimated_arrival,
+                "actual_departure": times.actual_departure,
+                "actual_arrival": times.actual_arrival,
+                "reason": change.reason,
+            }),
+        }
+    }
+
+    pub fn ticket_rebooked(rebooking: &Rebooking) -> OutboxEvent {
+        OutboxEvent {
+            event_type: WebhookEventType::TicketRebooked,
+            payload: json!({
+                "ticket_no": rebooking.ticket_no,
+                "replaced_flight_ids": rebooking.removed,
+                "flight_ids": rebooking.added,
+                "fare_conditions": rebooking.fare_conditions,
+            }),
+        }
+    }
+}
+
+/// Endpoint receiving the events of `event_types`, or all of them if empty
+#[derive(Serialize, Clone, ToSchema)]
+pub struct Webhook {
+    pub endpoint_id: i32,
+    pub url: String,
+    pub event_types: Vec<WebhookEventType>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewWebhook {
+    pub url: String,
+    /// Key of the HMAC-SHA256 signatures, never returned
+    pub secret: String,
+    #[serde(default)]
+    pub event_types: Vec<WebhookEventType>,
+}
+
+impl Validate for NewWebhook {
+    async fn validate(&self, _: &AppState, errors: &mut ValidationErrors) -> RepositoryResult<()> {
+        match reqwest::Url::parse(&self.url) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+            _ => { errors.add("url", "Must be an absolute http or https URL") }
+        }
+
+        if self.secret.len() < MIN_SECRET_LENGTH {
+            errors.add("secret", format!("Must be at least {} characters long", MIN_SECRET_LENGTH));
+        }
+
+        Ok(())
+    }
+}
+
+/// Delivery claimed by the worker, with what it needs to send it
+pub struct PendingDelivery {
+    pub delivery_id: i64,
+    pub event_id: i64,
+    pub event_type: WebhookEventType,
+    pub payload: Value,
+    pub created_at: DateTime<Utc>,
+    pub url: String,
+    pub secret: String,
+    /// Attempts made before this one
+    pub attempts: i32,
+}
+
+pub enum DeliveryOutcome {
+    Delivered,
+    Retry { at: DateTime<Utc>, error: String },
+    Dead { error: String },
+}
+
+/// Delivery that ran out of attempts
+#[derive(Serialize, Clone, ToSchema)]
+pub struct DeadLetter {
+    pub delivery_id: i64,
+    pub endpoint_id: i32,
+    pub url: String,
+    pub event_id: i64,
+    pub event_type: WebhookEventType,
+    pub payload: Value,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `{timestamp}.{body}`, sent in `X-Webhook-Signature`.
+/// Receivers should recompute it and reject old timestamps to prevent replays.
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Delay before the next attempt, doubled after every failure up to the maximum
+fn backoff(cfg: &WebhooksConfig, attempts: i32) -> chrono::Duration {
+    let factor = 2_i64.saturating_pow(attempts.saturating_sub(1).max(0) as u32);
+
+    chrono::Duration::seconds(cfg.backoff_base_secs.saturating_mul(factor).min(cfg.max_backoff_secs))
+}
+
+async fn send(client: &reqwest::Client, delivery: &PendingDelivery) -> Result<(), String> {
+    let body = json!({
+        "event_id": delivery.event_id,
+        "event_type": delivery.event_type,
+        "created_at": delivery.created_at,
+        "data": delivery.payload,
+    })
+        .to_string();
+
+    let timestamp = Utc::now().timestamp();
+
+    let response = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("X-Webhook-Event", delivery.event_type.as_str())
+        .header("X-Webhook-Delivery", delivery.delivery_id.to_string())
+        .header("X-Webhook-Timestamp", timestamp.to_string())
+        .header("X-Webhook-Signature", signature(&delivery.secret, timestamp, body.as_bytes()))
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("Endpoint answered {}", response.status()))
+    }
+}
+
+pub fn client(cfg: &WebhooksConfig) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(cfg.timeout_secs))
+        .build()
+        .expect("Can't build the webhook HTTP client")
+}
+
+/// Sends one batch of due deliveries concurrently, returning how many were attempted.
+/// Claimed deliveries are hidden from other workers for twice the request timeout.
+pub async fn deliver_due(client: &reqwest::Client, state: &AppState) -> RepositoryResult<usize> {
+    let cfg = &state.cfg.webhooks;
+    let lease = chrono::Duration::seconds(2 * cfg.timeout_secs as i64);
+
+    let deliveries = state.repo.claim_deliveries(cfg.batch_size, lease).await?;
+
+    let results = futures::future::join_all(deliveries.iter().map(|d| send(client, d))).await;
+
+    for (delivery, result) in deliveries.iter().zip(results) {
+        let attempts = delivery.attempts + 1;
+
+        let outcome = match result {
+            Ok(()) => { DeliveryOutcome::Delivered }
+            Err(error) if attempts >= cfg.max_attempts => { DeliveryOutcome::Dead { error } }
+            Err(error) => { DeliveryOutcome::Retry { at: Utc::now() + backoff(cfg, attempts), error } }
+        };
+
+        let label = match &outcome {
+            DeliveryOutcome::Delivered => { "delivered" }
+            DeliveryOutcome::Retry { error, .. } => {
+                tracing::warn!(delivery_id = delivery.delivery_id, url = %delivery.url, attempts, error = %error, "Webhook delivery failed");
+                "retry"
+            }
+            DeliveryOutcome::Dead { error } => {
+                tracing::error!(delivery_id = delivery.delivery_id, url = %delivery.url, attempts, error = %error, "Webhook delivery moved to dead letters");
+                "dead"
+            }
+        };
+
+        state.metrics.webhook_deliveries.with_label_values(&[label]).inc();
+        state.repo.finish_delivery(delivery.delivery_id, &outcome).await?;
+    }
+
+    Ok(deliveries.len())
+}
+
+/// Delivers outbox events until the server stops, polling when idle
+pub async fn deliver(state: web::Data<AppState>) {
+    let client = client(&state.cfg.webhooks);
+    let poll_interval = Duration::from_millis(state.cfg.webhooks.poll_interval_ms);
+
+    tracing::info!("Delivering webhooks");
+
+    loop {
+        match deliver_due(&client, &state).await {
+            // A full batch suggests more deliveries are due
+            Ok(sent) if sent as i64 >= state.cfg.webhooks.batch_size => {}
+            Ok(_) => { sleep(poll_interval).await }
+            Err(e) => {
+                tracing::error!(error = %e, "Can't deliver webhooks");
+                sleep(poll_interval).await;
+            }
+        }
+    }
+}
+
+#[utoipa::path(
+    get, path = "/api/admin/webhooks", tag = "admin",
+    security(("bearer" = [])),
+    responses((status = 200, body = Vec<Webhook>))
+)]
+pub async fn list_webhooks(state: web::Data<AppState>) -> impl Responder {
+    match state.repo.webhooks().await {
+        Ok(webhooks) => {
+            HttpResponse::Ok().json(webhooks)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[utoipa::path(
+    post, path = "/api/admin/webhooks", tag = "admin",
+    security(("bearer" = [])),
+    request_body = NewWebhook,
+    responses(
+        (status = 201, body = Webhook),
+        (status = 400, description = "Invalid fields", body = ValidationErrors)
+    )
+)]
+pub async fn create_webhook(webhook: web::Json<NewWebhook>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = validate(&*webhook, &state).await {
+        return response;
+    }
+
+    match state.repo.create_webhook(&webhook).await {
+        Ok(created) => {
+            HttpResponse::Created().json(created)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[utoipa::path(
+    delete, path = "/api/admin/webhooks/{endpoint_id}", tag = "admin",
+    security(("bearer" = [])),
+    params(("endpoint_id" = i32, Path)),
+    responses(
+        (status = 204, description = "Deleted with its pending and dead deliveries"),
+        (status = 404, description = "Unknown webhook")
+    )
+)]
+pub async fn delete_webhook(path: web::Path<i32>, state: web::Data<AppState>) -> impl Responder {
+    match state.repo.delete_webhook(path.into_inner()).await {
+        Ok(false) => {
+            HttpResponse::NotFound().json("")
+        }
+        Ok(true) => {
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[utoipa::path(
+    get, path = "/api/admin/webhooks/dead_letters", tag = "admin",
+    security(("bearer" = [])),
+    responses((status = 200, description = "Deliveries that ran out of attempts, oldest first", body = Vec<DeadLetter>))
+)]
+pub async fn list_dead_letters(state: web::Data<AppState>) -> impl Responder {
+    match state.repo.dead_letters().await {
+        Ok(dead_letters) => {
+            HttpResponse::Ok().json(dead_letters)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[utoipa::path(
+    post, path = "/api/admin/webhooks/dead_letters/{delivery_id}/retry", tag = "admin",
+    security(("bearer" = [])),
+    params(("delivery_id" = i64, Path)),
+    responses(
+        (status = 202, description = "Queued again with a fresh set of attempts"),
+        (status = 404, description = "Unknown dead letter")
+    )
+)]
+pub async fn retry_dead_letter(path: web::Path<i64>, state: web::Data<AppState>) -> impl Responder {
+    match state.repo.retry_dead_letter(path.into_inner()).await {
+        Ok(false) => {
+            HttpResponse::NotFound().json("")
+        }
+        Ok(true) => {
+            HttpResponse::Accepted().finish()
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
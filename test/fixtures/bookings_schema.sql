@@ -0,0 +1,148 @@
+-- Subset of the bookings demo database schema (https://postgrespro.com/community/demodb)
+-- the service reads: reference data, flights, tickets and the views built on them.
+-- The clock is frozen like in the demo dump, so fixtures can use fixed dates.
+
+CREATE SCHEMA bookings;
+SET search_path = bookings, public;
+
+CREATE FUNCTION bookings.now() RETURNS timestamptz
+    AS $$SELECT '2017-08-15 18:00:00+03'::timestamptz;$$
+    LANGUAGE sql IMMUTABLE;
+
+CREATE FUNCTION bookings.lang() RETURNS text AS $$
+BEGIN
+  RETURN current_setting('bookings.lang');
+EXCEPTION
+  WHEN undefined_object THEN RETURN NULL;
+END;
+$$ LANGUAGE plpgsql STABLE;
+
+CREATE TABLE aircrafts_data (
+    aircraft_code char(3) PRIMARY KEY,
+    model jsonb NOT NULL,
+    range integer NOT NULL CHECK (range > 0)
+);
+CREATE VIEW aircrafts AS
+    SELECT ml.aircraft_code, ml.model ->> lang() AS model, ml.range FROM aircrafts_data ml;
+
+CREATE TABLE airports_data (
+    airport_code char(3) PRIMARY KEY,
+    airport_name jsonb NOT NULL,
+    city jsonb NOT NULL,
+    coordinates point NOT NULL,
+    timezone text NOT NULL
+);
+CREATE VIEW airports AS
+    SELECT ml.airport_code, ml.airport_name ->> lang() AS airport_name, ml.city ->> lang() AS city,
+           ml.coordinates, ml.timezone
+    FROM airports_data ml;
+
+CREATE TABLE bookings (
+    book_ref char(6) PRIMARY KEY,
+    book_date timestamptz NOT NULL,
+    total_amount numeric(10,2) NOT NULL
+);
+
+CREATE TABLE flights (
+    flight_id serial PRIMARY KEY,
+    flight_no char(6) NOT NULL,
+    scheduled_departure timestamptz NOT NULL,
+    scheduled_arrival timestamptz NOT NULL,
+    departure_airport char(3) NOT NULL REFERENCES airports_data(airport_code),
+    arrival_airport char(3) NOT NULL REFERENCES airports_data(airport_code),
+    status varchar(20) NOT NULL,
+    aircraft_code char(3) NOT NULL REFERENCES aircrafts_data(aircraft_code),
+    actual_departure timestamptz,
+    actual_arrival timestamptz,
+    UNIQUE (flight_no, scheduled_departure),
+    CHECK (scheduled_arrival > scheduled_departure),
+    CHECK (status IN ('On Time', 'Delayed', 'Departed', 'Arrived', 'Scheduled', 'Cancelled'))
+);
+
+CREATE TABLE seats (
+    aircraft_code char(3) NOT NULL REFERENCES aircrafts_data(aircraft_code) ON DELETE CASCADE,
+    seat_no varchar(4) NOT NULL,
+    fare_conditions varchar(10) NOT NULL CHECK (fare_conditions IN ('Economy', 'Comfort', 'Business')),
+    PRIMARY KEY (aircraft_code, seat_no)
+);
+
+CREATE TABLE tickets (
+    ticket_no char(13) PRIMARY KEY,
+    book_ref char(6) NOT NULL REFERENCES bookings(book_ref),
+    passenger_id varchar(20) NOT NULL,
+    passenger_name text NOT NULL,
+    contact_data jsonb
+);
+
+CREATE TABLE ticket_flights (
+    ticket_no char(13) NOT NULL REFERENCES tickets(ticket_no),
+    flight_id integer NOT NULL REFERENCES flights(flight_id),
+    fare_conditions varchar(10) NOT NULL CHECK (fare_conditions IN ('Economy', 'Comfort', 'Business')),
+    amount numeric(10,2) NOT NULL CHECK (amount >= 0),
+    PRIMARY KEY (ticket_no, flight_id)
+);
+
+CREATE TABLE boarding_passes (
+    ticket_no char(13) NOT NULL,
+    flight_id integer NOT NULL,
+    boarding_no integer NOT NULL,
+    seat_no varchar(4) NOT NULL,
+    PRIMARY KEY (ticket_no, flight_id),
+    UNIQUE (flight_id, boarding_no),
+    UNIQUE (flight_id, seat_no),
+    FOREIGN KEY (ticket_no, flight_id) REFERENCES ticket_flights(ticket_no, flight_id)
+);
+
+CREATE VIEW flights_v AS
+    SELECT f.flight_id,
+           f.flight_no,
+           f.scheduled_departure,
+           timezone(dep.timezone, f.scheduled_departure) AS scheduled_departure_local,
+           f.scheduled_arrival,
+           timezone(arr.timezone, f.scheduled_arrival) AS scheduled_arrival_local,
+           f.scheduled_arrival - f.scheduled_departure AS scheduled_duration,
+           f.departure_airport,
+           dep.airport_name AS departure_airport_name,
+           dep.city AS departure_city,
+           f.arrival_airport,
+           arr.airport_name AS arrival_airport_name,
+           arr.city AS arrival_city,
+           f.status,
+           f.aircraft_code,
+           f.actual_departure,
+           timezone(dep.timezone, f.actual_departure) AS actual_departure_local,
+           f.actual_arrival,
+           timezone(arr.timezone, f.actual_arrival) AS actual_arrival_local,
+           f.actual_arrival - f.actual_departure AS actual_duration
+    FROM flights f, airports dep, airports arr
+    WHERE f.departure_airport = dep.airport_code AND f.arrival_airport = arr.airport_code;
+
+CREATE VIEW routes AS
+    WITH f3 AS (
+        SELECT f2.flight_no, f2.departure_airport, f2.arrival_airport, f2.aircraft_code, f2.duration,
+               array_agg(f2.days_of_week) AS days_of_week
+        FROM (
+            SELECT f1.flight_no, f1.departure_airport, f1.arrival_airport, f1.aircraft_code, f1.duration, f1.days_of_week
+            FROM (
+                SELECT flights.flight_no, flights.departure_airport, flights.arrival_airport, flights.aircraft_code,
+                       flights.scheduled_arrival - flights.scheduled_departure AS duration,
+                       to_char(flights.scheduled_departure, 'ID')::integer AS days_of_week
+                FROM flights
+            ) f1
+            GROUP BY f1.flight_no, f1.departure_airport, f1.arrival_airport, f1.aircraft_code, f1.duration, f1.days_of_week
+            ORDER BY f1.flight_no, f1.departure_airport, f1.arrival_airport, f1.aircraft_code, f1.duration, f1.days_of_week
+        ) f2
+        GROUP BY f2.flight_no, f2.departure_airport, f2.arrival_airport, f2.aircraft_code, f2.duration
+    )
+    SELECT f3.flight_no,
+           f3.departure_airport,
+           dep.airport_name AS departure_airport_name,
+           dep.city AS departure_city,
+           f3.arrival_airport,
+           arr.airport_name AS arrival_airport_name,
+           arr.city AS arrival_city,
+           f3.aircraft_code,
+           f3.duration,
+           f3.days_of_week
+    FROM f3, airports dep, airports arr
+    WHERE f3.departure_airport = dep.airport_code AND f3.arrival_airport = arr.airport_code;
@@ -0,0 +1,34 @@
+-- Helpers used by the itinerary search, as in sql/scratchpad.sql. They read
+-- seats_comfort, so they are created after the migrations.
+SET search_path = bookings, public;
+
+CREATE FUNCTION aircraft_type(integer) RETURNS varchar
+AS '
+    SELECT aircraft_code FROM flights_v
+    WHERE flight_id = $1
+'
+    LANGUAGE SQL
+    IMMUTABLE
+    RETURNS NULL ON NULL INPUT;
+
+CREATE FUNCTION occupied_seats(integer, varchar) RETURNS integer
+AS '
+    SELECT count(ticket_no) AS occupied_seats
+    FROM ticket_flights
+    WHERE flight_id = $1
+      AND fare_conditions = $2
+'
+    LANGUAGE SQL
+    IMMUTABLE
+    RETURNS NULL ON NULL INPUT;
+
+CREATE FUNCTION free_seats(integer, varchar, varchar) RETURNS integer
+AS '
+    SELECT count(seat_no) - $1 as free_seats
+    FROM seats_comfort
+    WHERE seats_comfort.aircraft_code = $2
+      AND fare_conditions = $3
+'
+    LANGUAGE SQL
+    IMMUTABLE
+    RETURNS NULL ON NULL INPUT;
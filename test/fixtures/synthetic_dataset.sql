@@ -0,0 +1,131 @@
+-- Small deterministic network for integration tests: seven airports, two
+-- aircraft, eleven routes flown from August 1 to September 14, 2017, and
+-- tickets sold on every flight departing before August 17. Flights that
+-- departed before bookings.now() have arrived and all their passengers boarded.
+SET search_path = bookings, public;
+
+INSERT INTO airports_data (airport_code, airport_name, city, coordinates, timezone)
+VALUES ('SVO', '{"en": "Sheremetyevo International Airport", "ru": "Шереметьево"}', '{"en": "Moscow", "ru": "Москва"}', '(37.4146,55.9726)', 'Europe/Moscow'),
+       ('DME', '{"en": "Domodedovo International Airport", "ru": "Домодедово"}', '{"en": "Moscow", "ru": "Москва"}', '(37.9063,55.4088)', 'Europe/Moscow'),
+       ('VKO', '{"en": "Vnukovo International Airport", "ru": "Внуково"}', '{"en": "Moscow", "ru": "Москва"}', '(37.2615,55.5915)', 'Europe/Moscow'),
+       ('LED', '{"en": "Pulkovo Airport", "ru": "Пулково"}', '{"en": "St. Petersburg", "ru": "Санкт-Петербург"}', '(30.2625,59.8003)', 'Europe/Moscow'),
+       ('KZN', '{"en": "Kazan International Airport", "ru": "Казань"}', '{"en": "Kazan", "ru": "Казань"}', '(49.2787,55.6062)', 'Europe/Moscow'),
+       ('AER', '{"en": "Sochi International Airport", "ru": "Сочи"}', '{"en": "Sochi", "ru": "Сочи"}', '(39.9566,43.4499)', 'Europe/Moscow'),
+       ('SVX', '{"en": "Koltsovo Airport", "ru": "Кольцово"}', '{"en": "Yekaterinburg", "ru": "Екатеринбург"}', '(60.8027,56.7431)', 'Asia/Yekaterinburg');
+
+INSERT INTO aircrafts_data (aircraft_code, model, range)
+VALUES ('SU9', '{"en": "Sukhoi Superjet-100", "ru": "Сухой Суперджет-100"}', 3000),
+       ('733', '{"en": "Boeing 737-300", "ru": "Боинг 737-300"}', 4200);
+
+-- Superjet: 2 Business rows of 4 seats and 6 Economy rows, Boeing: 3 Business
+-- rows and 17 Economy rows of 6 seats
+INSERT INTO seats (aircraft_code, seat_no, fare_conditions)
+SELECT 'SU9', seat_row || letter, CASE WHEN seat_row <= 2 THEN 'Business' ELSE 'Economy' END
+FROM generate_series(1, 8) AS seat_row,
+     unnest(ARRAY['A', 'C', 'D', 'F']) AS letter;
+
+INSERT INTO seats (aircraft_code, seat_no, fare_conditions)
+SELECT '733', seat_row || letter, CASE WHEN seat_row <= 3 THEN 'Business' ELSE 'Economy' END
+FROM generate_series(1, 20) AS seat_row,
+     unnest(ARRAY['A', 'B', 'C', 'D', 'E', 'F']) AS letter;
+
+-- Departure times are local to the departure airport, Economy fares in rubles
+CREATE TEMPORARY TABLE schedule
+(
+    flight_no         CHAR(6),
+    departure_airport CHAR(3),
+    arrival_airport   CHAR(3),
+    departure_time    TIME,
+    duration          INTERVAL,
+    aircraft_code     CHAR(3),
+    days_of_week      INT[],
+    fare              INT
+);
+
+INSERT INTO schedule
+VALUES ('PG0001', 'SVO', 'LED', '09:00', '1:30', '733', '{1,2,3,4,5,6,7}', 6000),
+       ('PG0002', 'LED', 'SVO', '18:00', '1:30', '733', '{1,2,3,4,5,6,7}', 6000),
+       ('PG0003', 'LED', 'KZN', '13:00', '1:40', 'SU9', '{1,2,3,4,5,6,7}', 8000),
+       ('PG0004', 'KZN', 'LED', '07:00', '1:40', 'SU9', '{1,2,3,4,5,6,7}', 8000),
+       ('PG0005', 'DME', 'KZN', '10:00', '1:30', 'SU9', '{1,2,3,4,5,6,7}', 7000),
+       ('PG0006', 'KZN', 'DME', '15:00', '1:30', 'SU9', '{1,2,3,4,5,6,7}', 7000),
+       ('PG0007', 'VKO', 'AER', '08:00', '2:20', '733', '{1,3,5,7}', 9000),
+       ('PG0008', 'AER', 'VKO', '12:00', '2:20', '733', '{1,3,5,7}', 9000),
+       ('PG0009', 'KZN', 'SVX', '16:00', '1:50', 'SU9', '{2,4,6}', 10000),
+       ('PG0010', 'SVX', 'SVO', '08:00', '2:30', '733', '{1,2,3,4,5,6,7}', 12000),
+       ('PG0011', 'LED', 'AER', '11:00', '3:20', '733', '{3,6}', 11000);
+
+INSERT INTO flights (flight_no, scheduled_departure, scheduled_arrival, departure_airport, arrival_airport, status, aircraft_code)
+SELECT schedule.flight_no,
+       (day + schedule.departure_time) AT TIME ZONE dep.timezone,
+       (day + schedule.departure_time) AT TIME ZONE dep.timezone + schedule.duration,
+       schedule.departure_airport,
+       schedule.arrival_airport,
+       'Scheduled',
+       schedule.aircraft_code
+FROM schedule
+         JOIN airports_data dep ON dep.airport_code = schedule.departure_airport
+         CROSS JOIN generate_series('2017-08-01'::TIMESTAMP, '2017-09-14'::TIMESTAMP, '1 day') AS day
+WHERE extract(ISODOW FROM day) = ANY (schedule.days_of_week)
+ORDER BY day, schedule.departure_time, schedule.flight_no;
+
+UPDATE flights
+SET status           = 'Arrived',
+    actual_departure = scheduled_departure + INTERVAL '5 minutes',
+    actual_arrival   = scheduled_arrival + INTERVAL '5 minutes'
+WHERE scheduled_arrival < bookings.now();
+
+-- Excluded from searches
+UPDATE flights
+SET status = 'Cancelled'
+WHERE flight_no = 'PG0005' AND scheduled_departure = '2017-08-17 10:00+03';
+
+-- One Business and two to four Economy passengers per flight. The first Economy
+-- passenger of PG0001 paid the Comfort fare, so rebuilds derive a Comfort class.
+CREATE TEMPORARY TABLE sold AS
+SELECT flights.flight_id,
+       flights.scheduled_departure,
+       cabin.seat_no,
+       cabin.fare_conditions,
+       CASE
+           WHEN cabin.fare_conditions = 'Business' THEN schedule.fare * 3
+           WHEN flights.flight_no = 'PG0001' AND cabin.k = 1 THEN schedule.fare * 13 / 10
+           ELSE schedule.fare
+       END AS amount,
+       row_number() OVER (ORDER BY flights.flight_id, cabin.seat_no) AS n
+FROM flights
+         JOIN schedule ON schedule.flight_no = flights.flight_no
+         CROSS JOIN LATERAL (
+    SELECT seat_no, fare_conditions, row_number() OVER (PARTITION BY fare_conditions ORDER BY seat_no) AS k
+    FROM seats
+    WHERE seats.aircraft_code = flights.aircraft_code
+) AS cabin
+WHERE flights.scheduled_departure < '2017-08-17 00:00+03'
+  AND cabin.k <= CASE WHEN cabin.fare_conditions = 'Business' THEN 1 ELSE 2 + flights.flight_id % 3 END;
+
+INSERT INTO bookings (book_ref, book_date, total_amount)
+SELECT 'B' || lpad(n::TEXT, 5, '0'), scheduled_departure - INTERVAL '1 day' * (10 + n % 20), amount
+FROM sold;
+
+INSERT INTO tickets (ticket_no, book_ref, passenger_id, passenger_name)
+SELECT '00054' || lpad(n::TEXT, 8, '0'),
+       'B' || lpad(n::TEXT, 5, '0'),
+       lpad((n * 7919 % 10000)::TEXT, 4, '0') || ' ' || lpad((n * 104729 % 1000000)::TEXT, 6, '0'),
+       (ARRAY ['IVAN', 'PETR', 'ANNA', 'OLGA', 'SERGEY', 'ELENA', 'DMITRY', 'MARIA'])[1 + n % 8] || ' ' ||
+       (ARRAY ['IVANOV', 'PETROV', 'SMIRNOV', 'KUZNETSOV', 'POPOV', 'VOLKOV', 'SOKOLOV'])[1 + n % 7]
+FROM sold;
+
+INSERT INTO ticket_flights (ticket_no, flight_id, fare_conditions, amount)
+SELECT '00054' || lpad(n::TEXT, 8, '0'), flight_id, fare_conditions, amount
+FROM sold;
+
+INSERT INTO boarding_passes (ticket_no, flight_id, boarding_no, seat_no)
+SELECT '00054' || lpad(n::TEXT, 8, '0'),
+       flight_id,
+       row_number() OVER (PARTITION BY flight_id ORDER BY n),
+       seat_no
+FROM sold
+WHERE scheduled_departure < bookings.now();
+
+DROP TABLE sold;
+DROP TABLE schedule;